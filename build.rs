@@ -1,4 +1,9 @@
 fn main() {
+    // 在 PC 上跑 src/lib.rs 的单元测试时不需要 ESP 的链接脚本
+    if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() != Ok("riscv32") {
+        return;
+    }
+
     linker_be_nice();
     println!("cargo:rustc-link-arg=-Tdefmt.x");
     // make sure linkall.x is the last linker script (otherwise might cause problems with flip-link)
@@ -0,0 +1,132 @@
+//! ADC 模拟量输入
+//!
+//! 只约定“读一次得到毫伏值”，具体用哪个 ADC、怎么校准由固件决定
+//! (ESP32-C6 上用 esp-hal 的曲线校准直接得到毫伏)。
+
+/// 一个已经换算成毫伏的模拟输入通道
+pub trait AnalogInput {
+    type Error;
+
+    /// 读一次 ADC 引脚上的电压 (mV)
+    fn read_mv(&mut self) -> Result<u16, Self::Error>;
+}
+
+/// 连续读 `n` 次求平均，降低 ADC 噪声；3 次以上时去掉一个最大值和一个最小值
+///
+/// 读失败的次数不参与平均，全部失败时返回 `None`。
+pub fn oversample<A: AnalogInput>(input: &mut A, n: usize) -> Option<f32> {
+    let mut count = 0u32;
+    let mut sum = 0u32;
+    let mut min = u16::MAX;
+    let mut max = 0u16;
+    for _ in 0..n {
+        if let Ok(mv) = input.read_mv() {
+            count += 1;
+            sum += mv as u32;
+            min = min.min(mv);
+            max = max.max(mv);
+        }
+    }
+    match count {
+        0 => None,
+        1 | 2 => Some(sum as f32 / count as f32),
+        _ => Some((sum - min as u32 - max as u32) as f32 / (count - 2) as f32),
+    }
+}
+
+/// 电阻分压器：被测电压接 `top` 一端，ADC 接 `top` 与 `bottom` 的中点
+///
+/// 5 V 的模块输出或电池电压超过 ADC 量程时用它降压。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Divider {
+    pub top_ohms: f32,
+    pub bottom_ohms: f32,
+}
+
+impl Divider {
+    /// 不分压，ADC 直接接被测电压
+    pub const NONE: Divider = Divider {
+        top_ohms: 0.0,
+        bottom_ohms: 1.0,
+    };
+
+    /// 由 ADC 上的电压反推分压前的电压
+    pub fn input_mv(&self, adc_mv: f32) -> f32 {
+        adc_mv * (self.top_ohms + self.bottom_ohms) / self.bottom_ohms
+    }
+}
+
+/// 分段线性曲线，点按横坐标从小到大排列，点与点之间线性插值
+///
+/// 用于传感器的灵敏度曲线、电池的电压-电量曲线等查表换算。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Curve(pub &'static [(f32, f32)]);
+
+impl Curve {
+    /// 查曲线；超出曲线范围时取端点的值
+    pub fn lookup(&self, x: f32) -> f32 {
+        let points = self.0;
+        let (first, last) = (points[0], points[points.len() - 1]);
+        if x <= first.0 {
+            return first.1;
+        }
+        if x >= last.0 {
+            return last.1;
+        }
+        let i = points.iter().position(|&(px, _)| px >= x).unwrap();
+        let ((x0, y0), (x1, y1)) = (points[i - 1], points[i]);
+        y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按顺序返回给定的读数，None 表示这次读失败
+    struct Fake(Vec<Option<u16>>);
+
+    impl AnalogInput for Fake {
+        type Error = ();
+
+        fn read_mv(&mut self) -> Result<u16, ()> {
+            self.0.remove(0).ok_or(())
+        }
+    }
+
+    #[test]
+    fn oversample_trims_extremes() {
+        let mut adc = Fake(vec![
+            Some(1000),
+            Some(3000),
+            Some(1010),
+            Some(0),
+            Some(1020),
+        ]);
+        assert_eq!(oversample(&mut adc, 5), Some(1010.0));
+
+        let mut adc = Fake(vec![Some(1000), None, Some(1010)]);
+        assert_eq!(oversample(&mut adc, 3), Some(1005.0));
+        let mut adc = Fake(vec![None, None]);
+        assert_eq!(oversample(&mut adc, 2), None);
+    }
+
+    #[test]
+    fn divider_scales_back_to_input() {
+        let d = Divider {
+            top_ohms: 10_000.0,
+            bottom_ohms: 20_000.0,
+        };
+        assert_eq!(d.input_mv(2000.0), 3000.0);
+        assert_eq!(Divider::NONE.input_mv(1234.0), 1234.0);
+    }
+
+    #[test]
+    fn curve_interpolates_and_clamps() {
+        let curve = Curve(&[(1.0, 10.0), (2.0, 30.0), (4.0, 40.0)]);
+        assert_eq!(curve.lookup(1.5), 20.0);
+        assert_eq!(curve.lookup(3.0), 35.0);
+        assert_eq!(curve.lookup(0.0), 10.0);
+        assert_eq!(curve.lookup(9.0), 40.0);
+    }
+}
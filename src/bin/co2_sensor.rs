@@ -1,22 +1,34 @@
 #![no_std]
 #![no_main]
 
+use esp32c6_test::jw01::{FrameError, Parser};
+use esp_alloc as _;
 use esp_backtrace as _;
 use esp_hal::{
     clock::CpuClock,
     main,
+    time::{Duration, Instant},
     uart::{Config as UartConfig, DataBits, Parity, StopBits, Uart},
 };
-use esp_println::println;
+use esp_println::{print, println};
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
+// 改成 true 时不解析，按 testdata/jw01_frames.txt 的格式原样打印收到的字节，
+// 用来采集真实模块的语料 (做法见那个文件开头)
+const DUMP_RAW: bool = false;
+// 超过这么久没有收到字节就算一次停顿，换一行 (模块连续发一帧只要 6 ms 多)
+const DUMP_GAP_MS: u64 = 100;
+
 #[main]
 fn main() -> ! {
     // 跟你之前 temp_sensor.rs 一样的风格：用 esp_hal::Config 初始化
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
+    // 库 (esp32c6_test) 里的传感器抽象用到了 alloc，需要一块堆
+    esp_alloc::heap_allocator!(size: 8 * 1024);
+
     // 配置 UART：9600, 8N1，对应 CO2 模块协议
     let uart_cfg = UartConfig::default()
         .with_baudrate(9_600) // 9600 bps
@@ -35,76 +47,44 @@ fn main() -> ! {
 
     println!("CO2 传感器读取程序启动，正在等待数据流...");
 
-    let mut frame = [0u8; 6];
+    // 帧解析 (帧头、满量程字段、校验和、失败后重新对齐) 在库里实现
+    let mut parser = Parser::new();
     let mut buf1 = [0u8; 1];
+    let mut last_byte: Option<Instant> = None;
 
     loop {
-        // 1)先从串口里“捞”到一个 0x2C，当成帧头
-        loop {
-            if let Ok(n) = uart.read(&mut buf1) {
-                if n == 0 {
-                    // 当前没有数据，继续转一圈
-                    continue;
-                }
-
-                if buf1[0] == 0x2C {
-                    frame[0] = 0x2C;
-                    break;
-                }
-                // 否则丢掉，继续找下一个字节
-            }
-        }
-
-        // 2)已经拿到了 B1 = 0x2C，再读后面 5 个字节
-        for i in 1..6 {
-            loop {
-                if let Ok(n) = uart.read(&mut buf1) {
-                    if n == 0 {
-                        continue;
-                    } else {
-                        frame[i] = buf1[0];
-                        break;
-                    }
-                }
+        // 逐字节喂给解析器，凑满一帧才会有结果
+        let byte = match uart.read(&mut buf1) {
+            Ok(n) if n > 0 => buf1[0],
+            // 当前没有数据，继续转一圈
+            _ => continue,
+        };
+
+        if DUMP_RAW {
+            // 每段停顿前的字节一行，`=>` 后面的结果采集完再对照着填
+            if last_byte.is_some_and(|t| t.elapsed() > Duration::from_millis(DUMP_GAP_MS)) {
+                println!("=>");
             }
-        }
-
-        let b1 = frame[0];
-        let b2 = frame[1];
-        let b3 = frame[2];
-        let b4 = frame[3];
-        let b5 = frame[4];
-        let b6 = frame[5];
-
-        // 3)检查满量程字段是否是固定值 0x03, 0xFF
-        if b4 != 0x03 || b5 != 0xFF {
-            println!(
-                "CO2 帧满量程字段异常: b4=0x{:02X}, b5=0x{:02X}, frame={:02X?}",
-                b4, b5, frame
-            );
+            print!("{:02X} ", byte);
+            last_byte = Some(Instant::now());
             continue;
         }
 
-        // 4)Checksum 校验
-        let sum = b1
-            .wrapping_add(b2)
-            .wrapping_add(b3)
-            .wrapping_add(b4)
-            .wrapping_add(b5);
-
-        if sum != b6 {
-            println!(
-                "CO2 校验失败: 期望=0x{:02X}, 实际=0x{:02X}, frame={:02X?}",
-                sum, b6, frame
-            );
-            continue;
+        match parser.push(byte) {
+            Some(Ok(frame)) => println!("CO2 = {} ppm (帧: {:02X?})", frame.ppm, frame.raw),
+            Some(Err(FrameError::BadFullScale { high, low })) => {
+                println!("CO2 帧满量程字段异常: b4=0x{:02X}, b5=0x{:02X}", high, low)
+            }
+            Some(Err(FrameError::Checksum { expected, actual })) => {
+                println!(
+                    "CO2 校验失败: 期望=0x{:02X}, 实际=0x{:02X}",
+                    expected, actual
+                )
+            }
+            Some(Err(e)) => println!("CO2 帧错误: {:?}", e),
+            None => {}
         }
 
-        // 5)转换为 ppm
-        let co2_ppm: u16 = ((b2 as u16) << 8) | (b3 as u16);
-
-        println!("CO2 = {} ppm (帧: {:02X?})", co2_ppm, frame);
-
         // 模块本身一般 1~2 秒发一帧，这里就不另外 delay 了，
         // 想 10 秒打印一次的话，可以自己加一个计数器或者 Delay。
     }
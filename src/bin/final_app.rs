@@ -4,30 +4,68 @@
 
 extern crate alloc; // 开启动态内存支持，用于格式化字符串
 
-use alloc::format; // 引入 format! 宏
-use core::net::Ipv4Addr;
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec}; // 引入 format! 宏
+use core::{cell::RefCell, net::Ipv4Addr};
+use critical_section::Mutex;
+use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
 use embassy_executor::Spawner;
-use embassy_net::{tcp::TcpSocket, Runner, StackResources};
-use embassy_time::{Duration, Timer};
+use embassy_net::{dns::DnsQueryType, tcp::TcpSocket, IpAddress, Runner, Stack, StackResources};
+use embassy_sync::blocking_mutex::NoopMutex;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use embedded_io_async::Error as _;
+use embedded_storage::nor_flash::ReadNorFlash as _;
+use embedded_tls::{TlsConfig, TlsConnection, TlsContext};
 use esp_alloc as _;
 use esp_backtrace as _;
 #[cfg(target_arch = "riscv32")]
 use esp_hal::interrupt::software::SoftwareInterruptControl;
 // 引入 GPIO 和 Delay 相关的库
+#[cfg(feature = "rmt-onewire")]
+use esp32c6_test::onewire::rmt::RmtOneWire;
+#[cfg(not(feature = "rmt-onewire"))]
+use esp32c6_test::onewire::{open_drain, BitBang};
+use esp32c6_test::{
+    adc::{AnalogInput, Divider},
+    calibration::{self, Calibration, Command, CommandError},
+    discovery::{self, Inventory},
+    dns,
+    ds18b20::{self, Ds18b20Sensor, Resolution},
+    flash::{Region, SharedFlash},
+    health::{self, Saved, UploadStats},
+    http::{self, Response, Url},
+    jw01,
+    light::{self, LuxSensor, PhaseTracker},
+    mqtt::{self, MqttError, QoS},
+    ndir::{self, Ndir},
+    nh3::{self, Nh3Sensor},
+    onewire::OneWire,
+    outbox::{Outbox, Record},
+    plausibility::{Limits, Plausibility},
+    power::{self, BatterySensor, PowerMonitor},
+    sensor::{json_number, Collector, Measurement, Quality, Registry, Sensor, Unit},
+    sht::{self, Sht},
+    tls::{self, Trust},
+    window::OutlierFilter,
+};
+#[cfg(not(feature = "rmt-onewire"))]
+use esp_hal::delay::Delay;
 use esp_hal::{
+    analog::adc::{Adc, AdcCalCurve, AdcChannel, AdcConfig, AdcPin, Attenuation},
     clock::CpuClock,
-    delay::Delay,
-    gpio::{Flex, InputConfig, OutputConfig, Pull},
+    efuse::Efuse,
+    gpio::{Flex, Input, InputConfig, Pull},
+    i2c::master::{Config as I2cConfig, I2c},
+    peripherals::ADC1,
     ram,
     rng::Rng,
     timer::timg::TimerGroup,
-    uart::{Config as UartConfig, DataBits, Parity, StopBits, Uart},
+    uart::{Config as UartConfig, DataBits, Parity, StopBits, Uart, UartRx},
+    usb_serial_jtag::{UsbSerialJtag, UsbSerialJtagRx},
+    Async, Blocking,
 };
 use esp_println::println;
 use esp_radio::{
-    wifi::{
-        ClientConfig, ModeConfig, ScanConfig, WifiController, WifiDevice, WifiEvent, WifiStaState,
-    },
+    wifi::{ClientConfig, ModeConfig, WifiController, WifiDevice, WifiEvent, WifiStaState},
     Controller,
 };
 
@@ -38,99 +76,376 @@ macro_rules! mk_static {
     ($t:ty,$val:expr) => {{
         static STATIC_CELL: static_cell::StaticCell<$t> = static_cell::StaticCell::new();
         #[deny(unused_attributes)]
-        let x = STATIC_CELL.uninit().write(($val));
+        let x = STATIC_CELL.uninit().write($val);
         x
     }};
 }
 
 const SSID: &str = "XiongLab_p2_2.4G";
 const PASSWORD: &str = "Xiong123";
+// 服务器地址 (请确认主机名/IP 和端口是否正确)，写成主机名时开机后通过 DNS 解析，
+// 例如 "http://data.example.com:5005"；数据发到 <SERVER_URL>/upload
+// 写成 "https://..." 时走 TLS 1.3，服务器证书按 SERVER_TRUST 校验 (见 README)
+const SERVER_URL: &str = "http://159.75.201.91:5005";
+// HTTPS 服务器证书公钥的 SHA-256，计算方法：
+//   openssl x509 -in server.crt -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256
+// 也可以改成 Trust::Ca(CA 证书的 DER)，接受该 CA 直接签发给服务器主机名的证书
+const SERVER_TRUST: Trust<'static> = Trust::PublicKeySha256([0; 32]);
+// smoltcp 自己会重发 DNS 查询，10 秒没有回复就放弃；这里再加一道上限
+const DNS_TIMEOUT_MS: u64 = 15_000;
+// 从发出请求到读完服务器回复的最长时间
+const HTTP_TIMEOUT_MS: u32 = 10_000;
+
+// 上传方式：HTTP (SERVER_URL)、MQTT (MQTT_BROKER) 或两者都发
+const BACKEND: Backend = Backend::Http;
+// MQTT broker 的主机名或 IP、端口和协议版本
+const MQTT_BROKER: &str = "192.168.1.10";
+const MQTT_PORT: u16 = mqtt::DEFAULT_PORT;
+const MQTT_VERSION: mqtt::Version = mqtt::Version::V311;
+// 用户名和密码，broker 允许匿名连接时为 None
+const MQTT_CREDENTIALS: Option<(&str, &str)> = None;
+// 主题是 <MQTT_TOPIC_PREFIX>/<MAC>/...，client id 是 mmu-<MAC>
+const MQTT_TOPIC_PREFIX: &str = "lab/mmu";
+// 心跳间隔：采样间隔 (30 秒) 要明显短于它的一半
+const MQTT_KEEP_ALIVE_S: u16 = 120;
+const MQTT_TIMEOUT_MS: u32 = 10_000;
+
+#[allow(dead_code)] // 只用到 BACKEND 选中的那一种
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Http,
+    Mqtt,
+    Both,
+}
+
+impl Backend {
+    const fn http(self) -> bool {
+        matches!(self, Backend::Http | Backend::Both)
+    }
+
+    const fn mqtt(self) -> bool {
+        matches!(self, Backend::Mqtt | Backend::Both)
+    }
+
+    // 积压队列里每条记录要发往的通道
+    const fn channels(self) -> u8 {
+        (self.http() as u8 * VIA_HTTP) | (self.mqtt() as u8 * VIA_MQTT)
+    }
+}
+
+// 积压队列的通道位：哪边已经收到的记录不再重发给哪边
+const VIA_HTTP: u8 = 1;
+const VIA_MQTT: u8 = 2;
 
 // ==========================================
-//  移植过来的 1-Wire 驱动 (不用改动)
+//  1-Wire 总线 (驱动在库里：esp32c6_test::onewire)
 // ==========================================
-struct OneWire<'d> {
-    pin: Flex<'d>,
-    delay: Delay,
-}
-
-impl<'d> OneWire<'d> {
-    fn new(mut pin: Flex<'d>, delay: Delay) -> Self {
-        // 预设输出低，开启输入，初始状态关闭输出(释放/High)
-        pin.set_low();
-        pin.set_input_enable(true);
-        pin.set_output_enable(false);
-        // 如果有外部上拉电阻，这行其实可以去掉；没有则保留
-        // pin.set_pull(Pull::Up);
-        Self { pin, delay }
-    }
-
-    #[inline(always)]
-    fn drive_low(&mut self) {
-        self.pin.set_output_enable(true);
-    }
-
-    #[inline(always)]
-    fn release_high(&mut self) {
-        self.pin.set_output_enable(false);
-    }
-
-    // 复位脉冲
-    fn reset(&mut self) -> bool {
-        critical_section::with(|_| {
-            self.drive_low();
-            self.delay.delay_micros(480);
-            self.release_high();
-            self.delay.delay_micros(60); // 稍微调小了一点等待时间，适配 C6
-            let presence = self.pin.is_low();
-            self.delay.delay_micros(420);
-            presence
-        })
-    }
-
-    // 写 Bit
-    fn write_bit(&mut self, bit: bool) {
-        critical_section::with(|_| {
-            self.drive_low();
-            if bit {
-                self.delay.delay_micros(6);
-                self.release_high();
-                self.delay.delay_micros(64);
-            } else {
-                self.delay.delay_micros(60);
-                self.release_high();
-                self.delay.delay_micros(10);
-            }
-        });
+// 默认用 GPIO 位翻转 (bit-bang) 产生时隙；打开 cargo feature `rmt-onewire`
+// 则改用 RMT 外设，两种后端对上层提供相同的 OneWireBus 接口
+#[cfg(not(feature = "rmt-onewire"))]
+type Bus = BitBang<Flex<'static>, Delay>;
+#[cfg(feature = "rmt-onewire")]
+type Bus = RmtOneWire<'static>;
+
+// 最多同时挂载的 DS18B20 数量
+const MAX_SENSORS: usize = 8;
+
+// 温度分辨率：9 位 0.5°C / 10 位 0.25°C / 11 位 0.125°C / 12 位 0.0625°C
+// 分辨率越低转换越快 (94/188/375/750ms)，用电池供电时可以调低
+const RESOLUTION: Resolution = Resolution::Bits12;
+// 是否把分辨率写进 DS18B20 的 EEPROM (只在与当前值不同时写，避免磨损)
+const PERSIST_RESOLUTION: bool = false;
+// true：轮询读时隙，探头一报告完成就去读；false：按分辨率固定等待 tCONV
+const POLL_CONVERSION: bool = true;
+const CONVERSION_POLL_MS: u32 = 10;
+
+// ==========================================
+//  温湿度 (SHT4x/SHT3x，I2C0：GPIO6 = SDA，GPIO7 = SCL)
+// ==========================================
+const SHT_MODEL: sht::Model = sht::Model::Sht4x;
+// 连续 4 次 (约 2 分钟) 不低于 95 %RH 时认为探头结露，打开加热器约 1 秒
+const CONDENSATION_RH: f32 = 95.0;
+const CONDENSATION_SAMPLES: u8 = 4;
+
+// ==========================================
+//  光照 (BH1750/VEML7700，与温湿度传感器共用 I2C0，型号按扫描到的地址自动选择)
+// ==========================================
+// 高于 50 lx 为亮期，低于 10 lx 为暗期；连续 2 次 (约 1 分钟) 越过阈值才算开关灯
+const LIGHT_ON_LUX: f32 = 50.0;
+const LIGHT_OFF_LUX: f32 = 10.0;
+const LIGHT_CONFIRM_SAMPLES: u8 = 2;
+
+// ==========================================
+//  氨气 (MQ-137 模块 AOUT，经分压接 GPIO2 / ADC1)
+// ==========================================
+// 模块 5 V 供电，AOUT 经 10k/20k 分压后不超过 3.3 V
+const NH3_DIVIDER: Divider = Divider {
+    top_ohms: 10_000.0,
+    bottom_ohms: 20_000.0,
+};
+// 模块上的负载电阻 RL 和标定得到的 R0：在室外洁净空气中预热后读出 Rs，
+// R0 = Rs / nh3::MQ137_CLEAN_AIR_RATIO
+const NH3_CALIBRATION: nh3::Calibration = nh3::Calibration {
+    supply_mv: 5000.0,
+    load_ohms: 10_000.0,
+    r0_ohms: 20_000.0,
+    curve: nh3::MQ137,
+};
+// 加热丝上电后预热 3 分钟，期间 nh3 上传为 null，flags 里记为 warming_up
+const NH3_WARM_UP_SECS: u64 = 180;
+// 每次采样读 64 次 ADC 求平均
+const NH3_OVERSAMPLE: usize = 64;
+
+// ==========================================
+//  电池 / UPS (电池经 100k/100k 分压接 GPIO3 / ADC1)
+// ==========================================
+const BATTERY_DIVIDER: Divider = Divider {
+    top_ohms: 100_000.0,
+    bottom_ohms: 100_000.0,
+};
+const BATTERY_OVERSAMPLE: usize = 16;
+// 电量低于这个百分比时在日志里报警
+const LOW_BATTERY_SOC: f32 = 20.0;
+// UPS 模块有“外部供电正常”输出时接 GPIO11 并改为 true；高电平表示有外部供电
+const POWER_SENSE: bool = false;
+const POWER_SENSE_ACTIVE_HIGH: bool = true;
+
+type Adc1 = Adc<'static, ADC1<'static>, Blocking>;
+
+// ADC1 上的一个通道 (氨气和电池共用 ADC1)，读数经 esp-hal 的曲线校准换算成 mV
+struct AdcInput<PIN> {
+    adc: &'static NoopMutex<RefCell<Adc1>>,
+    pin: AdcPin<PIN, ADC1<'static>, AdcCalCurve<ADC1<'static>>>,
+}
+
+impl<PIN: AdcChannel> AnalogInput for AdcInput<PIN> {
+    type Error = ();
+
+    fn read_mv(&mut self) -> Result<u16, ()> {
+        self.adc
+            .lock(|adc| nb::block!(adc.borrow_mut().read_oneshot(&mut self.pin)))
     }
+}
 
-    // 读 Bit
-    fn read_bit(&mut self) -> bool {
-        critical_section::with(|_| {
-            self.drive_low();
-            self.delay.delay_micros(6);
-            self.release_high();
-            self.delay.delay_micros(9);
-            let bit = self.pin.is_high();
-            self.delay.delay_micros(55);
-            bit
-        })
+fn uptime_ms() -> u64 {
+    Instant::now().as_millis()
+}
+
+// ==========================================
+//  校准参数 (Flash 的 calib 分区，见 partitions.csv)
+// ==========================================
+const CALIBRATION_PARTITION: &str = "calib";
+// 控制台一行命令的最大长度
+const CONSOLE_LINE_MAX: usize = 96;
+// 服务器响应里下发的命令 (校准、清零 health 计数) 只在 HTTPS (证书按 SERVER_TRUST
+// 校验过) 时执行；明文 HTTP 谁都能伪造响应，确实需要时才改成 true
+const COMMANDS_OVER_PLAIN_HTTP: bool = false;
+
+// 当前的校准参数和保存它的分区，主循环和控制台任务共用
+struct CalibrationState {
+    current: Calibration,
+    flash: Option<Region<SharedFlash>>,
+}
+
+static CALIBRATION: Mutex<RefCell<CalibrationState>> = Mutex::new(RefCell::new(CalibrationState {
+    current: Calibration::NONE,
+    flash: None,
+}));
+
+fn calibration() -> Calibration {
+    critical_section::with(|cs| CALIBRATION.borrow_ref(cs).current)
+}
+
+// 执行一条校准命令 (来自串口控制台或服务器响应)，修改后立即写入 Flash；
+// 锁里只解析命令、更新参数，擦写 Flash 要几十毫秒，把分区取出来在锁外面做
+fn run_calibration_command(line: &str) {
+    let result = critical_section::with(|cs| {
+        let mut state = CALIBRATION.borrow_ref_mut(cs);
+        let command = calibration::parse_command(line, &state.current)?;
+        let flash = match command {
+            Command::Show => None,
+            Command::Set(cal) => {
+                state.current = cal;
+                state.flash.take()
+            }
+        };
+        Ok((command, state.current, flash))
+    });
+    match result {
+        Ok((Command::Show, current, _)) => println!("[CAL] {}", current.json_field()),
+        Ok((Command::Set(cal), _, Some(mut flash))) => {
+            match cal.save(&mut flash) {
+                Ok(()) => println!("[CAL] 已保存 {}", cal.json_field()),
+                Err(e) => println!("[CAL] 写 Flash 失败 {:?}，本次运行仍然生效", e),
+            }
+            critical_section::with(|cs| CALIBRATION.borrow_ref_mut(cs).flash = Some(flash));
+        }
+        Ok((Command::Set(_), _, None)) => println!("[CAL] 没有 calib 分区，只在本次运行生效"),
+        Err(CommandError::NotCalibration) => {
+            println!("[CAL] 未知命令: {} (用法: cal [reset] [temp_offset.<ROM>=..] [co2_offset=..] [co2_gain=..])", line)
+        }
+        Err(e) => println!("[CAL] 命令有误 {:?}: {}", e, line),
     }
+}
+
+// ==========================================
+//  采样窗口 (每个上传周期内连续采样，上传统计值)
+// ==========================================
+// 每 300 秒 (5分钟) 上传一次，期间每 30 秒采样一次
+const UPLOAD_INTERVAL_SECS: u64 = 300;
+const SAMPLE_INTERVAL_SECS: u64 = 30;
+// 窗口容量，比一个周期的采样次数多留一些余量
+const MAX_SAMPLES: usize = (UPLOAD_INTERVAL_SECS / SAMPLE_INTERVAL_SECS) as usize + 4;
+// 离群值剔除：偏离中位数超过 3.5 倍 (MAD 估计的) 标准差的样本不参与统计，
+// 改成 OutlierFilter::None 则保留全部样本
+const OUTLIER_FILTER: OutlierFilter = OutlierFilter::Mad { k: 3.5 };
+
+// ==========================================
+//  积压队列 (连不上服务器时先存着，恢复后从最早的一条开始补发)
+// ==========================================
+// 内存里最多积压 8 条 (40 分钟)，更早的转存到 Flash 的 outbox 分区 (248 KiB，几百条)，
+// 再多就丢掉最早的，丢掉的条数随 health 里的 outbox 计数上报
+const OUTBOX_RAM_RECORDS: usize = 8;
+const OUTBOX_PARTITION: &str = "outbox";
+// 每个周期最多补发这么多条，积压多时分几个周期发完，不耽误采样
+const MAX_REPLAY_PER_INTERVAL: usize = 12;
+
+// ==========================================
+//  运行状况计数 (累计值保存在 Flash 的 health 分区)
+// ==========================================
+const HEALTH_PARTITION: &str = "health";
+// 每 6 个上传周期 (30 分钟) 保存一次，两个扇区轮流擦写，每个扇区一天擦 24 次，
+// 10 万次寿命够用十年以上；掉电时最多丢掉最近 30 分钟的计数
+const HEALTH_SAVE_INTERVALS: u32 = 6;
+
+// ==========================================
+//  CO2 后台采集 (UART0：GPIO4 = RX，GPIO5 = TX)
+// ==========================================
+// JW01 约每秒主动发一帧，只接 RX；SenseAir S8 / MH-Z19 要由我们发请求，需要接 TX
+// 默认 JW01，用 cargo feature `co2-s8` / `co2-mhz19` 切换，也可以直接改 CO2_MODEL
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)] // 只用到 CO2_MODEL 选中的那一种
+enum Co2Model {
+    Jw01,
+    Ndir(ndir::Model),
+}
+
+#[cfg(all(feature = "co2-s8", feature = "co2-mhz19"))]
+compile_error!("co2-s8 和 co2-mhz19 只能选一个");
+#[cfg(feature = "co2-s8")]
+const CO2_MODEL: Co2Model = Co2Model::Ndir(ndir::Model::SenseAirS8);
+#[cfg(feature = "co2-mhz19")]
+const CO2_MODEL: Co2Model = Co2Model::Ndir(ndir::Model::Mhz19);
+#[cfg(not(any(feature = "co2-s8", feature = "co2-mhz19")))]
+const CO2_MODEL: Co2Model = Co2Model::Jw01;
+// 问答式传感器的读取间隔
+const CO2_POLL_MS: u64 = 2_000;
+// 上电时设置问答式传感器的 ABC：饲养室 CO2 长期高于室外，建议关闭 (Some(false))；
+// None 则保持传感器自己的设置
+const CO2_ABC: Option<bool> = Some(false);
+// 零点校准 (把当前环境当作 400 ppm)：只在设备放到新鲜空气中稳定 20 分钟后
+// 打开、烧录、重启一次，校准完务必改回 false
+const CO2_CALIBRATE_ZERO_ON_BOOT: bool = false;
+// 串口停顿超过这个时间，就丢弃收到一半的帧
+const CO2_FRAME_GAP_MS: u64 = 500;
+// 超过一个发送周期 (留一半余量) 一个字节都没收到，记一次 no_data
+const CO2_NO_DATA_MS: u64 = jw01::FRAME_PERIOD_MS * 3 / 2;
+// 最新读数超过这个时间没有更新就视为过期，不再上传
+const CO2_MAX_AGE_SECS: u64 = 10;
+// 开机后最多等这么久收到第一个有效读数，收不到就认为没接 CO2 传感器
+const CO2_DISCOVERY_SECS: u64 = 10;
+// 上电预热时间，期间的读数标记为 warming_up，不计入统计
+const CO2_WARM_UP_SECS: u64 = match CO2_MODEL {
+    Co2Model::Jw01 | Co2Model::Ndir(ndir::Model::Mhz19) => 180,
+    Co2Model::Ndir(ndir::Model::SenseAirS8) => 60,
+};
+
+// ==========================================
+//  合理性检查 (超出范围或变化太快的读数标记为 implausible，不计入统计)
+// ==========================================
+const CO2_LIMITS: Limits = Limits {
+    min: 300.0,
+    max: 5000.0,
+    // 约每 30 秒最多变化 1500 ppm
+    max_rate: 50.0,
+    // 连续 3 次都在新水平上，才当作真实的变化 (例如开门通风)
+    confirm: 3,
+};
+const TEMP_LIMITS: Limits = Limits {
+    min: -10.0,
+    max: 50.0,
+    // 约每 30 秒最多变化 3 °C
+    max_rate: 0.1,
+    confirm: 3,
+};
+
+// 后台任务写入、主循环读取的 CO2 状态
+struct Co2State {
+    // 最新的有效读数和收到它的时刻
+    latest: Option<(u16, Instant)>,
+    stats: jw01::Stats,
+    // 串口层面的错误 (FIFO 溢出、帧格式错误等)
+    uart_errors: u32,
+    // 问答式传感器的请求统计
+    ndir: ndir::Stats,
+}
+
+static CO2_STATE: Mutex<RefCell<Co2State>> = Mutex::new(RefCell::new(Co2State {
+    latest: None,
+    stats: jw01::Stats::new(),
+    uart_errors: 0,
+    ndir: ndir::Stats::new(),
+}));
 
-    fn write_byte(&mut self, byte: u8) {
-        for i in 0..8 {
-            self.write_bit((byte >> i) & 0x01 != 0);
+// 主循环拿到的一份 CO2 快照
+struct Co2Snapshot {
+    ppm: Option<u16>,
+    age: Option<Duration>,
+    stats: jw01::Stats,
+    uart_errors: u32,
+    ndir: ndir::Stats,
+}
+
+// 立即返回当前缓存的读数，不用等下一帧
+fn co2_snapshot() -> Co2Snapshot {
+    critical_section::with(|cs| {
+        let state = CO2_STATE.borrow_ref(cs);
+        let age = state.latest.map(|(_, at)| Instant::now() - at);
+        Co2Snapshot {
+            ppm: state.latest.map(|(ppm, _)| ppm),
+            age,
+            stats: state.stats,
+            uart_errors: state.uart_errors,
+            ndir: state.ndir,
+        }
+    })
+}
+
+// 把后台任务缓存的 CO2 读数包装成 Sensor，采样时立即返回
+struct Co2Sensor;
+
+impl Sensor for Co2Sensor {
+    fn name(&self) -> &'static str {
+        match CO2_MODEL {
+            Co2Model::Jw01 => "JW01-CO2",
+            Co2Model::Ndir(model) => model.name(),
         }
     }
 
-    fn read_byte(&mut self) -> u8 {
-        let mut byte = 0;
-        for i in 0..8 {
-            if self.read_bit() {
-                byte |= 1 << i;
+    async fn sample(&mut self) -> Vec<Measurement> {
+        let co2 = co2_snapshot();
+        let m = match (co2.ppm, co2.age) {
+            (Some(ppm), Some(age)) => {
+                let m = Measurement::new(jw01::METRIC, ppm as f32, Unit::Ppm);
+                if age <= Duration::from_secs(CO2_MAX_AGE_SECS) {
+                    m
+                } else {
+                    m.with_quality(Quality::Stale)
+                }
             }
-        }
-        byte
+            _ => Measurement::invalid(jw01::METRIC, Unit::Ppm),
+        };
+        vec![m]
     }
 }
 
@@ -158,251 +473,909 @@ async fn main(spawner: Spawner) -> ! {
         sw_int.software_interrupt0,
     );
 
+    // 读出保存在 Flash 里的校准参数
+    let flash = SharedFlash::new(peripherals.FLASH);
+    let mut calib_flash = flash.partition(CALIBRATION_PARTITION);
+    let saved = match calib_flash.as_mut().map(Calibration::load) {
+        Some(Ok(saved)) => saved,
+        Some(Err(e)) => {
+            println!("[WARN] 读取校准参数失败: {:?}", e);
+            None
+        }
+        None => {
+            println!("[WARN] 分区表里没有 calib 分区，校准参数无法保存");
+            None
+        }
+    };
+    critical_section::with(|cs| {
+        let mut state = CALIBRATION.borrow_ref_mut(cs);
+        state.current = saved.unwrap_or_default();
+        state.flash = calib_flash;
+    });
+    println!("Calibration: {}", calibration().json_field());
+
+    // 连不上服务器时积压的记录，内存放不下的转存到 outbox 分区 (上次运行留下的直接覆盖)
+    let mut outbox = Outbox::new(OUTBOX_RAM_RECORDS).with_channels(BACKEND.channels());
+    match flash.partition(OUTBOX_PARTITION) {
+        Some(flash) => {
+            println!("Outbox: {} KiB flash", flash.capacity() / 1024);
+            outbox = outbox.with_flash(flash);
+        }
+        None => println!("[WARN] 分区表里没有 outbox 分区，积压的记录只放在内存里"),
+    }
+
+    // 上次运行保存的运行状况计数，之后上传的是它加上本次上电以来的计数
+    let mut health_flash = flash.partition(HEALTH_PARTITION);
+    let mut saved_health = match health_flash.as_mut().map(Saved::load) {
+        Some(Ok(saved)) => saved.unwrap_or_default(),
+        Some(Err(e)) => {
+            println!("[WARN] 读取运行状况计数失败: {:?}", e);
+            Saved::new()
+        }
+        None => {
+            println!("[WARN] 分区表里没有 health 分区，运行状况计数重启后清零");
+            Saved::new()
+        }
+    };
+
     // 3. 初始化 1-Wire 传感器 (GPIO 10)
     // 注意：Delay 用于微秒级操作，不会阻塞 Wi-Fi 任务
-    let delay_driver = Delay::new();
     let one_wire_pin = Flex::new(peripherals.GPIO10);
-    let mut sensor = OneWire::new(one_wire_pin, delay_driver);
+    #[cfg(not(feature = "rmt-onewire"))]
+    let bus = BitBang::new(open_drain(one_wire_pin), Delay::new());
+    #[cfg(feature = "rmt-onewire")]
+    let bus = RmtOneWire::new(peripherals.RMT, one_wire_pin).unwrap();
+    let mut ds18b20: Ds18b20Sensor<Bus, embassy_time::Delay, MAX_SENSORS> =
+        Ds18b20Sensor::new(OneWire::new(bus), embassy_time::Delay, RESOLUTION)
+            .with_persist(PERSIST_RESOLUTION);
+    if POLL_CONVERSION {
+        ds18b20 = ds18b20.with_polling(CONVERSION_POLL_MS);
+    }
 
-    // 4. 初始化 CO2 传感器串口 (GPIO4 接 RX)
+    // 开机时枚举总线上的所有 DS18B20，一个都没有就不采样温度
+    println!("Found {} DS18B20 sensor(s) on GPIO10", ds18b20.discover());
+    for rom in ds18b20.roms() {
+        println!("  ROM: {:02X?}", rom);
+    }
+    if ds18b20.is_parasite() {
+        println!("Parasite-powered sensor detected, using strong pull-up");
+    }
+    let mut inventory = Inventory {
+        firmware: env!("CARGO_PKG_VERSION"),
+        mac: Efuse::mac_address(),
+        onewire: ds18b20.roms().to_vec(),
+        ..Default::default()
+    };
+    let ds18b20 = (!inventory.onewire.is_empty()).then_some(ds18b20);
+
+    // 4. 初始化 CO2 传感器串口 (GPIO4 接 RX，GPIO5 接 TX，三种传感器都是 9600 8N1)
     let uart_cfg = UartConfig::default()
         .with_baudrate(9_600)
         .with_data_bits(DataBits::_8)
         .with_parity(Parity::None)
         .with_stop_bits(StopBits::_1);
-    let mut co2_uart = Uart::new(peripherals.UART0, uart_cfg)
+    let co2_uart = Uart::new(peripherals.UART0, uart_cfg)
+        .unwrap()
+        .with_rx(peripherals.GPIO4)
+        .with_tx(peripherals.GPIO5)
+        .into_async();
+
+    // 5. 初始化 I2C 传感器 (I2C0，100 kHz)：温湿度和光照挂在同一条总线上
+    let i2c = I2c::new(peripherals.I2C0, I2cConfig::default())
         .unwrap()
-        .with_rx(peripherals.GPIO4);
+        .with_sda(peripherals.GPIO6)
+        .with_scl(peripherals.GPIO7);
+    let i2c_bus = &*mk_static!(
+        NoopMutex<RefCell<I2c<'static, Blocking>>>,
+        NoopMutex::new(RefCell::new(i2c))
+    );
+    // 扫描总线，只创建找到的传感器 (没接的不采样，对应指标不上传)
+    inventory.i2c = discovery::scan_i2c(&mut I2cDevice::new(i2c_bus));
+    for &address in &inventory.i2c {
+        println!(
+            "I2C device at 0x{:02X}: {}",
+            address,
+            discovery::i2c_device_name(address).unwrap_or("unknown")
+        );
+    }
+    let sht = inventory.has_i2c(sht::DEFAULT_ADDRESS).then(|| {
+        let mut sht = Sht::new(I2cDevice::new(i2c_bus), embassy_time::Delay, SHT_MODEL)
+            .with_condensation_recovery(CONDENSATION_RH, CONDENSATION_SAMPLES);
+        match sht.identify() {
+            Ok(id) => println!("{} found, id 0x{:08X}", sht.name(), id),
+            Err(e) => println!("[WARN] {} not responding: {:?}", sht.name(), e),
+        }
+        sht
+    });
+    let lux = discovery::find_lux(&inventory.i2c).map(|(model, address)| {
+        LuxSensor::new(I2cDevice::new(i2c_bus), embassy_time::Delay, model).with_address(address)
+    });
+
+    // 6. 初始化 ADC1 上的氨气 (GPIO2) 和电池 (GPIO3)，11 dB 衰减量程约 0~3.1 V
+    let mut adc_config = AdcConfig::new();
+    let nh3_pin = adc_config
+        .enable_pin_with_cal::<_, AdcCalCurve<ADC1>>(peripherals.GPIO2, Attenuation::_11dB);
+    let battery_pin = adc_config
+        .enable_pin_with_cal::<_, AdcCalCurve<ADC1>>(peripherals.GPIO3, Attenuation::_11dB);
+    let adc = &*mk_static!(
+        NoopMutex<RefCell<Adc1>>,
+        NoopMutex::new(RefCell::new(Adc::new(peripherals.ADC1, adc_config)))
+    );
+    let nh3_input = AdcInput { adc, pin: nh3_pin };
+    let nh3 = Nh3Sensor::new(nh3_input, NH3_CALIBRATION, uptime_ms)
+        .with_divider(NH3_DIVIDER)
+        .with_oversample(NH3_OVERSAMPLE)
+        .with_warm_up_ms(NH3_WARM_UP_SECS * 1000);
+    println!("NH3 sensor warming up for {} s", NH3_WARM_UP_SECS);
+    let battery = BatterySensor::new(
+        AdcInput {
+            adc,
+            pin: battery_pin,
+        },
+        BATTERY_DIVIDER,
+    )
+    .with_oversample(BATTERY_OVERSAMPLE);
+    // 模拟量传感器无法自动探测，按接线列出
+    inventory.analog = vec![nh3::METRIC, power::VOLTAGE_METRIC];
+    // 没接供电信号时不上传 power 字段
+    let mut power_monitor = POWER_SENSE.then(|| {
+        let pin = Input::new(
+            peripherals.GPIO11,
+            InputConfig::default().with_pull(Pull::None),
+        );
+        PowerMonitor::new(pin, POWER_SENSE_ACTIVE_HIGH)
+    });
 
-    // 5. 初始化 Wi-Fi
+    // 7. 初始化 Wi-Fi
     let esp_radio_ctrl = &*mk_static!(Controller<'static>, esp_radio::init().unwrap());
     let (controller, interfaces) =
-        esp_radio::wifi::new(&esp_radio_ctrl, peripherals.WIFI, Default::default()).unwrap();
+        esp_radio::wifi::new(esp_radio_ctrl, peripherals.WIFI, Default::default()).unwrap();
     let wifi_interface = interfaces.sta;
     let config = embassy_net::Config::dhcpv4(Default::default());
     let rng = Rng::new();
     let seed = (rng.random() as u64) << 32 | rng.random() as u64;
 
+    // 套接字：DHCP、HTTP 上传用的 TCP、DNS、MQTT 用的 TCP 各一个
     let (stack, runner) = embassy_net::new(
         wifi_interface,
         config,
-        mk_static!(StackResources<3>, StackResources::<3>::new()),
+        mk_static!(StackResources<4>, StackResources::<4>::new()),
         seed,
     );
 
     // 启动后台任务
     spawner.spawn(connection(controller)).ok();
     spawner.spawn(net_task(runner)).ok();
+    let (console_rx, _) = UsbSerialJtag::new(peripherals.USB_DEVICE)
+        .into_async()
+        .split();
+    spawner.spawn(console_task(console_rx)).ok();
+    match CO2_MODEL {
+        Co2Model::Jw01 => {
+            let (co2_rx, _) = co2_uart.split();
+            spawner.spawn(co2_task(co2_rx)).ok();
+        }
+        Co2Model::Ndir(model) => {
+            spawner.spawn(co2_poll_task(co2_uart, model)).ok();
+        }
+    }
+    let co2_deadline = Instant::now() + Duration::from_secs(CO2_DISCOVERY_SECS);
 
     let mut rx_buffer = [0; 4096];
     let mut tx_buffer = [0; 4096];
+    // HTTPS 的 TLS 记录缓冲区，每条连接重新借用
+    let mut tls_read_buffer = [0; tls::READ_BUFFER_LEN];
+    let mut tls_write_buffer = [0; tls::WRITE_BUFFER_LEN];
+    let mut mqtt_rx_buffer = [0; 1024];
+    let mut mqtt_tx_buffer = [0; 2048];
 
-    // 等待连接
-    println!("Waiting for WiFi link up...");
-    loop {
-        if stack.is_link_up() {
-            break;
+    // 不等 Wi-Fi 连上就开始采样：连接和 DHCP 在后台任务里进行，上电时没网也照样采样、
+    // 记录进积压队列，上传前再检查链路和 IP
+
+    // CO2 任务已经在收数据，到期还没有有效读数就认为 CO2 传感器没接
+    Timer::at(co2_deadline).await;
+    let co2_sensor = co2_snapshot().ppm.is_some().then_some(Co2Sensor);
+    match &co2_sensor {
+        Some(co2) => {
+            println!("{} found", co2.name());
+            inventory.co2 = Some(co2.name());
         }
-        Timer::after(Duration::from_millis(500)).await;
+        None => println!("[WARN] {} not responding, CO2 disabled", Co2Sensor.name()),
     }
+    // 设备清单只需要发一次，失败时下个周期上传前重试
+    let mut inventory_pending = Some(inventory.json());
+    let server = http::Url::parse(SERVER_URL).expect("SERVER_URL 格式不对");
+    let mut dns_cache = dns::Cache::new();
+    println!("Inventory: {}", inventory.json());
 
-    println!("Waiting to get IP address...");
-    loop {
-        if let Some(config) = stack.config_v4() {
-            println!("Got IP: {}", config.address);
-            break;
-        }
-        Timer::after(Duration::from_millis(500)).await;
+    // MQTT 的连接一直保持 (断了在上传前重连)，broker 发现设备掉线时发布遗嘱 "offline"
+    let mac: String = inventory.mac.iter().map(|b| format!("{:02X}", b)).collect();
+    let mqtt_base = format!("{}/{}", MQTT_TOPIC_PREFIX, mac);
+    let mqtt_status = format!("{}/status", mqtt_base);
+    let mqtt_client_id = format!("mmu-{}", mac);
+    let mut mqtt_options = mqtt::Options::new(&mqtt_client_id)
+        .with_version(MQTT_VERSION)
+        .with_keep_alive_s(MQTT_KEEP_ALIVE_S)
+        .with_will(mqtt::Will {
+            topic: &mqtt_status,
+            payload: b"offline",
+            qos: QoS::AtLeastOnce,
+            retain: true,
+        });
+    if let Some((username, password)) = MQTT_CREDENTIALS {
+        mqtt_options = mqtt_options.with_credentials(username, password);
     }
+    let mut mqtt_dns_cache = dns::Cache::new();
+    let mut mqtt_client = BACKEND.mqtt().then(|| {
+        let mut socket = TcpSocket::new(stack, &mut mqtt_rx_buffer, &mut mqtt_tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+        mqtt::Client::new(TcpConn(socket), embassy_time::Delay).with_timeout_ms(MQTT_TIMEOUT_MS)
+    });
 
     // ==========================================
-    //  主循环：读温度 -> 发请求
+    //  主循环：周期内连续采样 -> 汇总统计 -> 发请求
     // ==========================================
+    // 新增探头时只需要实现 Sensor 并加进这个元组
+    // 开机时没找到的传感器是 None，采样时跳过
+    let mut sensors = Registry::new((ds18b20, co2_sensor, sht, nh3, lux, battery));
+    let mut collector: Collector<MAX_SAMPLES> = Collector::new();
+    // 明暗周期跨上传周期持续跟踪，切换记录放进上传队列后清掉
+    let mut light_phase = PhaseTracker::new(LIGHT_ON_LUX, LIGHT_OFF_LUX, LIGHT_CONFIRM_SAMPLES);
+    // 上传统计跨周期 (和重启) 累计，和其他运行状况计数一起上传
+    let mut uploads = UploadStats::from_saved(&saved_health);
+    let mut intervals: u32 = 0;
+    let mut plausibility = Plausibility::new()
+        .with_warm_up(jw01::METRIC, CO2_WARM_UP_SECS * 1000)
+        .with_limits(jw01::METRIC, CO2_LIMITS)
+        .with_limits(ds18b20::METRIC, TEMP_LIMITS);
+    println!("CO2 sensor warming up for {} s", CO2_WARM_UP_SECS);
+
     loop {
-        println!("--- Starting new measurement loop ---");
+        println!("--- Starting new upload interval ---");
+        let interval_end = Instant::now() + Duration::from_secs(UPLOAD_INTERVAL_SECS);
+        collector.clear();
 
-        // 额外自检：确认 Wi-Fi 已连接且拿到 IP，避免在断线时忙尝试
-        if !stack.is_link_up() {
-            println!("[WARN] Wi-Fi link down，暂不发送。");
-            Timer::after(Duration::from_secs(5)).await;
-            continue;
+        // --- 步骤 A: 每 SAMPLE_INTERVAL_SECS 采样一次所有传感器，直到本周期结束 ---
+        // 等待温度转换用的是 Timer (异步等待)，这段时间里 Wi-Fi 还能处理后台数据
+        loop {
+            let next_sample = Instant::now() + Duration::from_secs(SAMPLE_INTERVAL_SECS);
+            let uptime_s = Instant::now().as_secs();
+            let cal = calibration();
+            for m in sensors.sample().await {
+                let m = plausibility.check(cal.apply(m), uptime_ms());
+                println!("{}", m);
+                collector.record(&m);
+                if m.metric == light::METRIC && m.quality == Quality::Good {
+                    if let Some(t) = light_phase.update(uptime_s, m.value) {
+                        println!(
+                            "[INFO] 光照切换: {} (uptime {} s)",
+                            t.to.as_str(),
+                            t.uptime_s
+                        );
+                    }
+                }
+                if m.metric == power::SOC_METRIC
+                    && m.quality == Quality::Good
+                    && m.value < LOW_BATTERY_SOC
+                {
+                    println!("[WARN] 电池电量低: {:.0}%", m.value);
+                }
+            }
+
+            // 停电时不等周期结束，马上上传一次，让服务器尽早知道
+            let power_lost = match power_monitor.as_mut().and_then(|p| p.poll(uptime_s)) {
+                Some(e) if e.external => {
+                    println!("[INFO] 外部供电恢复 (uptime {} s)", e.uptime_s);
+                    false
+                }
+                Some(e) => {
+                    println!("[WARN] 外部供电断开，改用电池 (uptime {} s)", e.uptime_s);
+                    true
+                }
+                None => false,
+            };
+            if power_lost {
+                break;
+            }
+
+            // 空闲时给 broker 发心跳，免得被当成掉线
+            if let Some(client) = mqtt_client.as_mut().filter(|c| c.is_connected()) {
+                if let Err(e) = client.keep_alive(uptime_ms()).await {
+                    println!("[WARN] MQTT 心跳失败: {:?}", e);
+                }
+            }
+
+            if next_sample >= interval_end {
+                Timer::at(interval_end).await;
+                break;
+            }
+            Timer::at(next_sample).await;
         }
-        if let Some(cfg) = stack.config_v4() {
-            println!("[INFO] 当前 IP: {}", cfg.address);
-        } else {
-            println!("[WARN] 尚未获得 DHCP 地址，稍后重试。");
-            Timer::after(Duration::from_secs(5)).await;
-            continue;
+
+        let co2 = co2_snapshot();
+        match CO2_MODEL {
+            Co2Model::Jw01 => println!(
+                "[INFO] CO2 帧统计: 有效 {}, 满量程错误 {}, 校验错误 {}, 截断 {}, 无数据 {}, 串口错误 {}",
+                co2.stats.frames,
+                co2.stats.bad_full_scale,
+                co2.stats.checksum_errors,
+                co2.stats.truncated,
+                co2.stats.no_data,
+                co2.uart_errors
+            ),
+            Co2Model::Ndir(_) => println!(
+                "[INFO] CO2 请求统计: 成功 {}, 超时 {}, 校验错误 {}, 其他错误 {}",
+                co2.ndir.readings,
+                co2.ndir.timeouts,
+                co2.ndir.checksum_errors,
+                co2.ndir.other_errors
+            ),
         }
 
-        // --- 步骤 A: 读取温度 ---
-        let mut temperature = 0.0;
-        let mut success = false;
+        let onewire = sensors
+            .sensors()
+            .0
+            .as_ref()
+            .map(|d| d.stats())
+            .unwrap_or_default();
+        println!(
+            "[INFO] DS18B20 统计: 成功 {}, 无响应 {}, 总线拉低 {}, CRC 错误 {}, 上电值 {}, 驱动错误 {}",
+            onewire.readings,
+            onewire.no_device,
+            onewire.bus_stuck_low,
+            onewire.crc_errors,
+            onewire.power_on_values,
+            onewire.bus_faults
+        );
 
-        // 1. 复位 & 发起转换
-        if sensor.reset() {
-            sensor.write_byte(0xCC); // Skip ROM
-            sensor.write_byte(0x44); // Convert T
+        // --- 步骤 B: 汇总本周期的样本 ---
+        for series in collector.series() {
+            if let Some(summary) = series.window.summary(OUTLIER_FILTER) {
+                println!(
+                    "{} {:02X?}: n={} rejected={} min={:.2} max={:.2} mean={:.2} median={:.2} {}",
+                    series.metric,
+                    series.channel,
+                    summary.count,
+                    summary.rejected,
+                    summary.min,
+                    summary.max,
+                    summary.mean,
+                    summary.median,
+                    series.unit.symbol()
+                );
+            }
+        }
 
-            // 2. [关键] 异步等待转换完成
-            // 这里我们不使用 sensor.delay (它是死等)，而是用 Timer::after (异步等待)
-            // 这样在等待的 800ms 里，Wi-Fi 还能处理后台数据
-            Timer::after(Duration::from_millis(800)).await;
+        // 1. 动态构建 JSON 内容
+        // 各指标是本周期的平均值 (temps 按 ROM 码展开)，stats 里是完整统计；
+        // temp 保留第一个探头的读数以兼容旧接口，没有温度读数时为 null；
+        // light_phase、power 里事件的时刻是上电以来的秒数，和 uptime_s 对照换算成时间
+        let temp = match collector.first(ds18b20::METRIC, OUTLIER_FILTER) {
+            Some(first) => json_number(first.mean, 2),
+            None => "null".into(),
+        };
+        let co2_counters: Vec<(&str, u32)> = match CO2_MODEL {
+            Co2Model::Jw01 => {
+                let mut counters = co2.stats.counters().to_vec();
+                counters.push(("uart_errors", co2.uart_errors));
+                counters
+            }
+            Co2Model::Ndir(_) => co2.ndir.counters().to_vec(),
+        };
+        let mut events = light_phase.json_field();
+        if let Some(power) = &power_monitor {
+            events.push_str(", ");
+            events.push_str(&power.json_field());
+        }
+        events.push_str(", ");
+        events.push_str(&calibration().json_field());
+        // health 里的计数跨重启累计，服务器回复 "reset_health": true 时清零
+        let onewire_total = saved_health.total("onewire", &onewire.counters());
+        let co2_total = saved_health.total("co2", &co2_counters);
+        let outbox_counters = outbox.counters();
+        // 前两项是当时排队的条数，不累计
+        let (queued, lost) = outbox_counters.split_at(2);
+        let outbox_total = [queued, &saved_health.total("outbox", lost)].concat();
+        let health_sections: [(&str, &[(&str, u32)]); 4] = [
+            ("onewire", &onewire_total),
+            ("co2", &co2_total),
+            ("upload", &uploads.counters()),
+            ("outbox", &outbox_total),
+        ];
+        events.push_str(", ");
+        events.push_str(&health::json_field(&health_sections));
+        intervals = intervals.wrapping_add(1);
+        if intervals.is_multiple_of(HEALTH_SAVE_INTERVALS) {
+            save_health(&mut health_flash, &health_sections);
+        }
+        let json_body = format!(
+            "{{\"temp\":{}, \"uptime_s\":{}, {}, {}}}",
+            temp,
+            Instant::now().as_secs(),
+            collector.json_fields(OUTLIER_FILTER),
+            events
+        );
 
-            // 3. 读取数据
-            sensor.reset();
-            sensor.write_byte(0xCC);
-            sensor.write_byte(0xBE);
+        // 2. 本周期的记录先进队列 (连不上服务器时留着下次补发)，里面的事件就可以清掉了
+        outbox.push(Record::new(uptime_ms(), json_body));
+        light_phase.clear();
+        if let Some(power) = &mut power_monitor {
+            power.clear();
+        }
 
-            let lsb = sensor.read_byte();
-            let msb = sensor.read_byte();
-            let raw_temp = ((msb as u16) << 8) | (lsb as u16);
-            temperature = raw_temp as f32 / 16.0;
-            success = true;
-            println!("Read Temp: {:.2} C", temperature);
+        // 确认 Wi-Fi 已连接且拿到 IP，断线时记录留在队列里
+        if !stack.is_link_up() {
+            println!(
+                "[WARN] Wi-Fi link down，暂不发送 (积压 {} 条)。",
+                outbox.len()
+            );
+            uploads.record(false);
+            continue;
+        }
+        if let Some(cfg) = stack.config_v4() {
+            println!("[INFO] 当前 IP: {}", cfg.address);
         } else {
-            println!("Sensor not found!");
+            println!(
+                "[WARN] 尚未获得 DHCP 地址，暂不发送 (积压 {} 条)。",
+                outbox.len()
+            );
+            uploads.record(false);
+            continue;
         }
 
-        // --- 步骤 B: 读取 CO2，带简单超时避免卡死 ---
-        let mut co2_ppm: Option<u16> = None;
-        let mut frame = [0u8; 6];
-        let mut buf1 = [0u8; 1];
+        // --- 步骤 C: 从最早的一条开始补发 (HTTP 和/或 MQTT)，两边都收到才出队，失败就等下个周期 ---
+        let mut endpoint = None;
+        if BACKEND.http() {
+            match resolve(stack, &server.host, &mut dns_cache).await {
+                Some(server_ip) => endpoint = Some((server_ip, server.port)),
+                None => println!("[WARN] 无法解析服务器地址 {}，暂不发送。", server.host),
+            }
+        }
+        if let Some(client) = mqtt_client.as_mut().filter(|c| !c.is_connected()) {
+            let inventory_json = inventory.json();
+            mqtt_connect(
+                client,
+                stack,
+                &mut mqtt_dns_cache,
+                &mqtt_options,
+                &mqtt_base,
+                &inventory_json,
+            )
+            .await
+            .ok();
+        }
 
-        let mut waited_ms = 0;
-        while waited_ms < 2000 {
-            if let Ok(n) = co2_uart.read(&mut buf1) {
-                if n > 0 && buf1[0] == 0x2C {
-                    frame[0] = 0x2C;
+        let upload_path = server.path("/upload");
+        let mut ok = (!BACKEND.http() || endpoint.is_some())
+            && mqtt_client.as_ref().is_none_or(|c| c.is_connected());
+        let mut reset_health = false;
+        let mut sent = 0;
+        // 清单和数据共用一条连接，服务器不要求关闭就复用
+        let mut conn = TcpConn(TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer));
+        // 连接建立不了或对方长时间不回 ACK 时由 smoltcp 复位
+        conn.0.set_timeout(Some(Duration::from_secs(10)));
+        let mut inventory_tried = false;
+        // 每次循环用一条连接，服务器关掉连接后重新连接 (HTTPS 重新握手) 接着发
+        while ok && sent < MAX_REPLAY_PER_INTERVAL && !outbox.is_empty() {
+            let mut http_client = None;
+            if let Some(endpoint) = endpoint {
+                let Some(transport) = connect(
+                    &mut conn,
+                    endpoint,
+                    &server,
+                    &mut tls_read_buffer,
+                    &mut tls_write_buffer,
+                )
+                .await
+                else {
+                    ok = false;
                     break;
+                };
+                let mut client =
+                    http::Client::new(transport, embassy_time::Delay, &server.authority())
+                        .with_timeout_ms(HTTP_TIMEOUT_MS);
+                if let Some(json) = inventory_pending.as_ref().filter(|_| !inventory_tried) {
+                    inventory_tried = true;
+                    let path = server.path("/inventory");
+                    if send_json(&mut client, &path, json).await.is_ok() {
+                        inventory_pending = None;
+                    }
                 }
+                http_client = Some(client);
             }
-            Timer::after(Duration::from_millis(20)).await;
-            waited_ms += 20;
-        }
-
-        if frame[0] == 0x2C {
-            let mut frame_ok = true;
-            for i in 1..6 {
-                let mut byte_wait = 0;
-                loop {
-                    if let Ok(n) = co2_uart.read(&mut buf1) {
-                        if n > 0 {
-                            frame[i] = buf1[0];
-                            break;
+
+            while ok && sent < MAX_REPLAY_PER_INTERVAL {
+                if http_client.as_ref().is_some_and(|c| !c.is_open()) {
+                    break;
+                }
+                let Some(record) = outbox.oldest() else {
+                    break;
+                };
+                // 补发的记录带上 age_s，服务器据此还原采样时间
+                let json = record.json(uptime_ms());
+                let pending = outbox.pending();
+                if let Some(client) = http_client.as_mut().filter(|_| pending & VIA_HTTP != 0) {
+                    let result = send_json(client, &upload_path, &json).await;
+                    if let Some(resp) = result.as_ref().ok().and_then(|r| r.body_str()) {
+                        if server.tls || COMMANDS_OVER_PLAIN_HTTP {
+                            reset_health |= health::reset_requested(resp);
+                            if let Some(command) = calibration::command_in_response(resp) {
+                                run_calibration_command(command);
+                            }
+                        } else if health::reset_requested(resp)
+                            || calibration::command_in_response(resp).is_some()
+                        {
+                            println!("[WARN] 忽略明文 HTTP 响应里的命令");
                         }
                     }
-                    Timer::after(Duration::from_millis(20)).await;
-                    byte_wait += 20;
-                    if byte_wait >= 500 {
-                        frame_ok = false;
-                        println!("[WARN] CO2 读取第 {} 字节超时，放弃本轮。", i + 1);
-                        break;
+                    ok &= result.is_ok();
+                    if ok {
+                        outbox.delivered(VIA_HTTP);
                     }
                 }
-                if !frame_ok {
-                    break;
+                if let Some(client) = mqtt_client
+                    .as_mut()
+                    .filter(|_| ok && pending & VIA_MQTT != 0)
+                {
+                    let result = mqtt_publish(client, &mqtt_base, &json).await;
+                    if let Err(e) = &result {
+                        println!("[WARN] MQTT 发布失败: {:?}", e);
+                    }
+                    ok &= result.is_ok();
+                    if ok {
+                        outbox.delivered(VIA_MQTT);
+                    }
+                }
+                if ok {
+                    sent += 1;
                 }
             }
 
-            if frame_ok {
-                let b1 = frame[0];
-                let b2 = frame[1];
-                let b3 = frame[2];
-                let b4 = frame[3];
-                let b5 = frame[4];
-                let b6 = frame[5];
-
-                if b4 != 0x03 || b5 != 0xFF {
-                    println!(
-                        "[WARN] CO2 满量程字段异常: b4=0x{:02X}, b5=0x{:02X}, frame={:02X?}",
-                        b4, b5, frame
-                    );
-                } else {
-                    let sum = b1
-                        .wrapping_add(b2)
-                        .wrapping_add(b3)
-                        .wrapping_add(b4)
-                        .wrapping_add(b5);
+            let reconnect = http_client.as_ref().is_some_and(|c| !c.is_open());
+            if let Some(client) = http_client {
+                close(client.into_inner()).await;
+            }
+            if !reconnect {
+                break;
+            }
+        }
+        shutdown(&mut conn.0).await;
+        // 积压的都发完了，再更新 retain 的最新值
+        if let Some(client) = mqtt_client.as_mut().filter(|_| ok && outbox.is_empty()) {
+            if let Err(e) = mqtt_publish_values(client, &mqtt_base, &collector).await {
+                println!("[WARN] MQTT 发布失败: {:?}", e);
+            }
+        }
+        if !outbox.is_empty() {
+            println!(
+                "[WARN] 还有 {} 条记录没发出去，下个周期继续。",
+                outbox.len()
+            );
+        }
+        uploads.record(ok);
 
-                    if sum != b6 {
-                        println!(
-                            "[WARN] CO2 校验失败: 期望=0x{:02X}, 实际=0x{:02X}, frame={:02X?}",
-                            sum, b6, frame
-                        );
-                    } else {
-                        let value = ((b2 as u16) << 8) | (b3 as u16);
-                        co2_ppm = Some(value);
-                        println!("CO2 = {} ppm (帧: {:02X?})", value, frame);
-                    }
-                }
+        if reset_health {
+            println!("[INFO] 服务器要求清零运行状况计数");
+            if let Some(ds18b20) = &mut sensors.sensors_mut().0 {
+                ds18b20.reset_stats();
             }
-        } else {
-            println!("[WARN] 未在 2 秒内捕获到 CO2 帧头，跳过本轮。");
-        }
-
-        // --- 步骤 C: 发送 HTTP 请求 ---
-        if success {
-            let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
-            socket.set_timeout(Some(embassy_time::Duration::from_secs(10)));
-
-            // 你的服务器地址 (请确认 IP 和端口是否正确)
-            let remote_endpoint = (Ipv4Addr::new(159, 75, 201, 91), 5005);
-
-            println!("Connecting to server...");
-            match socket.connect(remote_endpoint).await {
-                Ok(_) => {
-                    println!("Connected!");
-
-                    let co2_field = if let Some(v) = co2_ppm {
-                        format!("{}", v)
-                    } else {
-                        "null".into()
-                    };
-
-                    // 1. 动态构建 JSON 内容
-                    let json_body =
-                        format!("{{\"temp\":{:.2}, \"co2\":{}}}", temperature, co2_field);
-
-                    // 2. 动态构建 HTTP 请求头
-                    // 注意：必须计算正确的 Content-Length，否则服务器可能不认
-                    let request = format!(
-                        "POST /upload HTTP/1.1\r\n\
-                        Host: 159.75.201.91\r\n\
-                        Content-Type: application/json\r\n\
-                        Content-Length: {}\r\n\
-                        \r\n\
-                        {}",
-                        json_body.len(),
-                        json_body
-                    );
-
-                    // 3. 发送
-                    if let Err(e) = socket.write(request.as_bytes()).await {
-                        println!("Write error: {:?}", e);
-                    } else {
-                        println!("Data sent: {}", json_body);
-                    }
+            critical_section::with(|cs| {
+                let mut state = CO2_STATE.borrow_ref_mut(cs);
+                state.stats = jw01::Stats::new();
+                state.uart_errors = 0;
+                state.ndir = ndir::Stats::new();
+            });
+            uploads = UploadStats::new();
+            outbox.reset_stats();
+            saved_health = Saved::new();
+            save_health(&mut health_flash, &[]);
+        }
+    }
+}
 
-                    // 4. 读取响应 (可选，读一下确认服务器收到了)
-                    let mut buf = [0; 1024];
-                    match socket.read(&mut buf).await {
-                        Ok(n) if n > 0 => {
-                            if let Ok(resp) = core::str::from_utf8(&buf[..n]) {
-                                println!("Server response: {}", resp);
-                            }
-                        }
-                        _ => println!("No response or read error"),
-                    }
-                }
-                Err(e) => println!("Connect error: {:?}", e),
+// 把运行状况的累计值写进 health 分区
+fn save_health(flash: &mut Option<Region<SharedFlash>>, sections: &[(&str, &[(&str, u32)])]) {
+    let Some(flash) = flash else { return };
+    let result = match Saved::to_bytes(sections) {
+        Some(record) => Saved::save(flash, &record),
+        None => {
+            println!("[WARN] 运行状况计数太多，Flash 记录放不下");
+            return;
+        }
+    };
+    if let Err(e) = result {
+        println!("[WARN] 保存运行状况计数失败: {:?}", e);
+    }
+}
+
+// embassy-net 的 TcpSocket 实现的是 embedded-io-async 0.6，库里的 HTTP 客户端用 0.7，包一层
+struct TcpConn<'a>(TcpSocket<'a>);
+
+impl embedded_io_async::ErrorType for TcpConn<'_> {
+    type Error = embedded_io_async::ErrorKind;
+}
+
+impl embedded_io_async::Read for TcpConn<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0
+            .read(buf)
+            .await
+            .map_err(|_| embedded_io_async::ErrorKind::ConnectionReset)
+    }
+}
+
+impl embedded_io_async::Write for TcpConn<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0
+            .write(buf)
+            .await
+            .map_err(|_| embedded_io_async::ErrorKind::ConnectionReset)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0
+            .flush()
+            .await
+            .map_err(|_| embedded_io_async::ErrorKind::ConnectionReset)
+    }
+}
+
+// 上传用的连接：明文 TCP，或者 SERVER_URL 是 https:// 时在 TCP 上跑 TLS；
+// 都借用同一个 socket，每次 (重新) 连接时新建 (TLS 的状态里有两套 AES 密钥，放在堆上)
+enum Transport<'c, 'a> {
+    Plain(&'c mut TcpConn<'a>),
+    Tls(Box<TlsConnection<'c, &'c mut TcpConn<'a>, tls::CipherSuite>>),
+}
+
+impl embedded_io_async::ErrorType for Transport<'_, '_> {
+    type Error = embedded_io_async::ErrorKind;
+}
+
+impl embedded_io_async::Read for Transport<'_, '_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match self {
+            Transport::Plain(conn) => conn.read(buf).await,
+            Transport::Tls(tls) => tls.read(buf).await.map_err(|e| e.kind()),
+        }
+    }
+}
+
+impl embedded_io_async::Write for Transport<'_, '_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        match self {
+            Transport::Plain(conn) => conn.write(buf).await,
+            Transport::Tls(tls) => tls.write(buf).await.map_err(|e| e.kind()),
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        match self {
+            Transport::Plain(conn) => conn.flush().await,
+            Transport::Tls(tls) => tls.flush().await.map_err(|e| e.kind()),
+        }
+    }
+}
+
+// Wi-Fi 开着时 Rng 取的是射频噪声，可以用来生成 TLS 的临时密钥；
+// esp-hal 的 Rng 没有 CryptoRng 标记，包一层
+struct RadioRng(Rng);
+
+impl rand_core::RngCore for RadioRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.random()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        (self.0.random() as u64) << 32 | self.0.random() as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.read(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.0.read(dest);
+        Ok(())
+    }
+}
+
+impl rand_core::CryptoRng for RadioRng {}
+
+type HttpClient<'c, 'a> = http::Client<Transport<'c, 'a>, embassy_time::Delay>;
+
+// 连上服务器，HTTPS 时完成握手并按 SERVER_TRUST 校验证书；
+// socket 可能停在上一条连接的半途，先复位成 Closed 才能重新 connect
+async fn connect<'c, 'a>(
+    conn: &'c mut TcpConn<'a>,
+    endpoint: (Ipv4Addr, u16),
+    server: &Url,
+    tls_read_buffer: &'c mut [u8],
+    tls_write_buffer: &'c mut [u8],
+) -> Option<Transport<'c, 'a>> {
+    conn.0.abort();
+    conn.0.flush().await.ok();
+    println!("Connecting to server...");
+    if let Err(e) = conn.0.connect(endpoint).await {
+        println!("Connect error: {:?}", e);
+        return None;
+    }
+    let transport = if server.tls {
+        let mut tls = Box::new(TlsConnection::new(conn, tls_read_buffer, tls_write_buffer));
+        let config = TlsConfig::new().with_server_name(&server.host);
+        let provider = tls::Provider::new(RadioRng(Rng::new()), SERVER_TRUST);
+        if let Err(e) = tls.open(TlsContext::new(&config, provider)).await {
+            println!("[WARN] TLS 握手失败: {:?}", e);
+            return None;
+        }
+        Transport::Tls(tls)
+    } else {
+        Transport::Plain(conn)
+    };
+    println!("Connected!");
+    Some(transport)
+}
+
+// POST 一段 JSON；超时、连接断开或服务器回复非 2xx 都算失败
+async fn send_json(
+    client: &mut HttpClient<'_, '_>,
+    path: &str,
+    body: &str,
+) -> Result<Response, ()> {
+    match client.post_json(path, body).await {
+        Ok(resp) => {
+            println!("Data sent to {}: {}", path, body);
+            println!(
+                "Server response: {} {}",
+                resp.status,
+                resp.body_str().unwrap_or("")
+            );
+            Ok(resp)
+        }
+        Err(e) => {
+            println!("[WARN] {} 上传失败: {:?}", path, e);
+            Err(())
+        }
+    }
+}
+
+type MqttClient<'a> = mqtt::Client<TcpConn<'a>, embassy_time::Delay>;
+
+// 连接 broker (TCP + CONNECT)，连上后把状态改成 online，并更新设备清单 (都 retain)
+async fn mqtt_connect(
+    client: &mut MqttClient<'_>,
+    stack: Stack<'_>,
+    cache: &mut dns::Cache,
+    options: &mqtt::Options<'_>,
+    base: &str,
+    inventory: &str,
+) -> Result<(), ()> {
+    let Some(broker_ip) = resolve(stack, MQTT_BROKER, cache).await else {
+        println!("[WARN] 无法解析 MQTT broker 地址 {}", MQTT_BROKER);
+        return Err(());
+    };
+    let socket = &mut client.connection().0;
+    // 上一条连接可能停在半途，先复位成 Closed 才能重新 connect
+    socket.abort();
+    socket.flush().await.ok();
+    println!("Connecting to MQTT broker...");
+    if let Err(e) = socket.connect((broker_ip, MQTT_PORT)).await {
+        println!("MQTT connect error: {:?}", e);
+        return Err(());
+    }
+    let result = async {
+        client.connect(options, uptime_ms()).await?;
+        let status = format!("{}/status", base);
+        client
+            .publish(&status, b"online", QoS::AtLeastOnce, true)
+            .await?;
+        let topic = format!("{}/inventory", base);
+        client
+            .publish(&topic, inventory.as_bytes(), QoS::AtLeastOnce, true)
+            .await
+    }
+    .await;
+    match result {
+        Ok(()) => {
+            println!("MQTT connected!");
+            Ok(())
+        }
+        Err(e) => {
+            println!("[WARN] MQTT 连接失败: {:?}", e);
+            Err(())
+        }
+    }
+}
+
+// 发布一条记录的完整 JSON 到 <base>/data
+async fn mqtt_publish(
+    client: &mut MqttClient<'_>,
+    base: &str,
+    json: &str,
+) -> Result<(), MqttError<embedded_io_async::ErrorKind>> {
+    let topic = format!("{}/data", base);
+    client
+        .publish(&topic, json.as_bytes(), QoS::AtLeastOnce, false)
+        .await?;
+    println!("Data published to {}", topic);
+    Ok(())
+}
+
+// 各指标本周期的平均值发到 <base>/<指标>[/<ROM 码>] 并 retain，新订阅的客户端马上能拿到最新值
+async fn mqtt_publish_values(
+    client: &mut MqttClient<'_>,
+    base: &str,
+    collector: &Collector<MAX_SAMPLES>,
+) -> Result<(), MqttError<embedded_io_async::ErrorKind>> {
+    for series in collector.series() {
+        let Some(summary) = series.window.summary(OUTLIER_FILTER) else {
+            continue;
+        };
+        let mut topic = format!("{}/{}", base, mqtt::topic_level(series.metric));
+        if let Some(rom) = &series.channel {
+            topic.push('/');
+            for b in rom {
+                topic.push_str(&format!("{:02X}", b));
             }
         }
+        let value = format!("{:.*}", series.unit.decimals(), summary.mean);
+        client
+            .publish(&topic, value.as_bytes(), QoS::AtLeastOnce, true)
+            .await?;
+    }
+    Ok(())
+}
+
+// 解析服务器主机名：IP 直接用，缓存没过期直接用，否则通过 embassy-net 向 DHCP 下发的
+// DNS 服务器查询；查不到时用上次解析到的地址
+async fn resolve(stack: Stack<'_>, host: &str, cache: &mut dns::Cache) -> Option<Ipv4Addr> {
+    if let Ok(ip) = host.parse() {
+        return Some(ip);
+    }
+    if let Some(ip) = cache.get(uptime_ms()) {
+        return Some(ip);
+    }
+
+    match with_timeout(
+        Duration::from_millis(DNS_TIMEOUT_MS),
+        stack.dns_query(host, DnsQueryType::A),
+    )
+    .await
+    {
+        Ok(Ok(addresses)) => match addresses.first() {
+            Some(&IpAddress::Ipv4(ip)) => {
+                cache.store(ip, uptime_ms());
+                println!("[INFO] {} -> {}", host, ip);
+                return Some(ip);
+            }
+            None => println!("[WARN] {} 没有 A 记录", host),
+        },
+        Ok(Err(e)) => println!("[WARN] 解析 {} 失败: {:?}", host, e),
+        Err(_) => println!("[WARN] 解析 {} 超时", host),
+    }
+
+    let stale = cache.stale();
+    if let Some(ip) = stale {
+        println!("[WARN] 继续使用 {} 上次的地址 {}", host, ip);
+    }
+    stale
+}
+
+// 一条连接上的请求发完了，TLS 先发 close_notify (对方没反应就算了)
+async fn close(transport: Transport<'_, '_>) {
+    if let Transport::Tls(tls) = transport {
+        with_timeout(Duration::from_secs(2), tls.close()).await.ok();
+    }
+}
 
-        // 每 300 秒 (5分钟) 采集发送一次
-        Timer::after(Duration::from_secs(300)).await;
-        // // 每 10 秒 采集发送一次
-        // Timer::after(Duration::from_secs(10)).await;
+// 发完本周期的请求后关闭 socket，等 FIN 发出去，对方没反应就直接复位
+async fn shutdown(socket: &mut TcpSocket<'_>) {
+    socket.close();
+    if with_timeout(Duration::from_secs(2), socket.flush())
+        .await
+        .is_err()
+    {
+        socket.abort();
+        socket.flush().await.ok();
     }
 }
 
@@ -413,14 +1386,11 @@ async fn main(spawner: Spawner) -> ! {
 #[embassy_executor::task]
 async fn connection(mut controller: WifiController<'static>) {
     loop {
-        match esp_radio::wifi::sta_state() {
-            WifiStaState::Connected => {
-                println!("[WiFi] 已连接，等待断开事件以便重连监控。");
-                controller.wait_for_event(WifiEvent::StaDisconnected).await;
-                println!("[WiFi] 检测到断开，5 秒后重试连接。");
-                Timer::after(Duration::from_millis(5000)).await
-            }
-            _ => {}
+        if esp_radio::wifi::sta_state() == WifiStaState::Connected {
+            println!("[WiFi] 已连接，等待断开事件以便重连监控。");
+            controller.wait_for_event(WifiEvent::StaDisconnected).await;
+            println!("[WiFi] 检测到断开，5 秒后重试连接。");
+            Timer::after(Duration::from_millis(5000)).await
         }
         if !matches!(controller.is_started(), Ok(true)) {
             let client_config = ModeConfig::Client(
@@ -441,6 +1411,127 @@ async fn connection(mut controller: WifiController<'static>) {
     }
 }
 
+// 串口控制台：从 USB 串口 (espflash monitor) 逐行读取命令
+#[embassy_executor::task]
+async fn console_task(mut rx: UsbSerialJtagRx<'static, Async>) {
+    let mut line = String::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let n = match embedded_io_async::Read::read(&mut rx, &mut buf).await {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+        for &b in &buf[..n] {
+            match b {
+                b'\r' | b'\n' => {
+                    if !line.trim().is_empty() {
+                        run_calibration_command(line.trim());
+                    }
+                    line.clear();
+                }
+                _ if line.len() < CONSOLE_LINE_MAX && b.is_ascii() => line.push(b as char),
+                _ => {}
+            }
+        }
+    }
+}
+
+// 持续接收 CO2 模块的数据流，把最新的有效读数写进 CO2_STATE
+// read_async 由串口中断唤醒：FIFO 收到数据且线路空闲一小段时间后返回
+#[embassy_executor::task]
+async fn co2_task(mut rx: UartRx<'static, Async>) {
+    let mut parser = jw01::Parser::new();
+    let mut buf = [0u8; 32];
+    let mut last_data = Instant::now();
+    loop {
+        let n = match with_timeout(
+            Duration::from_millis(CO2_FRAME_GAP_MS),
+            rx.read_async(&mut buf),
+        )
+        .await
+        {
+            Ok(Ok(n)) => {
+                last_data = Instant::now();
+                n
+            }
+            Ok(Err(e)) => {
+                println!("[WARN] CO2 串口错误: {:?}", e);
+                critical_section::with(|cs| CO2_STATE.borrow_ref_mut(cs).uart_errors += 1);
+                // 错误之后的数据可能已经错位，重新对齐
+                parser.timeout();
+                continue;
+            }
+            Err(_) => {
+                // 串口停顿：收到一半的帧作废
+                if let Some(e) = parser.timeout() {
+                    println!("[WARN] CO2 帧接收超时: {:?}", e);
+                    critical_section::with(|cs| CO2_STATE.borrow_ref_mut(cs).stats.record(&Err(e)));
+                } else if last_data.elapsed() >= Duration::from_millis(CO2_NO_DATA_MS) {
+                    // 一整个发送周期都没有数据：传感器没接、掉线或没供电
+                    last_data = Instant::now();
+                    critical_section::with(|cs| {
+                        CO2_STATE.borrow_ref_mut(cs).stats.record_no_data()
+                    });
+                }
+                continue;
+            }
+        };
+
+        for &byte in &buf[..n] {
+            let Some(result) = parser.push(byte) else {
+                continue;
+            };
+            if let Err(e) = result {
+                println!("[WARN] CO2 帧错误: {:?}", e);
+            }
+            critical_section::with(|cs| {
+                let mut state = CO2_STATE.borrow_ref_mut(cs);
+                state.stats.record(&result);
+                if let Ok(frame) = result {
+                    state.latest = Some((frame.ppm, Instant::now()));
+                }
+            });
+        }
+    }
+}
+
+// 问答式 CO2 传感器：上电时按配置设置 ABC/零点校准，之后定时读取并写进 CO2_STATE
+#[embassy_executor::task]
+async fn co2_poll_task(uart: Uart<'static, Async>, model: ndir::Model) {
+    let mut sensor = Ndir::new(uart, embassy_time::Delay, model);
+    if let Some(enabled) = CO2_ABC {
+        match sensor.set_abc(enabled).await {
+            Ok(()) => println!(
+                "[INFO] {} ABC {}",
+                model.name(),
+                if enabled { "on" } else { "off" }
+            ),
+            Err(e) => println!("[WARN] {} 设置 ABC 失败: {:?}", model.name(), e),
+        }
+    }
+    if CO2_CALIBRATE_ZERO_ON_BOOT {
+        match sensor.calibrate_zero().await {
+            Ok(()) => println!("[INFO] {} 已按 400 ppm 做零点校准", model.name()),
+            Err(e) => println!("[WARN] {} 零点校准失败: {:?}", model.name(), e),
+        }
+    }
+
+    loop {
+        let result = sensor.read_ppm().await;
+        if let Err(e) = &result {
+            println!("[WARN] {} 读取失败: {:?}", model.name(), e);
+        }
+        critical_section::with(|cs| {
+            let mut state = CO2_STATE.borrow_ref_mut(cs);
+            state.ndir.record(&result);
+            if let Ok(ppm) = result {
+                state.latest = Some((ppm, Instant::now()));
+            }
+        });
+        Timer::after(Duration::from_millis(CO2_POLL_MS)).await;
+    }
+}
+
 #[embassy_executor::task]
 async fn net_task(mut runner: Runner<'static, WifiDevice<'static>>) {
     runner.run().await
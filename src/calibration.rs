@@ -0,0 +1,417 @@
+//! 每台设备的校准参数
+//!
+//! 同一型号的 DS18B20、CO2 模块之间读数有固定偏差，和参考温度计、CO2 仪对照后得到：
+//!
+//! - 温度：`读数 + 偏移`，每个 DS18B20 探头按 ROM 码单独设置
+//! - CO2：`读数 × co2_gain + co2_offset`
+//!
+//! 参数保存在 Flash 的 `calib` 分区，上电时读出；可以通过串口控制台或服务器下发的
+//! 命令修改，格式相同：
+//!
+//! ```text
+//! cal                                            显示当前参数
+//! cal temp_offset.28FF1E6400000001=-0.3 co2_gain=1.02   修改其中几项并保存
+//! cal reset                                      恢复默认 (不校准) 并保存
+//! ```
+
+use alloc::{format, string::String, vec::Vec};
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::flash;
+
+use crate::ds18b20::{self, crc8};
+use crate::json;
+use crate::jw01;
+use crate::onewire::Rom;
+use crate::sensor::{ChannelHex, Measurement};
+
+/// 最多给几个 DS18B20 单独设置温度偏移
+pub const MAX_PROBES: usize = 8;
+// 每个探头占 12 字节：ROM 码、偏移 (f32)
+const PROBE_LEN: usize = 12;
+/// Flash 里一条记录的长度 (按 4 字节对齐)
+pub const RECORD_LEN: usize = 16 + MAX_PROBES * PROBE_LEN + 4;
+// 记录开头的标记，版本号变了就当作没有保存过
+const MAGIC: [u8; 4] = *b"CAL2";
+
+// 允许设置的范围，超出的多半是输错了
+const MAX_TEMP_OFFSET: f32 = 5.0;
+const MAX_CO2_OFFSET: f32 = 500.0;
+const MIN_CO2_GAIN: f32 = 0.5;
+const MAX_CO2_GAIN: f32 = 1.5;
+
+/// 各个 DS18B20 的温度偏移 (°C)，按 ROM 码对应；没有列出的探头不修正
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempOffsets {
+    // 前 len 项有效，其余保持全 0，整体比较才有意义
+    entries: [(Rom, f32); MAX_PROBES],
+    len: usize,
+}
+
+impl TempOffsets {
+    pub const NONE: Self = Self {
+        entries: [([0; 8], 0.0); MAX_PROBES],
+        len: 0,
+    };
+
+    /// 这个探头的偏移，没有设置过时是 0
+    pub fn get(&self, rom: &Rom) -> f32 {
+        self.iter()
+            .find(|(r, _)| r == rom)
+            .map_or(0.0, |&(_, offset)| offset)
+    }
+
+    /// 设置一个探头的偏移，设成 0 就是去掉；已经有 `MAX_PROBES` 个时加不进新的
+    pub fn set(&mut self, rom: Rom, offset: f32) -> Result<(), CommandError> {
+        let found = self.iter().position(|(r, _)| *r == rom);
+        match found {
+            Some(i) if offset == 0.0 => {
+                self.entries.copy_within(i + 1..self.len, i);
+                self.len -= 1;
+                self.entries[self.len] = ([0; 8], 0.0);
+            }
+            Some(i) => self.entries[i].1 = offset,
+            None if offset == 0.0 => {}
+            None if self.len == MAX_PROBES => return Err(CommandError::TooManyProbes),
+            None => {
+                self.entries[self.len] = (rom, offset);
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Rom, f32)> {
+        self.entries[..self.len].iter()
+    }
+}
+
+/// 校准参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// 温度偏移 (°C)，按 DS18B20 的 ROM 码分别设置
+    pub temp_offsets: TempOffsets,
+    /// CO2 偏移 (ppm)
+    pub co2_offset: f32,
+    /// CO2 增益
+    pub co2_gain: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+impl Calibration {
+    /// 不做任何修正
+    pub const NONE: Self = Self {
+        temp_offsets: TempOffsets::NONE,
+        co2_offset: 0.0,
+        co2_gain: 1.0,
+    };
+
+    /// 修正一个测量值，其他指标原样返回 (无效值是 NaN，修正后仍是 NaN)
+    pub fn apply(&self, m: Measurement) -> Measurement {
+        let value = match m.metric {
+            ds18b20::METRIC => m.value + m.channel.map_or(0.0, |rom| self.temp_offsets.get(&rom)),
+            jw01::METRIC => m.value * self.co2_gain + self.co2_offset,
+            _ => return m,
+        };
+        Measurement { value, ..m }
+    }
+
+    /// 各项是否在允许的范围内
+    pub fn is_valid(&self) -> bool {
+        self.temp_offsets
+            .iter()
+            .all(|(_, offset)| offset.abs() <= MAX_TEMP_OFFSET)
+            && self.co2_offset.abs() <= MAX_CO2_OFFSET
+            && (MIN_CO2_GAIN..=MAX_CO2_GAIN).contains(&self.co2_gain)
+    }
+
+    /// 编码成 Flash 记录：标记、CO2 偏移和增益、探头个数 (补齐到 4 字节)、
+    /// 每个探头的 ROM 码和偏移、CRC8 (补齐到 4 字节)，数值都是小端 f32
+    pub fn to_bytes(&self) -> [u8; RECORD_LEN] {
+        let mut record = [0u8; RECORD_LEN];
+        record[..4].copy_from_slice(&MAGIC);
+        record[4..8].copy_from_slice(&self.co2_offset.to_le_bytes());
+        record[8..12].copy_from_slice(&self.co2_gain.to_le_bytes());
+        record[12] = self.temp_offsets.len as u8;
+        for (i, (rom, offset)) in self.temp_offsets.iter().enumerate() {
+            let at = 16 + i * PROBE_LEN;
+            record[at..at + 8].copy_from_slice(rom);
+            record[at + 8..at + 12].copy_from_slice(&offset.to_le_bytes());
+        }
+        record[RECORD_LEN - 4] = crc8(&record[..RECORD_LEN - 4]);
+        record
+    }
+
+    /// 解码 Flash 记录，没有保存过 (全 0xFF)、CRC 不对或超出范围时返回 `None`
+    pub fn from_bytes(record: &[u8; RECORD_LEN]) -> Option<Self> {
+        if record[..4] != MAGIC || record[RECORD_LEN - 4] != crc8(&record[..RECORD_LEN - 4]) {
+            return None;
+        }
+        let f32_at =
+            |i: usize| f32::from_le_bytes([record[i], record[i + 1], record[i + 2], record[i + 3]]);
+        let len = record[12] as usize;
+        if len > MAX_PROBES {
+            return None;
+        }
+        let mut cal = Self {
+            temp_offsets: TempOffsets::NONE,
+            co2_offset: f32_at(4),
+            co2_gain: f32_at(8),
+        };
+        for i in 0..len {
+            let at = 16 + i * PROBE_LEN;
+            let mut rom = [0u8; 8];
+            rom.copy_from_slice(&record[at..at + 8]);
+            cal.temp_offsets.set(rom, f32_at(at + 8)).ok()?;
+        }
+        cal.is_valid().then_some(cal)
+    }
+
+    /// 读出最近一次保存的参数 (分区开头两个扇区里较新的有效记录)
+    pub fn load<F: NorFlash>(flash: &mut F) -> Result<Option<Self>, F::Error> {
+        flash::load_latest(flash, Self::from_bytes)
+    }
+
+    /// 写进两个扇区里较旧的那个，保存中途断电时仍能读出上一次的参数
+    pub fn save<F: NorFlash>(&self, flash: &mut F) -> Result<(), F::Error> {
+        flash::save_next(flash, &self.to_bytes(), Self::from_bytes)
+    }
+
+    /// 上传 JSON 的字段 (不含外层大括号)：
+    /// `"calibration":{"temp_offsets":{"28FF1E6400000001":-0.30},"co2_offset":12,"co2_gain":1.020}`
+    pub fn json_field(&self) -> String {
+        let temp_offsets: Vec<_> = self
+            .temp_offsets
+            .iter()
+            .map(|(rom, offset)| format!("\"{}\":{:.2}", ChannelHex(rom), offset))
+            .collect();
+        format!(
+            "\"calibration\":{{\"temp_offsets\":{{{}}},\"co2_offset\":{:.0},\"co2_gain\":{:.3}}}",
+            temp_offsets.join(","),
+            self.co2_offset,
+            self.co2_gain
+        )
+    }
+}
+
+/// 校准命令
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// 显示当前参数
+    Show,
+    /// 改成新的参数并保存 (`cal reset` 也是这一种)
+    Set(Calibration),
+}
+
+/// 命令解析失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    /// 不是 `cal` 开头
+    NotCalibration,
+    UnknownKey,
+    BadValue,
+    /// 修改后超出允许的范围
+    OutOfRange,
+    /// 单独设置偏移的探头超过 `MAX_PROBES` 个
+    TooManyProbes,
+}
+
+/// 解析一行命令，`current` 是当前参数 (只改命令里出现的项)
+pub fn parse_command(line: &str, current: &Calibration) -> Result<Command, CommandError> {
+    let mut words = line.split_whitespace();
+    if words.next() != Some("cal") {
+        return Err(CommandError::NotCalibration);
+    }
+    let mut cal = *current;
+    let mut changed = false;
+    for word in words {
+        if word == "reset" {
+            cal = Calibration::NONE;
+            changed = true;
+            continue;
+        }
+        let (key, value) = word.split_once('=').ok_or(CommandError::UnknownKey)?;
+        let value: f32 = value.parse().map_err(|_| CommandError::BadValue)?;
+        if !value.is_finite() {
+            return Err(CommandError::BadValue);
+        }
+        match key {
+            "co2_offset" => cal.co2_offset = value,
+            "co2_gain" => cal.co2_gain = value,
+            _ => {
+                let rom = key
+                    .strip_prefix("temp_offset.")
+                    .and_then(parse_rom)
+                    .ok_or(CommandError::UnknownKey)?;
+                cal.temp_offsets.set(rom, value)?;
+            }
+        }
+        changed = true;
+    }
+    if !changed {
+        return Ok(Command::Show);
+    }
+    if !cal.is_valid() {
+        return Err(CommandError::OutOfRange);
+    }
+    Ok(Command::Set(cal))
+}
+
+// 16 位十六进制的 ROM 码，和上传、日志里的写法一样 (家族码在前)
+fn parse_rom(hex: &str) -> Option<Rom> {
+    if hex.len() != 16 {
+        return None;
+    }
+    let mut rom = [0u8; 8];
+    for (i, byte) in rom.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(rom)
+}
+
+/// 从服务器响应里取出下发的命令：最外层的 `{"command": "cal co2_offset=12"}`
+pub fn command_in_response(response: &str) -> Option<&str> {
+    json::field(response, "command")?.as_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flash::sim::RamFlash;
+    use crate::sensor::{Quality, Unit};
+
+    const ROM_A: Rom = [0x28, 0xFF, 0x1E, 0x64, 0, 0, 0, 0x01];
+    const ROM_B: Rom = [0x28, 0xFF, 0x1E, 0x64, 0, 0, 0, 0x02];
+
+    fn offsets(list: &[(Rom, f32)]) -> TempOffsets {
+        let mut offsets = TempOffsets::NONE;
+        for &(rom, offset) in list {
+            offsets.set(rom, offset).unwrap();
+        }
+        offsets
+    }
+
+    #[test]
+    fn applies_to_temperature_and_co2_only() {
+        let cal = Calibration {
+            temp_offsets: offsets(&[(ROM_A, -0.25), (ROM_B, 0.5)]),
+            co2_offset: 12.0,
+            co2_gain: 1.1,
+        };
+        let temp = |rom| Measurement::new(ds18b20::METRIC, 21.5, Unit::Celsius).with_channel(rom);
+        assert_eq!(cal.apply(temp(ROM_A)).value, 21.25);
+        assert_eq!(cal.apply(temp(ROM_B)).value, 22.0);
+        // 没有设置过的探头不修正
+        assert_eq!(cal.apply(temp([0x28, 0, 0, 0, 0, 0, 0, 0x03])).value, 21.5);
+        let co2 = cal.apply(Measurement::new(jw01::METRIC, 500.0, Unit::Ppm));
+        assert!((co2.value - 562.0).abs() < 0.01);
+        let rh = Measurement::new("humidity", 55.0, Unit::Percent);
+        assert_eq!(cal.apply(rh), rh);
+        let invalid = cal.apply(Measurement::invalid(jw01::METRIC, Unit::Ppm));
+        assert_eq!(invalid.quality, Quality::Invalid);
+        assert!(invalid.value.is_nan());
+    }
+
+    #[test]
+    fn persists_in_flash() {
+        let mut flash = RamFlash::new(2 * 4096);
+        assert_eq!(Calibration::load(&mut flash), Ok(None));
+
+        let cal = Calibration {
+            temp_offsets: offsets(&[(ROM_A, 0.4), (ROM_B, -1.25)]),
+            co2_offset: -30.0,
+            co2_gain: 0.98,
+        };
+        cal.save(&mut flash).unwrap();
+        assert_eq!(Calibration::load(&mut flash), Ok(Some(cal)));
+        Calibration::NONE.save(&mut flash).unwrap();
+        assert_eq!(Calibration::load(&mut flash), Ok(Some(Calibration::NONE)));
+
+        // 第二条记录在扇区 B，坏了就退回扇区 A 里上一次保存的参数
+        flash.data[4096 + 5] ^= 0x01;
+        assert_eq!(Calibration::load(&mut flash), Ok(Some(cal)));
+        flash.data[5] ^= 0x01;
+        assert_eq!(Calibration::load(&mut flash), Ok(None));
+    }
+
+    #[test]
+    fn parses_commands() {
+        let current = Calibration::NONE;
+        assert_eq!(parse_command("cal", &current), Ok(Command::Show));
+        let set = Calibration {
+            temp_offsets: offsets(&[(ROM_A, -0.3)]),
+            co2_gain: 1.02,
+            ..current
+        };
+        assert_eq!(
+            parse_command(
+                "cal temp_offset.28ff1e6400000001=-0.3 co2_gain=1.02",
+                &current
+            ),
+            Ok(Command::Set(set))
+        );
+        assert_eq!(
+            set.json_field(),
+            "\"calibration\":{\"temp_offsets\":{\"28FF1E6400000001\":-0.30},\
+             \"co2_offset\":0,\"co2_gain\":1.020}"
+        );
+        // 设成 0 就去掉这个探头
+        assert_eq!(
+            parse_command("cal temp_offset.28FF1E6400000001=0 co2_gain=1", &set),
+            Ok(Command::Set(current))
+        );
+        assert_eq!(parse_command("cal reset", &set), Ok(Command::Set(current)));
+        assert_eq!(
+            parse_command("cal co2_gain=3", &current),
+            Err(CommandError::OutOfRange)
+        );
+        assert_eq!(
+            parse_command("cal co2_gain=abc", &current),
+            Err(CommandError::BadValue)
+        );
+        for key in [
+            "offset",
+            "temp_offset",
+            "temp_offset.28FF",
+            "temp_offset.28FF1E64000000XY",
+        ] {
+            assert_eq!(
+                parse_command(&format!("cal {}=1", key), &current),
+                Err(CommandError::UnknownKey)
+            );
+        }
+        let mut full = current;
+        for i in 0..MAX_PROBES as u8 {
+            full.temp_offsets
+                .set([0x28, 0, 0, 0, 0, 0, 0, i], 0.1)
+                .unwrap();
+        }
+        assert_eq!(
+            parse_command("cal temp_offset.28FF1E6400000001=0.2", &full),
+            Err(CommandError::TooManyProbes)
+        );
+        assert_eq!(
+            parse_command("help", &current),
+            Err(CommandError::NotCalibration)
+        );
+        assert_eq!(
+            command_in_response("{\"ok\":true,\"command\":\"cal co2_offset=12\"}"),
+            Some("cal co2_offset=12")
+        );
+        assert_eq!(
+            command_in_response("{ \"command\" : \"cal co2_offset=12\" }"),
+            Some("cal co2_offset=12")
+        );
+        assert_eq!(command_in_response("{\"ok\":true}"), None);
+        assert_eq!(
+            command_in_response("{\"log\":{\"command\":\"cal co2_offset=12\"}}"),
+            None
+        );
+        assert_eq!(command_in_response("{\"command\":12}"), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! 开机时的传感器发现
+//!
+//! 上电后依次探测 1-Wire 总线 (搜索 ROM 码)、CO2 串口 (等待一帧有效数据) 和 I2C 总线
+//! (逐个地址试写)，结果汇总成 `Inventory`：主循环只采样找到的传感器，
+//! 清单本身在联网后上传一次，服务器据此知道每台设备实际接了哪些探头。
+
+use alloc::{format, string::String, vec::Vec};
+use embedded_hal::i2c::I2c;
+
+use crate::light;
+use crate::onewire::Rom;
+use crate::sensor::ChannelHex;
+
+/// 7 位地址中可以使用的范围 (0x00~0x07、0x78~0x7F 是保留地址)
+pub const I2C_ADDRESSES: core::ops::RangeInclusive<u8> = 0x08..=0x77;
+
+/// 认识的 I2C 器件：地址和名称
+pub const KNOWN_I2C: [(u8, &str); 5] = [
+    (0x10, "VEML7700"),
+    (0x23, "BH1750"),
+    (0x44, "SHT4x/SHT3x"),
+    (0x45, "SHT3x"),
+    (0x5C, "BH1750"),
+];
+
+/// 地址对应的器件名称，不认识的返回 `None`
+pub fn i2c_device_name(address: u8) -> Option<&'static str> {
+    KNOWN_I2C
+        .iter()
+        .find(|&&(a, _)| a == address)
+        .map(|&(_, name)| name)
+}
+
+/// 逐个地址发一次空写，返回有 ACK 的地址
+pub fn scan_i2c<I: I2c>(i2c: &mut I) -> Vec<u8> {
+    I2C_ADDRESSES
+        .filter(|&address| i2c.write(address, &[]).is_ok())
+        .collect()
+}
+
+/// 根据扫描结果选择光照传感器的型号和地址
+pub fn find_lux(found: &[u8]) -> Option<(light::Model, u8)> {
+    [
+        (light::Model::Bh1750, 0x23),
+        (light::Model::Bh1750, 0x5C),
+        (light::Model::Veml7700, 0x10),
+    ]
+    .into_iter()
+    .find(|(_, address)| found.contains(address))
+}
+
+/// 开机时发现的传感器
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inventory {
+    /// 固件版本
+    pub firmware: &'static str,
+    /// Wi-Fi MAC 地址，用来区分设备
+    pub mac: [u8; 6],
+    /// 1-Wire 总线上的 DS18B20
+    pub onewire: Vec<Rom>,
+    /// 收到有效数据的 CO2 传感器型号
+    pub co2: Option<&'static str>,
+    /// I2C 总线上有 ACK 的地址
+    pub i2c: Vec<u8>,
+    /// 接在 ADC 上、无法自动探测的传感器 (按配置列出)
+    pub analog: Vec<&'static str>,
+}
+
+impl Inventory {
+    /// I2C 地址上是否有设备
+    pub fn has_i2c(&self, address: u8) -> bool {
+        self.i2c.contains(&address)
+    }
+
+    /// 上传给服务器的清单：
+    /// `{"type":"inventory","firmware":"0.1.0","mac":"..","onewire":["28FF.."],"co2":"JW01",
+    /// "i2c":[{"address":"0x44","device":"SHT4x/SHT3x"}],"analog":["nh3"]}`
+    pub fn json(&self) -> String {
+        let mac: Vec<_> = self.mac.iter().map(|b| format!("{:02X}", b)).collect();
+        let onewire: Vec<_> = self
+            .onewire
+            .iter()
+            .map(|rom| format!("\"{}\"", ChannelHex(rom)))
+            .collect();
+        let co2 = match self.co2 {
+            Some(model) => format!("\"{}\"", model),
+            None => "null".into(),
+        };
+        let i2c: Vec<_> = self
+            .i2c
+            .iter()
+            .map(|&address| {
+                let device = match i2c_device_name(address) {
+                    Some(name) => format!("\"{}\"", name),
+                    None => "null".into(),
+                };
+                format!(
+                    "{{\"address\":\"0x{:02X}\",\"device\":{}}}",
+                    address, device
+                )
+            })
+            .collect();
+        let analog: Vec<_> = self.analog.iter().map(|a| format!("\"{}\"", a)).collect();
+        format!(
+            "{{\"type\":\"inventory\",\"firmware\":\"{}\",\"mac\":\"{}\",\"onewire\":[{}],\
+             \"co2\":{},\"i2c\":[{}],\"analog\":[{}]}}",
+            self.firmware,
+            mac.join(":"),
+            onewire.join(","),
+            co2,
+            i2c.join(","),
+            analog.join(",")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+
+    // 只有列出的地址会 ACK
+    struct Bus(Vec<u8>);
+
+    impl ErrorType for Bus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Bus {
+        fn transaction(
+            &mut self,
+            address: u8,
+            _operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            if self.0.contains(&address) {
+                Ok(())
+            } else {
+                Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+            }
+        }
+    }
+
+    #[test]
+    fn scans_bus_and_picks_lux_model() {
+        let found = scan_i2c(&mut Bus(vec![0x44, 0x10, 0x7E]));
+        assert_eq!(found, [0x10, 0x44]);
+        assert_eq!(find_lux(&found), Some((light::Model::Veml7700, 0x10)));
+        assert_eq!(find_lux(&[0x44]), None);
+        assert_eq!(find_lux(&[0x44, 0x5C]), Some((light::Model::Bh1750, 0x5C)));
+    }
+
+    #[test]
+    fn builds_inventory_message() {
+        let inventory = Inventory {
+            firmware: "0.1.0",
+            mac: [0x40, 0x4C, 0xCA, 0x01, 0x02, 0x03],
+            onewire: vec![[0x28, 0xFF, 0x1E, 0x64, 0, 0, 0, 0x01]],
+            co2: Some("JW01-CO2"),
+            i2c: vec![0x44, 0x50],
+            analog: vec!["nh3"],
+        };
+        assert!(inventory.has_i2c(0x44));
+        assert_eq!(
+            inventory.json(),
+            "{\"type\":\"inventory\",\"firmware\":\"0.1.0\",\"mac\":\"40:4C:CA:01:02:03\",\
+             \"onewire\":[\"28FF1E6400000001\"],\"co2\":\"JW01-CO2\",\
+             \"i2c\":[{\"address\":\"0x44\",\"device\":\"SHT4x/SHT3x\"},\
+             {\"address\":\"0x50\",\"device\":null}],\"analog\":[\"nh3\"]}"
+        );
+    }
+}
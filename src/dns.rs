@@ -0,0 +1,58 @@
+//! 主机名解析结果的缓存
+//!
+//! 服务器配置成主机名，后台迁移时只改 DNS 记录，不用给每台设备重新烧录。解析由
+//! embassy-net 的 `Stack::dns_query` (smoltcp 的 DNS socket，向 DHCP 下发的 DNS 服务器查询)
+//! 完成，但它只返回地址、不返回记录的 TTL，所以这里只能按固定的 `MAX_AGE_S` 缓存：
+//! 取得很短，服务器的 TTL 再短也最多晚几十秒生效，同时同一个周期里 HTTP 和 MQTT
+//! 不用各查一次。重新解析失败时仍然可以取上次的地址 (服务器地址很少变，总比一直传不上去好)。
+
+use core::net::Ipv4Addr;
+
+/// 解析结果最多用多久 (秒)
+pub const MAX_AGE_S: u64 = 30;
+
+/// 一个主机名的解析结果
+#[derive(Debug, Default)]
+pub struct Cache {
+    // 地址和过期时刻 (上电以来的毫秒数)
+    entry: Option<(Ipv4Addr, u64)>,
+}
+
+impl Cache {
+    pub const fn new() -> Self {
+        Self { entry: None }
+    }
+
+    /// 没过期的地址
+    pub fn get(&self, now_ms: u64) -> Option<Ipv4Addr> {
+        self.entry
+            .filter(|&(_, expires_ms)| now_ms < expires_ms)
+            .map(|(address, _)| address)
+    }
+
+    /// 上次解析到的地址，不管过没过期
+    pub fn stale(&self) -> Option<Ipv4Addr> {
+        self.entry.map(|(address, _)| address)
+    }
+
+    /// 记下解析结果，`MAX_AGE_S` 秒后过期
+    pub fn store(&mut self, address: Ipv4Addr, now_ms: u64) {
+        self.entry = Some((address, now_ms + MAX_AGE_S * 1000));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_but_keeps_stale_address() {
+        let address = Ipv4Addr::new(93, 184, 216, 34);
+        let mut cache = Cache::new();
+        assert_eq!((cache.get(0), cache.stale()), (None, None));
+        cache.store(address, 1_000);
+        assert_eq!(cache.get(30_999), Some(address));
+        assert_eq!(cache.get(31_000), None);
+        assert_eq!(cache.stale(), Some(address));
+    }
+}
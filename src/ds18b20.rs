@@ -0,0 +1,816 @@
+//! DS18B20 温度传感器
+//!
+//! 暂存器 (Scratchpad) 的解析与校验，以及基于 `onewire::OneWire` 的功能命令。
+//! 总线时序由 `OneWireBus` 的具体实现负责，这里与硬件无关。
+
+use alloc::vec::Vec;
+use embedded_hal::delay::DelayNs;
+
+use crate::onewire::{BusError, OneWire, OneWireBus, Rom};
+use crate::sensor::{Measurement, Sensor, Unit};
+
+/// DS18B20 的家族码 (ROM 码第一个字节)
+pub const FAMILY_CODE: u8 = 0x28;
+
+const CONVERT_T: u8 = 0x44;
+const WRITE_SCRATCHPAD: u8 = 0x4E;
+const READ_SCRATCHPAD: u8 = 0xBE;
+const COPY_SCRATCHPAD: u8 = 0x48;
+// Copy Scratchpad 写 EEPROM 最长需要 10ms
+const COPY_SCRATCHPAD_MS: u32 = 10;
+
+/// Read Scratchpad (0xBE) 返回的完整长度：8 字节数据 + 1 字节 CRC
+pub const SCRATCHPAD_LEN: usize = 9;
+
+/// 上电后温度寄存器的默认值 (+85 °C)，说明还没有完成过一次转换
+pub const POWER_ON_RAW: u16 = 0x0550;
+
+/// 温度转换分辨率，对应配置寄存器 (暂存器第 4 字节) 的 R1 R0 位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Bits9,
+    Bits10,
+    Bits11,
+    Bits12,
+}
+
+impl Resolution {
+    /// 从配置寄存器解析分辨率 (bit6:5 = R1 R0)
+    pub fn from_config(config: u8) -> Self {
+        match (config >> 5) & 0x03 {
+            0 => Resolution::Bits9,
+            1 => Resolution::Bits10,
+            2 => Resolution::Bits11,
+            _ => Resolution::Bits12,
+        }
+    }
+
+    /// 写入配置寄存器的值 (R1 R0 之外的位固定为 1)
+    pub fn config(self) -> u8 {
+        let bits = match self {
+            Resolution::Bits9 => 0,
+            Resolution::Bits10 => 1,
+            Resolution::Bits11 => 2,
+            Resolution::Bits12 => 3,
+        };
+        (bits << 5) | 0x1F
+    }
+
+    /// 数据手册给出的最长转换时间 (tCONV)
+    pub fn conversion_time_ms(self) -> u32 {
+        match self {
+            Resolution::Bits9 => 94,
+            Resolution::Bits10 => 188,
+            Resolution::Bits11 => 375,
+            Resolution::Bits12 => 750,
+        }
+    }
+
+    /// 该分辨率下温度寄存器中未定义的低位
+    pub(crate) fn undefined_mask(self) -> u16 {
+        match self {
+            Resolution::Bits9 => 0x0007,
+            Resolution::Bits10 => 0x0003,
+            Resolution::Bits11 => 0x0001,
+            Resolution::Bits12 => 0x0000,
+        }
+    }
+}
+
+/// 校验通过的暂存器读数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reading {
+    /// 温度寄存器原始值 (MSB << 8 | LSB)
+    pub raw: u16,
+    /// 读数对应的分辨率
+    pub resolution: Resolution,
+}
+
+impl Reading {
+    /// 换算成摄氏度
+    pub fn celsius(&self) -> f32 {
+        decode_temperature(self.raw, self.resolution)
+    }
+}
+
+/// 把温度寄存器原始值解码为摄氏度
+///
+/// 原始值是 1/16 °C 为单位的二进制补码；低分辨率下未定义的低位会先清零。
+pub fn decode_temperature(raw: u16, resolution: Resolution) -> f32 {
+    let value = (raw & !resolution.undefined_mask()) as i16;
+    value as f32 / 16.0
+}
+
+/// 读取温度失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TempError {
+    /// 复位后没有存在脉冲，或者读回的全是 0xFF (DQ 断开)
+    NoDevice,
+    /// 读回的全是 0x00：DQ 一直被拉低 (短路或探头损坏)，全零恰好能通过 CRC
+    BusStuckLow,
+    /// 暂存器 CRC 不匹配 (总线干扰)
+    CrcMismatch { expected: u8, actual: u8 },
+    /// 读到上电默认值 85 °C，转换没有真正执行
+    PowerOnValue,
+    /// 总线驱动出错 (RMT 时隙没发出去或没录到)
+    BusFault,
+}
+
+impl From<BusError> for TempError {
+    fn from(_: BusError) -> Self {
+        TempError::BusFault
+    }
+}
+
+/// Dallas/Maxim CRC8 (多项式 x^8 + x^5 + x^4 + 1，低位先行)
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        let mut b = byte;
+        for _ in 0..8 {
+            let mix = (crc ^ b) & 0x01;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8C;
+            }
+            b >>= 1;
+        }
+    }
+    crc
+}
+
+/// 只检查暂存器是否完整可信 (器件在线且 CRC 正确)
+pub fn verify_scratchpad(scratchpad: &[u8; SCRATCHPAD_LEN]) -> Result<(), TempError> {
+    // DQ 断开时上拉电阻让每个读时隙都是 1
+    if scratchpad.iter().all(|&b| b == 0xFF) {
+        return Err(TempError::NoDevice);
+    }
+    if scratchpad.iter().all(|&b| b == 0x00) {
+        return Err(TempError::BusStuckLow);
+    }
+
+    let expected = crc8(&scratchpad[..8]);
+    let actual = scratchpad[8];
+    if expected != actual {
+        return Err(TempError::CrcMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+/// 校验 9 字节暂存器，成功时返回温度原始值和分辨率
+pub fn check_scratchpad(scratchpad: &[u8; SCRATCHPAD_LEN]) -> Result<Reading, TempError> {
+    verify_scratchpad(scratchpad)?;
+
+    let raw = ((scratchpad[1] as u16) << 8) | (scratchpad[0] as u16);
+    if raw == POWER_ON_RAW {
+        return Err(TempError::PowerOnValue);
+    }
+    Ok(Reading {
+        raw,
+        resolution: Resolution::from_config(scratchpad[4]),
+    })
+}
+
+/// Skip ROM + Convert T：总线上所有探头同时开始转换
+///
+/// 寄生供电时会保持强上拉，等满 tCONV 后必须调用 `OneWire::end_strong_pullup`。
+pub fn start_conversion<B: OneWireBus>(ow: &mut OneWire<B>) -> Result<(), TempError> {
+    if !ow.reset() {
+        return Err(TempError::NoDevice);
+    }
+    ow.skip();
+    ow.write_power_command(CONVERT_T);
+    Ok(ow.take_error()?)
+}
+
+/// 选中指定探头并读取完整的 9 字节暂存器 (未校验)
+pub fn read_scratchpad<B: OneWireBus>(
+    ow: &mut OneWire<B>,
+    rom: &Rom,
+) -> Result<[u8; SCRATCHPAD_LEN], TempError> {
+    if !ow.reset() {
+        return Err(TempError::NoDevice);
+    }
+    ow.select(rom);
+    ow.write_byte(READ_SCRATCHPAD);
+
+    let mut scratchpad = [0u8; SCRATCHPAD_LEN];
+    for b in scratchpad.iter_mut() {
+        *b = ow.read_byte();
+    }
+    ow.take_error()?;
+    Ok(scratchpad)
+}
+
+/// 读取并校验暂存器，返回温度读数
+pub fn read_temperature<B: OneWireBus>(
+    ow: &mut OneWire<B>,
+    rom: &Rom,
+) -> Result<Reading, TempError> {
+    check_scratchpad(&read_scratchpad(ow, rom)?)
+}
+
+/// 设置转换分辨率：Write Scratchpad (0x4E) 写入 TH、TL、配置寄存器
+///
+/// `persist` 为 true 时再用 Copy Scratchpad (0x48) 写进 EEPROM，掉电不丢。
+/// 分辨率已经相同时不会重复写入。
+pub fn set_resolution<B: OneWireBus>(
+    ow: &mut OneWire<B>,
+    rom: &Rom,
+    resolution: Resolution,
+    persist: bool,
+    delay: &mut impl DelayNs,
+) -> Result<(), TempError> {
+    // 先读出当前的 TH/TL，写回时保持不变
+    let scratchpad = read_scratchpad(ow, rom)?;
+    verify_scratchpad(&scratchpad)?;
+    if Resolution::from_config(scratchpad[4]) == resolution {
+        return Ok(());
+    }
+
+    if !ow.reset() {
+        return Err(TempError::NoDevice);
+    }
+    ow.select(rom);
+    ow.write_byte(WRITE_SCRATCHPAD);
+    ow.write_byte(scratchpad[2]); // TH
+    ow.write_byte(scratchpad[3]); // TL
+    ow.write_byte(resolution.config());
+    ow.take_error()?;
+
+    if persist {
+        if !ow.reset() {
+            return Err(TempError::NoDevice);
+        }
+        ow.select(rom);
+        ow.write_power_command(COPY_SCRATCHPAD);
+        delay.delay_ms(COPY_SCRATCHPAD_MS);
+        ow.end_strong_pullup();
+        ow.take_error()?;
+    }
+    Ok(())
+}
+
+/// 上传 JSON 里的字段名，按 ROM 码区分每个探头
+pub const METRIC: &str = "temps";
+
+/// 读温度的统计，从上电 (或上次清零) 开始累计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// 成功的读数
+    pub readings: u32,
+    /// 没有存在脉冲或读回全 1 (探头掉线、DQ 接触不良)
+    pub no_device: u32,
+    /// 读回全 0 (DQ 被拉低)
+    pub bus_stuck_low: u32,
+    pub crc_errors: u32,
+    /// 读到 85 °C 上电值 (供电不足)
+    pub power_on_values: u32,
+    /// 总线驱动出错
+    pub bus_faults: u32,
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Self {
+            readings: 0,
+            no_device: 0,
+            bus_stuck_low: 0,
+            crc_errors: 0,
+            power_on_values: 0,
+            bus_faults: 0,
+        }
+    }
+
+    /// 记一次读温度的结果
+    pub fn record(&mut self, result: &Result<Reading, TempError>) {
+        let counter = match result {
+            Ok(_) => &mut self.readings,
+            Err(TempError::NoDevice) => &mut self.no_device,
+            Err(TempError::BusStuckLow) => &mut self.bus_stuck_low,
+            Err(TempError::CrcMismatch { .. }) => &mut self.crc_errors,
+            Err(TempError::PowerOnValue) => &mut self.power_on_values,
+            Err(TempError::BusFault) => &mut self.bus_faults,
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    /// 失败的次数
+    pub fn errors(&self) -> u32 {
+        self.no_device
+            .saturating_add(self.bus_stuck_low)
+            .saturating_add(self.crc_errors)
+            .saturating_add(self.power_on_values)
+            .saturating_add(self.bus_faults)
+    }
+
+    /// 上传用的计数器名和值
+    pub fn counters(&self) -> [(&'static str, u32); 6] {
+        [
+            ("readings", self.readings),
+            ("no_device", self.no_device),
+            ("bus_stuck_low", self.bus_stuck_low),
+            ("crc_errors", self.crc_errors),
+            ("power_on_values", self.power_on_values),
+            ("bus_faults", self.bus_faults),
+        ]
+    }
+}
+
+/// 默认每隔这么多次采样重新搜索一次总线 (30 s 采样一次时是 1 小时)
+pub const DEFAULT_SEARCH_INTERVAL: u32 = 120;
+// 某个探头连续读取失败这么多次就提前重新搜索
+const MAX_MISSES: u8 = 3;
+
+/// 总线上所有 DS18B20 作为一个 `Sensor`
+///
+/// 每次采样用 Skip ROM 让所有探头同时转换，异步等待后用 Match ROM 逐个读取。
+/// 还没找到探头时，每次采样前重新搜索；找到之后每隔 `with_search_interval` 次采样、
+/// 或者某个探头连续失败 3 次时也重新搜索，新接上的探头加进来，不再应答的探头去掉。
+/// `D` 同时提供阻塞延时 (写 EEPROM) 和异步延时 (等待转换)，例如 `embassy_time::Delay`。
+pub struct Ds18b20Sensor<B, D, const N: usize> {
+    ow: OneWire<B>,
+    delay: D,
+    roms: [Rom; N],
+    count: usize,
+    // 每个探头连续失败的次数
+    misses: [u8; N],
+    // 上次搜索以来的采样次数
+    since_search: u32,
+    search_interval: u32,
+    resolution: Resolution,
+    persist: bool,
+    poll_ms: Option<u32>,
+    stats: Stats,
+}
+
+impl<B, D, const N: usize> Ds18b20Sensor<B, D, N>
+where
+    B: OneWireBus,
+    D: DelayNs + embedded_hal_async::delay::DelayNs,
+{
+    pub fn new(ow: OneWire<B>, delay: D, resolution: Resolution) -> Self {
+        Self {
+            ow,
+            delay,
+            roms: [[0; 8]; N],
+            count: 0,
+            misses: [0; N],
+            since_search: 0,
+            search_interval: DEFAULT_SEARCH_INTERVAL,
+            resolution,
+            persist: false,
+            poll_ms: None,
+            stats: Stats::new(),
+        }
+    }
+
+    /// 把分辨率写进 EEPROM (只在与当前值不同时写，避免磨损)
+    pub fn with_persist(mut self, persist: bool) -> Self {
+        self.persist = persist;
+        self
+    }
+
+    /// 外部供电时每隔 `poll_ms` 轮询一次转换是否完成，而不是固定等满 tCONV
+    pub fn with_polling(mut self, poll_ms: u32) -> Self {
+        self.poll_ms = Some(poll_ms);
+        self
+    }
+
+    /// 每隔 `samples` 次采样重新搜索一次总线，发现后来接上的探头
+    pub fn with_search_interval(mut self, samples: u32) -> Self {
+        self.search_interval = samples;
+        self
+    }
+
+    /// 枚举总线上的探头，检测寄生供电并设置分辨率，返回找到的数量
+    ///
+    /// 搜索结果替换原来的列表：这次没找到的探头不再读取。
+    pub fn discover(&mut self) -> usize {
+        self.count = self.ow.search(&mut self.roms);
+        self.misses = [0; N];
+        self.since_search = 0;
+        self.ow.detect_parasite_power();
+        for i in 0..self.count {
+            let rom = self.roms[i];
+            // 失败的探头读温度时同样会失败，在那里体现为无效值
+            let _ = set_resolution(
+                &mut self.ow,
+                &rom,
+                self.resolution,
+                self.persist,
+                &mut self.delay,
+            );
+        }
+        self.count
+    }
+
+    /// 当前已知的探头
+    pub fn roms(&self) -> &[Rom] {
+        &self.roms[..self.count]
+    }
+
+    pub fn is_parasite(&self) -> bool {
+        self.ow.is_parasite()
+    }
+
+    /// 读温度的统计 (没找到探头时每次采样记一次 `no_device`)
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::new();
+    }
+
+    // 等待转换完成，等待期间不占用 CPU
+    async fn wait_for_conversion(&mut self) {
+        let max_ms = self.resolution.conversion_time_ms();
+        match self.poll_ms {
+            // 寄生供电时总线一直被强上拉，不能轮询读时隙，只能等满 tCONV
+            Some(poll_ms) if !self.ow.is_parasite() => {
+                // 转换进行中读时隙返回 0，全部探头完成后返回 1；最多等到 tCONV
+                let mut waited_ms = 0;
+                while waited_ms < max_ms {
+                    embedded_hal_async::delay::DelayNs::delay_ms(&mut self.delay, poll_ms).await;
+                    waited_ms += poll_ms;
+                    let done = self.ow.read_bit();
+                    // 读时隙出错时不能当作已完成，继续等到 tCONV
+                    if self.ow.take_error().is_ok() && done {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                embedded_hal_async::delay::DelayNs::delay_ms(&mut self.delay, max_ms).await;
+                self.ow.end_strong_pullup();
+            }
+        }
+    }
+}
+
+impl<B, D, const N: usize> Sensor for Ds18b20Sensor<B, D, N>
+where
+    B: OneWireBus,
+    D: DelayNs + embedded_hal_async::delay::DelayNs,
+{
+    fn name(&self) -> &'static str {
+        "DS18B20"
+    }
+
+    async fn sample(&mut self) -> Vec<Measurement> {
+        let search_due = self.count == 0
+            || self.since_search >= self.search_interval
+            || self.misses[..self.count].iter().any(|&m| m >= MAX_MISSES);
+        if search_due && self.discover() == 0 {
+            self.stats.record(&Err(TempError::NoDevice));
+            return Vec::new();
+        }
+        self.since_search = self.since_search.saturating_add(1);
+        let converted = start_conversion(&mut self.ow);
+        if converted.is_ok() {
+            self.wait_for_conversion().await;
+        }
+
+        let mut out = Vec::with_capacity(self.count);
+        for i in 0..self.count {
+            let rom = self.roms[i];
+            let reading = converted.and_then(|_| read_temperature(&mut self.ow, &rom));
+            self.stats.record(&reading);
+            self.misses[i] = match reading {
+                Ok(_) => 0,
+                Err(_) => self.misses[i].saturating_add(1),
+            };
+            let m = match reading {
+                Ok(reading) => Measurement::new(METRIC, reading.celsius(), Unit::Celsius),
+                Err(_) => Measurement::invalid(METRIC, Unit::Celsius),
+            };
+            out.push(m.with_channel(rom));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::onewire::sim::{self, SimDevice};
+    use crate::sensor::Quality;
+    use embassy_futures::block_on;
+
+    fn rom(serial: u8) -> Rom {
+        let mut rom = [FAMILY_CODE, serial, 0x64, 0x1E, 0, 0, 0, 0];
+        rom[7] = crc8(&rom[..7]);
+        rom
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    impl embedded_hal_async::delay::DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    // 数据手册 Table 1：温度与数据的对应关系 (12 位)
+    const REFERENCE: [(u16, f32); 10] = [
+        (0x07D0, 125.0),
+        (0x0550, 85.0),
+        (0x0191, 25.0625),
+        (0x00A2, 10.125),
+        (0x0008, 0.5),
+        (0x0000, 0.0),
+        (0xFFF8, -0.5),
+        (0xFF5E, -10.125),
+        (0xFE6F, -25.0625),
+        (0xFC90, -55.0),
+    ];
+
+    #[test]
+    fn decodes_datasheet_table() {
+        for (raw, expected) in REFERENCE {
+            assert_eq!(
+                decode_temperature(raw, Resolution::Bits12),
+                expected,
+                "raw=0x{raw:04X}"
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_undefined_low_bits() {
+        // 9 位分辨率下 bit2..0 未定义，只保留 0.5 °C 步进
+        assert_eq!(decode_temperature(0x0197, Resolution::Bits9), 25.0);
+        assert_eq!(decode_temperature(0xFE6F, Resolution::Bits9), -25.5);
+        assert_eq!(decode_temperature(0x0193, Resolution::Bits10), 25.0);
+        assert_eq!(decode_temperature(0x0193, Resolution::Bits11), 25.125);
+    }
+
+    #[test]
+    fn resolution_from_config_register() {
+        assert_eq!(Resolution::from_config(0x1F), Resolution::Bits9);
+        assert_eq!(Resolution::from_config(0x3F), Resolution::Bits10);
+        assert_eq!(Resolution::from_config(0x5F), Resolution::Bits11);
+        assert_eq!(Resolution::from_config(0x7F), Resolution::Bits12);
+    }
+
+    #[test]
+    fn config_round_trips_resolution() {
+        for resolution in [
+            Resolution::Bits9,
+            Resolution::Bits10,
+            Resolution::Bits11,
+            Resolution::Bits12,
+        ] {
+            assert_eq!(Resolution::from_config(resolution.config()), resolution);
+        }
+        assert_eq!(Resolution::Bits12.config(), 0x7F);
+    }
+
+    #[test]
+    fn scratchpad_rejects_power_on_value() {
+        let scratchpad = [0x50, 0x05, 0x4B, 0x46, 0x7F, 0xFF, 0x0C, 0x10, 0x1C];
+        assert_eq!(check_scratchpad(&scratchpad), Err(TempError::PowerOnValue));
+    }
+
+    #[test]
+    fn scratchpad_rejects_stuck_bus() {
+        // 全 0 的 CRC 也是 0，不拦下来会被当成 0.00 °C
+        assert_eq!(crc8(&[0; 8]), 0);
+        assert_eq!(
+            check_scratchpad(&[0x00; SCRATCHPAD_LEN]),
+            Err(TempError::BusStuckLow)
+        );
+        assert_eq!(
+            check_scratchpad(&[0xFF; SCRATCHPAD_LEN]),
+            Err(TempError::NoDevice)
+        );
+    }
+
+    #[test]
+    fn reads_each_sensor_after_conversion() {
+        let mut a = SimDevice::new(rom(1));
+        a.temperature_raw = 0x0191;
+        let mut b = SimDevice::new(rom(2));
+        b.temperature_raw = 0xFE6F;
+        let (bus, _) = sim::bus(vec![a, b]);
+        let mut ow = OneWire::new(bus);
+
+        // 还没转换过，读到的是上电值
+        assert_eq!(
+            read_temperature(&mut ow, &rom(1)),
+            Err(TempError::PowerOnValue)
+        );
+
+        start_conversion(&mut ow).unwrap();
+        assert_eq!(
+            read_temperature(&mut ow, &rom(1)).unwrap().celsius(),
+            25.0625
+        );
+        assert_eq!(
+            read_temperature(&mut ow, &rom(2)).unwrap().celsius(),
+            -25.0625
+        );
+    }
+
+    #[test]
+    fn reports_crc_mismatch_and_missing_device() {
+        let mut device = SimDevice::new(rom(1));
+        device.corrupt_bit = Some(3);
+        let (bus, _) = sim::bus(vec![device]);
+        let mut ow = OneWire::new(bus);
+        start_conversion(&mut ow).unwrap();
+        assert!(matches!(
+            read_temperature(&mut ow, &rom(1)),
+            Err(TempError::CrcMismatch { .. })
+        ));
+
+        // 总线上有别的器件，但没有人响应这个 ROM 码：读回全 1
+        assert_eq!(read_temperature(&mut ow, &rom(9)), Err(TempError::NoDevice));
+
+        let (bus, _) = sim::bus(vec![]);
+        let mut ow = OneWire::new(bus);
+        assert_eq!(start_conversion(&mut ow), Err(TempError::NoDevice));
+    }
+
+    // 时隙照常转给模拟总线，但每次传输都报告硬件错误 (模拟 RMT 出错)
+    struct FaultyBus<B>(B);
+
+    impl<B: OneWireBus> OneWireBus for FaultyBus<B> {
+        fn reset(&mut self) -> bool {
+            self.0.reset()
+        }
+
+        fn write_bit(&mut self, bit: bool) {
+            self.0.write_bit(bit)
+        }
+
+        fn read_bit(&mut self) -> bool {
+            self.0.read_bit()
+        }
+
+        fn write_byte_strong_pullup(&mut self, byte: u8) {
+            self.0.write_byte_strong_pullup(byte)
+        }
+
+        fn end_strong_pullup(&mut self) {
+            self.0.end_strong_pullup()
+        }
+
+        fn take_error(&mut self) -> Result<(), BusError> {
+            Err(BusError)
+        }
+    }
+
+    #[test]
+    fn reports_bus_fault() {
+        let (bus, _) = sim::bus(vec![SimDevice::new(rom(1))]);
+        let mut ow = OneWire::new(FaultyBus(bus));
+        // 数据本身没问题，但驱动报告出错，读数不能用
+        assert_eq!(start_conversion(&mut ow), Err(TempError::BusFault));
+        assert_eq!(read_temperature(&mut ow, &rom(1)), Err(TempError::BusFault));
+        let mut roms = [[0u8; 8]; 2];
+        assert_eq!(ow.search(&mut roms), 0);
+    }
+
+    #[test]
+    fn set_resolution_keeps_alarm_registers() {
+        let (bus, wire) = sim::bus(vec![SimDevice::new(rom(1))]);
+        let mut ow = OneWire::new(bus);
+
+        set_resolution(&mut ow, &rom(1), Resolution::Bits9, false, &mut NoDelay).unwrap();
+        start_conversion(&mut ow).unwrap();
+        let reading = read_temperature(&mut ow, &rom(1)).unwrap();
+        assert_eq!(reading.resolution, Resolution::Bits9);
+        assert_eq!(reading.celsius(), 25.0);
+
+        // 没有写 EEPROM，重新上电后恢复 12 位
+        let device = &mut wire.borrow_mut().devices[0];
+        assert_eq!(&device.scratchpad[2..5], &[0x4B, 0x46, 0x1F]);
+        device.power_cycle();
+        assert_eq!(device.scratchpad[4], 0x7F);
+    }
+
+    #[test]
+    fn set_resolution_persists_to_eeprom() {
+        let (bus, wire) = sim::bus(vec![SimDevice::new(rom(1))]);
+        let mut ow = OneWire::new(bus);
+        set_resolution(&mut ow, &rom(1), Resolution::Bits10, true, &mut NoDelay).unwrap();
+
+        let device = &mut wire.borrow_mut().devices[0];
+        device.power_cycle();
+        assert_eq!(
+            Resolution::from_config(device.scratchpad[4]),
+            Resolution::Bits10
+        );
+    }
+
+    #[test]
+    fn finds_added_probes_and_drops_missing_ones() {
+        let (bus, wire) = sim::bus(vec![SimDevice::new(rom(1))]);
+        let mut sensor: Ds18b20Sensor<_, _, 4> =
+            Ds18b20Sensor::new(OneWire::new(bus), NoDelay, Resolution::Bits12)
+                .with_search_interval(2);
+        let channels = |sensor: &mut Ds18b20Sensor<_, _, 4>| -> Vec<Option<Rom>> {
+            block_on(sensor.sample())
+                .iter()
+                .map(|m| m.channel)
+                .collect()
+        };
+        assert_eq!(channels(&mut sensor), [Some(rom(1))]);
+
+        // 后来接上的探头在下一次定期搜索时加进来 (按搜索顺序排列)
+        wire.borrow_mut().devices.push(SimDevice::new(rom(2)));
+        assert_eq!(channels(&mut sensor), [Some(rom(1))]);
+        assert_eq!(channels(&mut sensor), [Some(rom(2)), Some(rom(1))]);
+
+        // 拔掉的探头连续失败 3 次后重新搜索，从列表里去掉
+        wire.borrow_mut().devices.remove(0);
+        let mut sensor = sensor.with_search_interval(u32::MAX);
+        for _ in 0..MAX_MISSES {
+            assert_eq!(channels(&mut sensor), [Some(rom(2)), Some(rom(1))]);
+        }
+        assert_eq!(channels(&mut sensor), [Some(rom(2))]);
+        assert_eq!(sensor.stats().no_device, MAX_MISSES as u32);
+    }
+
+    #[test]
+    fn parasite_conversion_needs_strong_pullup() {
+        let mut device = SimDevice::new(rom(1));
+        device.parasite = true;
+        let (bus, wire) = sim::bus(vec![device]);
+        let mut ow = OneWire::new(bus);
+
+        // 没有检测寄生供电就转换：器件得不到电流，温度仍是上电值
+        start_conversion(&mut ow).unwrap();
+        assert_eq!(
+            read_temperature(&mut ow, &rom(1)),
+            Err(TempError::PowerOnValue)
+        );
+
+        assert!(ow.detect_parasite_power());
+        start_conversion(&mut ow).unwrap();
+        ow.end_strong_pullup();
+        assert_eq!(
+            read_temperature(&mut ow, &rom(1)).unwrap().celsius(),
+            25.0625
+        );
+        assert_eq!(wire.borrow().pullup_violations, 0);
+    }
+
+    #[test]
+    fn sensor_discovers_and_reports_every_probe() {
+        let mut a = SimDevice::new(rom(1));
+        a.busy_slots = 3;
+        let mut b = SimDevice::new(rom(2));
+        b.temperature_raw = 0xFF5E;
+        b.parasite = true;
+        let (bus, wire) = sim::bus(vec![a, b]);
+        let mut sensor: Ds18b20Sensor<_, _, 4> =
+            Ds18b20Sensor::new(OneWire::new(bus), NoDelay, Resolution::Bits11).with_polling(10);
+
+        let out = embassy_futures::block_on(sensor.sample());
+        assert_eq!(sensor.roms().len(), 2);
+        assert!(sensor.is_parasite());
+        let value = |out: &[Measurement], r: Rom| {
+            let m = out.iter().find(|m| m.channel == Some(r)).unwrap();
+            (m.value, m.quality)
+        };
+        assert_eq!(value(&out, rom(1)).0, 25.0);
+        assert_eq!(value(&out, rom(2)).0, -10.125);
+
+        // 探头掉线：仍然上报该通道，但标记为无效
+        wire.borrow_mut().devices.remove(1);
+        let out = embassy_futures::block_on(sensor.sample());
+        assert_eq!(out.len(), 2);
+        assert_eq!(value(&out, rom(1)).1, Quality::Good);
+        assert_eq!(value(&out, rom(2)).1, Quality::Invalid);
+        assert_eq!((sensor.stats().readings, sensor.stats().no_device), (3, 1));
+    }
+
+    #[test]
+    fn sensor_counts_errors() {
+        let mut device = SimDevice::new(rom(1));
+        device.corrupt_bit = Some(3);
+        let (bus, _) = sim::bus(vec![device]);
+        let mut sensor: Ds18b20Sensor<_, _, 4> =
+            Ds18b20Sensor::new(OneWire::new(bus), NoDelay, Resolution::Bits12);
+        embassy_futures::block_on(sensor.sample());
+        assert_eq!(sensor.stats().crc_errors, 1);
+        sensor.reset_stats();
+        assert_eq!(sensor.stats(), Stats::new());
+
+        // 总线上一个探头都没有：每次采样记一次 no_device
+        let mut sensor: Ds18b20Sensor<_, _, 4> = Ds18b20Sensor::new(
+            OneWire::new(sim::bus(vec![]).0),
+            NoDelay,
+            Resolution::Bits12,
+        );
+        assert!(embassy_futures::block_on(sensor.sample()).is_empty());
+        assert!(embassy_futures::block_on(sensor.sample()).is_empty());
+        assert_eq!(sensor.stats().no_device, 2);
+        assert_eq!(sensor.stats().errors(), 2);
+    }
+}
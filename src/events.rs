@@ -0,0 +1,67 @@
+//! 等待随下一次上传发出的事件 (开关灯、停电/来电等)
+//!
+//! 事件放进上传队列后清掉；上传一直失败时只保留最近的 `N` 条，满了丢掉最早的。
+
+use alloc::{string::String, vec::Vec};
+
+/// 最多保留 `N` 条的事件记录，按发生的先后排列
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventLog<T, const N: usize> {
+    events: Vec<T>,
+}
+
+impl<T, const N: usize> EventLog<T, N> {
+    pub const fn new() -> Self {
+        Self { events: Vec::new() }
+    }
+
+    /// 记一条事件，满了丢掉最早的一条
+    pub fn push(&mut self, event: T) {
+        if self.events.len() == N {
+            self.events.remove(0);
+        }
+        self.events.push(event);
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.events
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// 上传用的 JSON 数组，每条事件由 `object` 转成一个 JSON 对象
+    pub fn json_array(&self, object: impl Fn(&T) -> String) -> String {
+        let objects: Vec<_> = self.events.iter().map(object).collect();
+        alloc::format!("[{}]", objects.join(","))
+    }
+}
+
+impl<T, const N: usize> Default for EventLog<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn keeps_latest_events() {
+        let mut log: EventLog<u32, 3> = EventLog::new();
+        assert_eq!(log.json_array(|e| format!("{e}")), "[]");
+        for e in 1..=5 {
+            log.push(e);
+        }
+        assert_eq!(log.as_slice(), [3, 4, 5]);
+        assert_eq!(
+            log.json_array(|e| format!("{{\"n\":{e}}}")),
+            "[{\"n\":3},{\"n\":4},{\"n\":5}]"
+        );
+        log.clear();
+        assert!(log.as_slice().is_empty());
+    }
+}
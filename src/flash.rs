@@ -0,0 +1,336 @@
+//! 片上 SPI Flash 的数据分区
+//!
+//! 分区表见 `partitions.csv`，烧录时 espflash 一起写入。`Region` 把整片 Flash 上的一段
+//! 当作独立的 `NorFlash` 使用，偏移量从分区起点算起，越界的读写直接报错，不会碰到程序区。
+//! 固件里通过 `SharedFlash` (esp-storage) 访问整片 Flash，几个分区共用。
+//!
+//! 只保存一条记录的分区 (校准参数等) 用 `load_latest`/`save_next`：分区开头两个扇区轮流写，
+//! 擦除或写入中途断电时另一个扇区里上一次保存的记录还在。
+
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// 整片 Flash 上的一段
+pub struct Region<F> {
+    flash: F,
+    offset: u32,
+    len: u32,
+}
+
+impl<F: NorFlash> Region<F> {
+    /// `offset`、`len` 按擦除扇区对齐 (分区表里的数据分区都是 4 KiB 对齐)
+    pub fn new(flash: F, offset: u32, len: u32) -> Self {
+        Self { flash, offset, len }
+    }
+
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+}
+
+impl<F: ErrorType> ErrorType for Region<F> {
+    type Error = NorFlashErrorKind;
+}
+
+impl<F: NorFlash> ReadNorFlash for Region<F> {
+    const READ_SIZE: usize = F::READ_SIZE;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        self.flash
+            .read(self.offset + offset, bytes)
+            .map_err(|_| NorFlashErrorKind::Other)
+    }
+
+    fn capacity(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl<F: NorFlash> NorFlash for Region<F> {
+    const WRITE_SIZE: usize = F::WRITE_SIZE;
+    const ERASE_SIZE: usize = F::ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        self.flash
+            .erase(self.offset + from, self.offset + to)
+            .map_err(|_| NorFlashErrorKind::Other)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        self.flash
+            .write(self.offset + offset, bytes)
+            .map_err(|_| NorFlashErrorKind::Other)
+    }
+}
+
+// A/B 两个扇区里，记录后面紧跟 4 字节序号 (小端)，写完记录才写序号。序号还是擦除后的
+// 0xFFFFFFFF 时按 0 算：要么是写到一半断电 (记录本身由调用方的 CRC 把关，另一个扇区的序号
+// 更大)，要么是只有一个扇区时的旧格式。
+const SLOTS: u32 = 2;
+
+fn read_slot<F: NorFlash, T, const N: usize>(
+    flash: &mut F,
+    slot: u32,
+    decode: &impl Fn(&[u8; N]) -> Option<T>,
+) -> Result<Option<(T, u32)>, F::Error> {
+    let at = slot * F::ERASE_SIZE as u32;
+    let mut record = [0u8; N];
+    let mut seq = [0u8; 4];
+    flash.read(at, &mut record)?;
+    flash.read(at + N as u32, &mut seq)?;
+    let seq = match u32::from_le_bytes(seq) {
+        u32::MAX => 0,
+        seq => seq,
+    };
+    Ok(decode(&record).map(|value| (value, seq)))
+}
+
+// 有效记录里序号最大的一个：(记录, 序号, 所在扇区)
+fn latest<F: NorFlash, T, const N: usize>(
+    flash: &mut F,
+    decode: &impl Fn(&[u8; N]) -> Option<T>,
+) -> Result<Option<(T, u32, u32)>, F::Error> {
+    let mut best: Option<(T, u32, u32)> = None;
+    for slot in 0..SLOTS {
+        if let Some((value, seq)) = read_slot(flash, slot, decode)? {
+            if best.as_ref().is_none_or(|&(_, best_seq, _)| seq > best_seq) {
+                best = Some((value, seq, slot));
+            }
+        }
+    }
+    Ok(best)
+}
+
+/// 读出两个扇区里最新的有效记录，`decode` 校验并解码 (两边都无效时返回 `None`)
+pub fn load_latest<F: NorFlash, T, const N: usize>(
+    flash: &mut F,
+    decode: impl Fn(&[u8; N]) -> Option<T>,
+) -> Result<Option<T>, F::Error> {
+    Ok(latest(flash, &decode)?.map(|(value, _, _)| value))
+}
+
+/// 把记录写进不是最新的那个扇区：先擦除、写记录，最后写上更大的序号
+///
+/// `N` 要是 `WRITE_SIZE` 的倍数，分区至少两个扇区。
+pub fn save_next<F: NorFlash, T, const N: usize>(
+    flash: &mut F,
+    record: &[u8; N],
+    decode: impl Fn(&[u8; N]) -> Option<T>,
+) -> Result<(), F::Error> {
+    let (slot, seq) = match latest(flash, &decode)? {
+        Some((_, seq, slot)) => ((slot + 1) % SLOTS, seq + 1),
+        None => (0, 1),
+    };
+    let at = slot * F::ERASE_SIZE as u32;
+    flash.erase(at, at + F::ERASE_SIZE as u32)?;
+    flash.write(at, record)?;
+    flash.write(at + N as u32, &seq.to_le_bytes())
+}
+
+#[cfg(target_arch = "riscv32")]
+mod esp {
+    use core::cell::RefCell;
+    use critical_section::Mutex;
+    use embedded_storage::nor_flash::{
+        ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+    };
+    use esp_bootloader_esp_idf::partitions::{self, PARTITION_TABLE_MAX_LEN};
+    use esp_hal::peripherals::FLASH;
+    use esp_storage::FlashStorage;
+
+    use super::Region;
+
+    static STORAGE: Mutex<RefCell<Option<FlashStorage<'static>>>> = Mutex::new(RefCell::new(None));
+
+    /// 整片 Flash，读写交给 esp-storage (擦写期间关闭 cache、ROM 函数从 RAM 调用都由它处理)
+    ///
+    /// 校准参数和积压队列各拿一个 `SharedFlash`，底下是同一个 `FlashStorage`：每次读写时把它
+    /// 从锁里取出来，用完放回去，擦除扇区的几十毫秒里不占着这把锁。
+    #[derive(Clone, Copy)]
+    pub struct SharedFlash {
+        capacity: usize,
+    }
+
+    impl SharedFlash {
+        /// 接管 Flash 外设，整个程序只调用一次
+        pub fn new(flash: FLASH<'static>) -> Self {
+            let storage = FlashStorage::new(flash);
+            let capacity = storage.capacity();
+            critical_section::with(|cs| STORAGE.replace(cs, Some(storage)));
+            Self { capacity }
+        }
+
+        /// 按标签在分区表里查找数据分区
+        pub fn partition(self, label: &str) -> Option<Region<Self>> {
+            let (offset, len) = access(|storage| {
+                let mut buf = [0u8; PARTITION_TABLE_MAX_LEN];
+                let table = partitions::read_partition_table(storage, &mut buf).ok()?;
+                let entry = table.iter().find(|p| p.label_as_str() == label)?;
+                Some((entry.offset(), entry.len()))
+            })
+            .ok()??;
+            Some(Region::new(self, offset, len))
+        }
+    }
+
+    // 取出 FlashStorage 执行一次读写，再放回去
+    fn access<R>(f: impl FnOnce(&mut FlashStorage<'static>) -> R) -> Result<R, NorFlashErrorKind> {
+        let mut storage =
+            critical_section::with(|cs| STORAGE.take(cs)).ok_or(NorFlashErrorKind::Other)?;
+        let result = f(&mut storage);
+        critical_section::with(|cs| STORAGE.replace(cs, Some(storage)));
+        Ok(result)
+    }
+
+    impl ErrorType for SharedFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for SharedFlash {
+        const READ_SIZE: usize = FlashStorage::READ_SIZE;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            access(|storage| storage.read(offset, bytes))?.map_err(|e| e.kind())
+        }
+
+        fn capacity(&self) -> usize {
+            self.capacity
+        }
+    }
+
+    impl NorFlash for SharedFlash {
+        const WRITE_SIZE: usize = FlashStorage::WRITE_SIZE;
+        const ERASE_SIZE: usize = FlashStorage::ERASE_SIZE;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            access(|storage| storage.erase(from, to))?.map_err(|e| e.kind())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            access(|storage| storage.write(offset, bytes))?.map_err(|e| e.kind())
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+pub use esp::SharedFlash;
+
+/// 单元测试用的内存 Flash：擦除置 1，写入只能把 1 变成 0
+#[cfg(test)]
+pub(crate) mod sim {
+    use alloc::{vec, vec::Vec};
+    use embedded_storage::nor_flash::{
+        check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash,
+    };
+
+    pub struct RamFlash {
+        pub data: Vec<u8>,
+        /// 擦除过几个扇区
+        pub erases: usize,
+    }
+
+    impl RamFlash {
+        pub fn new(len: usize) -> Self {
+            Self {
+                data: vec![0xFF; len],
+                erases: 0,
+            }
+        }
+    }
+
+    impl ErrorType for RamFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 4;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            check_read(self, offset, bytes.len())?;
+            let start = offset as usize;
+            bytes.copy_from_slice(&self.data[start..start + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for RamFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 4096;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self, from, to)?;
+            self.data[from as usize..to as usize].fill(0xFF);
+            self.erases += ((to - from) as usize) / Self::ERASE_SIZE;
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_write(self, offset, bytes.len())?;
+            let start = offset as usize;
+            for (dst, src) in self.data[start..start + bytes.len()].iter_mut().zip(bytes) {
+                *dst &= src;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sim::RamFlash;
+    use super::*;
+
+    #[test]
+    fn region_stays_inside_partition() {
+        let mut region = Region::new(RamFlash::new(4 * 4096), 4096, 2 * 4096);
+        region.write(0, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(region.capacity(), 2 * 4096);
+
+        let mut buf = [0u8; 4];
+        region.read(0, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert_eq!(region.flash.data[4096..4100], [1, 2, 3, 4]);
+
+        assert_eq!(
+            region.write(2 * 4096, &[0; 4]),
+            Err(NorFlashErrorKind::OutOfBounds)
+        );
+        assert_eq!(
+            region.erase(0, 3 * 4096),
+            Err(NorFlashErrorKind::OutOfBounds)
+        );
+        region.erase(0, 4096).unwrap();
+        assert!(region.flash.data[4096..8192].iter().all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    fn alternates_between_two_sectors() {
+        // 测试用的记录：第一个字节非 0xFF 就算有效
+        let decode = |r: &[u8; 4]| (r[0] != 0xFF).then_some(r[0]);
+        let mut flash = RamFlash::new(2 * 4096);
+        assert_eq!(load_latest(&mut flash, decode), Ok(None));
+
+        save_next(&mut flash, &[1; 4], decode).unwrap();
+        save_next(&mut flash, &[2; 4], decode).unwrap();
+        save_next(&mut flash, &[3; 4], decode).unwrap();
+        assert_eq!(load_latest(&mut flash, decode), Ok(Some(3)));
+        assert_eq!((flash.data[0], flash.data[4096]), (3, 2));
+
+        // 下一次保存擦除扇区 B 后断电：扇区 A 里的记录还在
+        flash.erase(4096, 8192).unwrap();
+        assert_eq!(load_latest(&mut flash, decode), Ok(Some(3)));
+        // 记录写完、序号没写上：比扇区 A 旧
+        flash.write(4096, &[4; 4]).unwrap();
+        assert_eq!(load_latest(&mut flash, decode), Ok(Some(3)));
+        save_next(&mut flash, &[5; 4], decode).unwrap();
+        assert_eq!(load_latest(&mut flash, decode), Ok(Some(5)));
+    }
+}
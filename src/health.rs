@@ -0,0 +1,247 @@
+//! 运行状况计数器
+//!
+//! 各驱动自己统计出错次数 (`ds18b20::Stats`、`jw01::Stats`、`ndir::Stats`)，这里再加上上传的
+//! 统计，把它们拼成上传 JSON 里的 `health` 字段。计数从第一次烧录 (或服务器要求清零) 开始
+//! 累计，不随上传周期清零，部署后也能远程看出接线松动或探头老化。
+//!
+//! 驱动里的计数每次上电从 0 开始，累计值定期写进 Flash 的 `health` 分区 (`Saved`)：上电时读出来，
+//! 之后上传的是 `保存的值 + 本次上电以来的计数`。崩溃、掉电后最多丢掉最后一次保存之后的部分。
+
+use alloc::{format, string::String, vec::Vec};
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::ds18b20::crc8;
+use crate::{flash, json};
+
+/// 服务器响应里这个字段为 true 时清零所有计数器
+pub const RESET_KEY: &str = "reset_health";
+
+/// 上传的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UploadStats {
+    pub attempts: u32,
+    pub failures: u32,
+    /// 连续失败的次数，成功一次后归零
+    pub consecutive_failures: u32,
+}
+
+impl UploadStats {
+    pub const fn new() -> Self {
+        Self {
+            attempts: 0,
+            failures: 0,
+            consecutive_failures: 0,
+        }
+    }
+
+    /// 接着上次保存的计数 (连续失败次数也接上，重启前后一直连不上时照样累计)
+    pub fn from_saved(saved: &Saved) -> Self {
+        Self {
+            attempts: saved.get("upload", "attempts"),
+            failures: saved.get("upload", "failures"),
+            consecutive_failures: saved.get("upload", "consecutive_failures"),
+        }
+    }
+
+    /// 记一次上传 (连接、发送、读响应都成功才算成功)
+    pub fn record(&mut self, ok: bool) {
+        self.attempts = self.attempts.saturating_add(1);
+        if ok {
+            self.consecutive_failures = 0;
+        } else {
+            self.failures = self.failures.saturating_add(1);
+            self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        }
+    }
+
+    /// 上传用的计数器名和值
+    pub fn counters(&self) -> [(&'static str, u32); 3] {
+        [
+            ("attempts", self.attempts),
+            ("failures", self.failures),
+            ("consecutive_failures", self.consecutive_failures),
+        ]
+    }
+}
+
+/// Flash 里一条记录的长度 (按 4 字节对齐)
+pub const RECORD_LEN: usize = 1024;
+// 记录开头的标记，格式变了就当作没有保存过
+const MAGIC: [u8; 4] = *b"HLT1";
+
+/// 保存在 Flash 里的累计计数，按 `组.名称` 对应
+///
+/// 记录是标记、文本长度 (小端 u16，补齐到 4 字节)、`onewire.readings=120;co2.frames=3;...`
+/// 形式的文本、CRC8 (补齐到 4 字节)。按名称保存，升级固件增减了计数器也能对上其余的。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Saved {
+    entries: Vec<(String, u32)>,
+}
+
+impl Saved {
+    /// 没有保存过 (全部从 0 开始)
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// 保存的值，没有时是 0
+    pub fn get(&self, section: &str, name: &str) -> u32 {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.split_once('.') == Some((section, name)))
+            .map_or(0, |&(_, value)| value)
+    }
+
+    /// 本次上电以来的计数加上保存的值
+    pub fn total<'a>(&self, section: &str, counters: &[(&'a str, u32)]) -> Vec<(&'a str, u32)> {
+        counters
+            .iter()
+            .map(|&(name, value)| (name, value.saturating_add(self.get(section, name))))
+            .collect()
+    }
+
+    /// 编码成 Flash 记录，放不下时返回 `None`
+    pub fn to_bytes(sections: &[(&str, &[(&str, u32)])]) -> Option<[u8; RECORD_LEN]> {
+        let text: Vec<_> = sections
+            .iter()
+            .flat_map(|(section, counters)| {
+                counters
+                    .iter()
+                    .map(move |(name, value)| format!("{}.{}={}", section, name, value))
+            })
+            .collect();
+        let text = text.join(";");
+        if 8 + text.len() > RECORD_LEN - 4 {
+            return None;
+        }
+        let mut record = [0u8; RECORD_LEN];
+        record[..4].copy_from_slice(&MAGIC);
+        record[4..6].copy_from_slice(&(text.len() as u16).to_le_bytes());
+        record[8..8 + text.len()].copy_from_slice(text.as_bytes());
+        record[RECORD_LEN - 4] = crc8(&record[..RECORD_LEN - 4]);
+        Some(record)
+    }
+
+    /// 解码 Flash 记录，没有保存过 (全 0xFF) 或 CRC 不对时返回 `None`
+    pub fn from_bytes(record: &[u8; RECORD_LEN]) -> Option<Self> {
+        if record[..4] != MAGIC || record[RECORD_LEN - 4] != crc8(&record[..RECORD_LEN - 4]) {
+            return None;
+        }
+        let len = u16::from_le_bytes([record[4], record[5]]) as usize;
+        let text = core::str::from_utf8(record.get(8..8 + len)?).ok()?;
+        let entries = text
+            .split(';')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (key, value) = entry.split_once('=')?;
+                Some((key.into(), value.parse().ok()?))
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { entries })
+    }
+
+    /// 读出最近一次保存的计数
+    pub fn load<F: NorFlash>(flash: &mut F) -> Result<Option<Self>, F::Error> {
+        flash::load_latest(flash, Self::from_bytes)
+    }
+
+    /// 保存一条 `to_bytes` 编码好的记录 (两个扇区轮流写)
+    pub fn save<F: NorFlash>(flash: &mut F, record: &[u8; RECORD_LEN]) -> Result<(), F::Error> {
+        flash::save_next(flash, record, Self::from_bytes)
+    }
+}
+
+/// 上传 JSON 的字段 (不含外层大括号)，每组计数器一个对象：
+/// `"health":{"onewire":{"readings":120,"no_device":2},"upload":{"failures":0}}`
+pub fn json_field(sections: &[(&str, &[(&str, u32)])]) -> String {
+    let sections: Vec<_> = sections
+        .iter()
+        .map(|(name, counters)| {
+            let counters: Vec<_> = counters
+                .iter()
+                .map(|(key, value)| format!("\"{}\":{}", key, value))
+                .collect();
+            format!("\"{}\":{{{}}}", name, counters.join(","))
+        })
+        .collect();
+    format!("\"health\":{{{}}}", sections.join(","))
+}
+
+/// 服务器的响应是否要求清零计数器 (最外层的 `"reset_health": true`)
+pub fn reset_requested(response: &str) -> bool {
+    json::field(response, RESET_KEY).and_then(json::Value::as_bool) == Some(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flash::sim::RamFlash;
+
+    #[test]
+    fn counts_upload_failures() {
+        let mut uploads = UploadStats::new();
+        uploads.record(false);
+        uploads.record(false);
+        assert_eq!(uploads.consecutive_failures, 2);
+        uploads.record(true);
+        assert_eq!(
+            uploads,
+            UploadStats {
+                attempts: 3,
+                failures: 2,
+                consecutive_failures: 0
+            }
+        );
+    }
+
+    #[test]
+    fn builds_health_field() {
+        let mut uploads = UploadStats::new();
+        uploads.record(false);
+        let json = json_field(&[
+            ("onewire", &[("readings", 120), ("crc_errors", 1)]),
+            ("upload", &uploads.counters()),
+        ]);
+        assert_eq!(
+            json,
+            "\"health\":{\"onewire\":{\"readings\":120,\"crc_errors\":1},\
+             \"upload\":{\"attempts\":1,\"failures\":1,\"consecutive_failures\":1}}"
+        );
+        assert!(reset_requested("{\"ok\":true,\"reset_health\":true}"));
+        assert!(reset_requested("{\"ok\": true, \"reset_health\": true}"));
+        assert!(!reset_requested("{\"ok\":true}"));
+        assert!(!reset_requested("{\"msg\":\"\\\"reset_health\\\":true\"}"));
+    }
+
+    #[test]
+    fn persists_totals_across_reboots() {
+        let mut flash = RamFlash::new(2 * 4096);
+        assert_eq!(Saved::load(&mut flash), Ok(None));
+
+        let mut uploads = UploadStats::new();
+        uploads.record(false);
+        let record = Saved::to_bytes(&[
+            ("onewire", &[("readings", 120), ("crc_errors", 1)]),
+            ("upload", &uploads.counters()),
+        ])
+        .unwrap();
+        Saved::save(&mut flash, &record).unwrap();
+
+        // 重启后驱动的计数从 0 开始，上传的是加上保存值的累计
+        let saved = Saved::load(&mut flash).unwrap().unwrap();
+        assert_eq!(
+            saved.total(
+                "onewire",
+                &[("readings", 3), ("crc_errors", 0), ("no_device", 2)]
+            ),
+            [("readings", 123), ("crc_errors", 1), ("no_device", 2)]
+        );
+        assert_eq!(UploadStats::from_saved(&saved), uploads);
+
+        // 清零时保存一条空记录
+        Saved::save(&mut flash, &Saved::to_bytes(&[]).unwrap()).unwrap();
+        assert_eq!(Saved::load(&mut flash), Ok(Some(Saved::new())));
+    }
+}
@@ -0,0 +1,654 @@
+//! 最小的 HTTP/1.1 客户端
+//!
+//! 上传只需要 POST 一段 JSON、看服务器回了什么，不值得引入完整的 HTTP 库。`Client`
+//! 在一条已经建立好的连接 (实现了异步读写的 TcpSocket 等) 上发请求，解析状态行和
+//! 响应头，按 `Content-Length` 或 `chunked` 读完响应体：
+//!
+//! - 非 2xx 的状态码算失败 (`HttpError::Status`)
+//! - 从发请求到读完响应有总的超时，超时或出错后连接状态未知，不再复用
+//! - 服务器回复 `Connection: close` (或是 HTTP/1.0) 时读完这次响应就不能再用，
+//!   `is_open()` 返回 false，调用方重新连接后调用 `reconnected()`
+
+use alloc::{format, string::String, vec::Vec};
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+/// 响应头加响应体的上限，超过就放弃 (服务器的回复一般只有几十字节)
+pub const MAX_RESPONSE_LEN: usize = 4096;
+/// 默认超时：从发出请求到读完响应
+pub const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+
+// 每次从连接里最多读这么多
+const READ_CHUNK: usize = 512;
+
+/// 一次请求失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpError<E> {
+    /// 连接读写出错
+    Io(E),
+    Timeout,
+    /// 响应没读完连接就断了，或者在已经关闭的连接上发请求
+    Closed,
+    /// 状态行、响应头或 chunk 的格式不对
+    Malformed,
+    /// 响应超过 `MAX_RESPONSE_LEN`
+    TooLarge,
+    /// 服务器回了非 2xx 的状态码
+    Status(u16),
+}
+
+/// 服务器的响应 (只有 2xx 才会返回)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// 按名称取响应头 (不区分大小写)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 响应体按 UTF-8 解码，不是文本时返回 `None`
+    pub fn body_str(&self) -> Option<&str> {
+        core::str::from_utf8(&self.body).ok()
+    }
+}
+
+// 响应体的长度怎么确定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// 204/304 等没有响应体
+    Empty,
+    Length(usize),
+    Chunked,
+    /// 没有长度信息，读到服务器关闭连接为止
+    UntilClose,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Head {
+    status: u16,
+    headers: Vec<(String, String)>,
+    keep_alive: bool,
+    framing: Framing,
+}
+
+// 解析状态行和响应头 (不含结尾的空行)
+fn parse_head(head: &str) -> Option<Head> {
+    let mut lines = head.split("\r\n");
+    let mut status_line = lines.next()?.splitn(3, ' ');
+    let version = status_line.next()?;
+    let minor = version.strip_prefix("HTTP/1.")?;
+    let status: u16 = status_line.next()?.parse().ok()?;
+    if !(100..600).contains(&status) {
+        return None;
+    }
+
+    let mut headers = Vec::new();
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        headers.push((String::from(name.trim()), String::from(value.trim())));
+    }
+    let value = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _): &&(String, String)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    };
+
+    // HTTP/1.1 默认保持连接，HTTP/1.0 默认关闭
+    let mut keep_alive = match value("connection") {
+        Some(c) if c.eq_ignore_ascii_case("close") => false,
+        Some(c) if c.eq_ignore_ascii_case("keep-alive") => true,
+        _ => minor != "0",
+    };
+    let chunked =
+        value("transfer-encoding").is_some_and(|te| te.to_ascii_lowercase().contains("chunked"));
+    let framing = if (100..200).contains(&status) || status == 204 || status == 304 {
+        Framing::Empty
+    } else if chunked {
+        Framing::Chunked
+    } else if let Some(len) = value("content-length") {
+        Framing::Length(len.parse().ok()?)
+    } else {
+        keep_alive = false;
+        Framing::UntilClose
+    };
+    Some(Head {
+        status,
+        headers,
+        keep_alive,
+        framing,
+    })
+}
+
+/// 服务器地址：`http(s)://主机名或 IP[:端口][/路径前缀]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    /// `https://`：连接上要先做 TLS 握手
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    /// 路径前缀，不以 `/` 结尾 (没有时为空)
+    pub base_path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Option<Self> {
+        let (tls, rest) = match url.strip_prefix("https://") {
+            Some(rest) => (true, rest),
+            None => (false, url.strip_prefix("http://")?),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, if tls { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(Self {
+            tls,
+            host: String::from(host),
+            port,
+            base_path: String::from(path.trim_end_matches('/')),
+        })
+    }
+
+    /// `Host` 请求头：默认端口 (http 80、https 443) 时省略端口号
+    pub fn authority(&self) -> String {
+        let default_port = if self.tls { 443 } else { 80 };
+        if self.port == default_port {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// 接口的完整路径，例如 `/api` + `/upload`
+    pub fn path(&self, path: &str) -> String {
+        format!("{}{}", self.base_path, path)
+    }
+}
+
+/// 在一条连接上收发 HTTP 请求
+pub struct Client<T, D> {
+    conn: T,
+    delay: D,
+    host: String,
+    timeout_ms: u32,
+    keep_alive: bool,
+    open: bool,
+}
+
+impl<T: Read + Write, D: DelayNs> Client<T, D> {
+    /// `conn` 是已经连上服务器的连接，`host` 用作 `Host` 请求头 (例如 `example.com:5005`)
+    pub fn new(conn: T, delay: D, host: &str) -> Self {
+        Self {
+            conn,
+            delay,
+            host: String::from(host),
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+            keep_alive: true,
+            open: true,
+        }
+    }
+
+    pub fn with_timeout_ms(mut self, timeout_ms: u32) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// false：每个请求都带 `Connection: close`，服务器回复后关闭连接
+    pub fn with_keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// 连接能否继续发下一个请求
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// 底层连接，用来关闭或重新连接
+    pub fn connection(&mut self) -> &mut T {
+        &mut self.conn
+    }
+
+    /// 调用方重新建立连接之后调用
+    pub fn reconnected(&mut self) {
+        self.open = true;
+    }
+
+    pub fn into_inner(self) -> T {
+        self.conn
+    }
+
+    /// POST 一段 JSON
+    pub async fn post_json(
+        &mut self,
+        path: &str,
+        json: &str,
+    ) -> Result<Response, HttpError<T::Error>> {
+        self.request(
+            "POST",
+            path,
+            &[("Content-Type", "application/json")],
+            json.as_bytes(),
+        )
+        .await
+    }
+
+    /// 发一个请求并读完响应；`Host`、`Content-Length` 和 `Connection` 由这里加上
+    pub async fn request(
+        &mut self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response, HttpError<T::Error>> {
+        if !self.open {
+            return Err(HttpError::Closed);
+        }
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n",
+            method,
+            path,
+            self.host,
+            body.len()
+        );
+        if !self.keep_alive {
+            head.push_str("Connection: close\r\n");
+        }
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+
+        // 出错或超时后连接里可能还留着半个响应，不能再用
+        self.open = false;
+        let result = match select(
+            exchange(&mut self.conn, head.as_bytes(), body),
+            self.delay.delay_ms(self.timeout_ms),
+        )
+        .await
+        {
+            Either::First(result) => result,
+            Either::Second(()) => return Err(HttpError::Timeout),
+        };
+        let (head, body) = result?;
+        self.open = head.keep_alive && self.keep_alive;
+        if !(200..300).contains(&head.status) {
+            return Err(HttpError::Status(head.status));
+        }
+        Ok(Response {
+            status: head.status,
+            headers: head.headers,
+            body,
+        })
+    }
+}
+
+// 发出请求，读完响应头和响应体
+async fn exchange<T: Read + Write>(
+    conn: &mut T,
+    head: &[u8],
+    body: &[u8],
+) -> Result<(Head, Vec<u8>), HttpError<T::Error>> {
+    conn.write_all(head).await.map_err(HttpError::Io)?;
+    conn.write_all(body).await.map_err(HttpError::Io)?;
+    conn.flush().await.map_err(HttpError::Io)?;
+
+    let mut buf = Vec::new();
+    let mut start = 0;
+    // 跳过 100 Continue 之类的临时响应
+    let head = loop {
+        let end = read_until(conn, &mut buf, start, b"\r\n\r\n").await?;
+        let head = core::str::from_utf8(&buf[start..end])
+            .ok()
+            .and_then(parse_head)
+            .ok_or(HttpError::Malformed)?;
+        start = end + 4;
+        if !(100..200).contains(&head.status) {
+            break head;
+        }
+    };
+
+    let body = match head.framing {
+        Framing::Empty => Vec::new(),
+        Framing::Length(len) => {
+            if start + len > MAX_RESPONSE_LEN {
+                return Err(HttpError::TooLarge);
+            }
+            read_to(conn, &mut buf, start + len).await?;
+            buf[start..start + len].to_vec()
+        }
+        Framing::Chunked => read_chunked(conn, &mut buf, start).await?,
+        Framing::UntilClose => {
+            while read_more(conn, &mut buf).await? > 0 {}
+            buf[start..].to_vec()
+        }
+    };
+    Ok((head, body))
+}
+
+// 从连接里再读一些追加到 buf 后面，返回读到的字节数 (0 表示对方关闭了连接)
+async fn read_more<T: Read>(conn: &mut T, buf: &mut Vec<u8>) -> Result<usize, HttpError<T::Error>> {
+    let len = buf.len();
+    if len >= MAX_RESPONSE_LEN {
+        return Err(HttpError::TooLarge);
+    }
+    buf.resize(MAX_RESPONSE_LEN.min(len + READ_CHUNK), 0);
+    let result = conn.read(&mut buf[len..]).await;
+    let n = *result.as_ref().unwrap_or(&0);
+    buf.truncate(len + n);
+    result.map_err(HttpError::Io)
+}
+
+// 读到 buf 里 from 之后出现 pattern 为止，返回 pattern 的位置
+async fn read_until<T: Read>(
+    conn: &mut T,
+    buf: &mut Vec<u8>,
+    from: usize,
+    pattern: &[u8],
+) -> Result<usize, HttpError<T::Error>> {
+    loop {
+        if let Some(i) = buf[from..]
+            .windows(pattern.len())
+            .position(|w| w == pattern)
+        {
+            return Ok(from + i);
+        }
+        if read_more(conn, buf).await? == 0 {
+            return Err(HttpError::Closed);
+        }
+    }
+}
+
+// 读到 buf 至少有 len 字节为止
+async fn read_to<T: Read>(
+    conn: &mut T,
+    buf: &mut Vec<u8>,
+    len: usize,
+) -> Result<(), HttpError<T::Error>> {
+    while buf.len() < len {
+        if read_more(conn, buf).await? == 0 {
+            return Err(HttpError::Closed);
+        }
+    }
+    Ok(())
+}
+
+// chunked 响应体：每块是 "长度(16 进制)\r\n数据\r\n"，长度为 0 的块之后是 trailer 和空行
+async fn read_chunked<T: Read>(
+    conn: &mut T,
+    buf: &mut Vec<u8>,
+    mut pos: usize,
+) -> Result<Vec<u8>, HttpError<T::Error>> {
+    let mut body = Vec::new();
+    loop {
+        let end = read_until(conn, buf, pos, b"\r\n").await?;
+        let line = core::str::from_utf8(&buf[pos..end]).map_err(|_| HttpError::Malformed)?;
+        // 忽略 chunk 扩展 (";name=value")
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| HttpError::Malformed)?;
+        pos = end + 2;
+        if size == 0 {
+            loop {
+                let end = read_until(conn, buf, pos, b"\r\n").await?;
+                let empty = end == pos;
+                pos = end + 2;
+                if empty {
+                    return Ok(body);
+                }
+            }
+        }
+        if pos + size + 2 > MAX_RESPONSE_LEN {
+            return Err(HttpError::TooLarge);
+        }
+        read_to(conn, buf, pos + size + 2).await?;
+        if &buf[pos + size..pos + size + 2] != b"\r\n" {
+            return Err(HttpError::Malformed);
+        }
+        body.extend_from_slice(&buf[pos..pos + size]);
+        pos += size + 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_io_async::{ErrorKind, ErrorType};
+    use std::io::{BufRead, BufReader, Read as _, Write as _};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    // 阻塞的 std TcpStream 包成异步读写，每次 await 都立即完成
+    struct Stream(TcpStream);
+
+    impl ErrorType for Stream {
+        type Error = ErrorKind;
+    }
+
+    impl Read for Stream {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+            self.0.read(buf).map_err(|_| ErrorKind::Other)
+        }
+    }
+
+    impl Write for Stream {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+            self.0.write(buf).map_err(|_| ErrorKind::Other)
+        }
+
+        async fn flush(&mut self) -> Result<(), ErrorKind> {
+            self.0.flush().map_err(|_| ErrorKind::Other)
+        }
+    }
+
+    // 只在超时的测试里用：立即到时
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    // 其余测试用：永远不会到时
+    struct Never;
+
+    impl DelayNs for Never {
+        async fn delay_ns(&mut self, _ns: u32) {
+            core::future::pending::<()>().await
+        }
+    }
+
+    // 本机上的服务器：每个连接依次读请求、回复准备好的响应，回复完一个连接的
+    // 全部响应后关闭它；返回地址和收到的请求 (请求头 + 请求体)
+    fn serve(connections: Vec<Vec<&'static str>>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for responses in connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                for response in responses {
+                    let mut request = String::new();
+                    let mut len = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if let Some(v) = line.strip_prefix("Content-Length: ") {
+                            len = v.trim().parse().unwrap();
+                        }
+                        request.push_str(&line);
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    let mut body = vec![0u8; len];
+                    reader.read_exact(&mut body).unwrap();
+                    request.push_str(core::str::from_utf8(&body).unwrap());
+                    requests.push(request);
+                    writer.write_all(response.as_bytes()).unwrap();
+                }
+            }
+            requests
+        });
+        (address, handle)
+    }
+
+    fn connect(address: &str) -> Client<Stream, Never> {
+        Client::new(Stream(TcpStream::connect(address).unwrap()), Never, address)
+    }
+
+    #[test]
+    fn parses_status_line_and_headers() {
+        let head = parse_head("HTTP/1.1 200 OK\r\nContent-Length: 12\r\nX-Id:7").unwrap();
+        assert_eq!(head.status, 200);
+        assert_eq!(head.framing, Framing::Length(12));
+        assert!(head.keep_alive);
+        assert_eq!(head.headers[1], ("X-Id".into(), "7".into()));
+
+        let head = parse_head("HTTP/1.1 200 OK\r\ntransfer-encoding: Chunked").unwrap();
+        assert_eq!(head.framing, Framing::Chunked);
+        let head = parse_head("HTTP/1.0 204 No Content").unwrap();
+        assert_eq!((head.framing, head.keep_alive), (Framing::Empty, false));
+        let head = parse_head("HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 0");
+        assert!(!head.unwrap().keep_alive);
+        // 没有长度信息：读到关闭为止，连接不能复用
+        let head = parse_head("HTTP/1.1 500 Internal Server Error").unwrap();
+        assert_eq!(
+            (head.framing, head.keep_alive),
+            (Framing::UntilClose, false)
+        );
+
+        assert_eq!(parse_head("ICY 200 OK"), None);
+        assert_eq!(parse_head("HTTP/1.1 abc OK"), None);
+        assert_eq!(parse_head("HTTP/1.1 200 OK\r\nbroken header"), None);
+        assert_eq!(parse_head("HTTP/1.1 200 OK\r\nContent-Length: -1"), None);
+    }
+
+    #[test]
+    fn parses_server_url() {
+        let url = Url::parse("http://data.example.com:5005/api/").unwrap();
+        assert_eq!(url.host, "data.example.com");
+        assert_eq!(url.authority(), "data.example.com:5005");
+        assert_eq!(url.path("/upload"), "/api/upload");
+
+        let url = Url::parse("http://159.75.201.91").unwrap();
+        assert_eq!((url.port, url.authority()), (80, "159.75.201.91".into()));
+        assert_eq!(url.path("/upload"), "/upload");
+        assert!(!url.tls);
+
+        let url = Url::parse("https://data.example.com/api").unwrap();
+        assert!(url.tls);
+        assert_eq!(
+            (url.port, url.authority()),
+            (443, "data.example.com".into())
+        );
+        let url = Url::parse("https://data.example.com:80").unwrap();
+        assert_eq!(url.authority(), "data.example.com:80");
+
+        assert_eq!(Url::parse("ftp://example.com"), None);
+        assert_eq!(Url::parse("http://:5005/"), None);
+        assert_eq!(Url::parse("http://example.com:port"), None);
+    }
+
+    #[test]
+    fn talks_to_local_server() {
+        let (address, server) = serve(vec![
+            vec![
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 11\r\n\r\n{\"ok\":true}",
+                "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"ok\r\n7;x=1\r\n\":true}\r\n0\r\nX-Trailer: 1\r\n\r\n",
+                "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 5\r\nConnection: close\r\n\r\noops!",
+            ],
+            vec!["HTTP/1.0 200 OK\r\n\r\nuntil close"],
+        ]);
+
+        let mut client = connect(&address);
+        let resp = block_on(client.post_json("/upload", "{\"temp\":21.5}")).unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.header("content-type"), Some("application/json"));
+        assert_eq!(resp.body_str(), Some("{\"ok\":true}"));
+        assert!(client.is_open());
+
+        // 同一条连接上的第二个请求，响应是 chunked
+        let resp = block_on(client.post_json("/inventory", "{}")).unwrap();
+        assert_eq!((resp.status, resp.body_str()), (201, Some("{\"ok\":true}")));
+
+        // 服务器出错：请求失败，连接被服务器关闭
+        let result = block_on(client.post_json("/upload", "{}"));
+        assert_eq!(result, Err(HttpError::Status(500)));
+        assert!(!client.is_open());
+        assert_eq!(
+            block_on(client.post_json("/upload", "{}")),
+            Err(HttpError::Closed)
+        );
+
+        // HTTP/1.0 没有长度：读到关闭为止
+        let mut client = connect(&address).with_keep_alive(false);
+        let resp = block_on(client.request("GET", "/", &[], &[])).unwrap();
+        assert_eq!(resp.body_str(), Some("until close"));
+        assert!(!client.is_open());
+
+        let requests = server.join().unwrap();
+        assert_eq!(
+            requests[0],
+            format!(
+                "POST /upload HTTP/1.1\r\nHost: {}\r\nContent-Length: 13\r\n\
+                 Content-Type: application/json\r\n\r\n{{\"temp\":21.5}}",
+                address
+            )
+        );
+        assert!(requests[1].starts_with("POST /inventory HTTP/1.1\r\n"));
+        assert!(requests[3].contains("Connection: close\r\n"));
+    }
+
+    // 对方不回复的连接
+    struct Silent;
+
+    impl ErrorType for Silent {
+        type Error = ErrorKind;
+    }
+
+    impl Read for Silent {
+        async fn read(&mut self, _buf: &mut [u8]) -> Result<usize, ErrorKind> {
+            core::future::pending().await
+        }
+    }
+
+    impl Write for Silent {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), ErrorKind> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn times_out_without_response() {
+        let mut client = Client::new(Silent, NoDelay, "example.com");
+        assert_eq!(
+            block_on(client.post_json("/upload", "{}")),
+            Err(HttpError::Timeout)
+        );
+        assert!(!client.is_open());
+        client.reconnected();
+        assert!(client.is_open());
+    }
+}
@@ -0,0 +1,186 @@
+//! 读服务器响应最外层字段的最小 JSON 解析
+//!
+//! 设备只关心响应对象最外层的几个字段 (`reset_health`、`command`)，不值得引入完整的
+//! JSON 库。这里按 JSON 语法逐个跳过值 (字符串里的引号和转义、嵌套的对象和数组)，
+//! 只在最外层对象里比较键名：空白写法不同也能认出来，嵌套在别的值里的同名键不会被误认。
+
+/// 一个字段的值；字符串、数字、对象和数组都是原文 (字符串不含两边的引号，转义不展开)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+    Null,
+    Bool(bool),
+    Number(&'a str),
+    String(&'a str),
+    Object(&'a str),
+    Array(&'a str),
+}
+
+impl<'a> Value<'a> {
+    pub fn as_bool(self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> Option<&'a str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// 取出 `json` 最外层对象里 `key` 的值；不是对象、格式不对或者没有这个键时返回 `None`
+pub fn field<'a>(json: &'a str, key: &str) -> Option<Value<'a>> {
+    let mut cursor = Cursor { json, pos: 0 };
+    cursor.expect(b'{')?;
+    if cursor.eat(b'}') {
+        return None;
+    }
+    loop {
+        cursor.skip_whitespace();
+        let name = cursor.string()?;
+        cursor.expect(b':')?;
+        let value = cursor.value()?;
+        if name == key {
+            return Some(value);
+        }
+        if !cursor.eat(b',') {
+            return None;
+        }
+    }
+}
+
+struct Cursor<'a> {
+    json: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\r' | b'\n')) {
+            self.pos += 1;
+        }
+    }
+
+    // 跳过空白后如果是 `byte` 就吃掉它
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.eat(byte).then_some(())
+    }
+
+    // 当前位置是一个字符串，返回引号里面的原文
+    fn string(&mut self) -> Option<&'a str> {
+        if self.peek() != Some(b'"') {
+            return None;
+        }
+        let start = self.pos + 1;
+        let mut i = start;
+        let bytes = self.json.as_bytes();
+        loop {
+            match bytes.get(i)? {
+                b'"' => break,
+                b'\\' => i += 2,
+                _ => i += 1,
+            }
+        }
+        self.pos = i + 1;
+        Some(&self.json[start..i])
+    }
+
+    fn literal(&mut self, text: &str) -> Option<()> {
+        let found = self.json[self.pos..].starts_with(text);
+        if found {
+            self.pos += text.len();
+        }
+        found.then_some(())
+    }
+
+    fn value(&mut self) -> Option<Value<'a>> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek()? {
+            b'"' => self.string().map(Value::String),
+            b'{' | b'[' => {
+                // 数括号跳过整个对象或数组，字符串里的括号不算
+                let mut depth = 0usize;
+                loop {
+                    match self.peek()? {
+                        b'"' => {
+                            self.string()?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => depth -= 1,
+                        _ => {}
+                    }
+                    self.pos += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                let text = &self.json[start..self.pos];
+                Some(match text.as_bytes()[0] {
+                    b'{' => Value::Object(text),
+                    _ => Value::Array(text),
+                })
+            }
+            b't' => self.literal("true").map(|_| Value::Bool(true)),
+            b'f' => self.literal("false").map(|_| Value::Bool(false)),
+            b'n' => self.literal("null").map(|_| Value::Null),
+            _ => {
+                while matches!(
+                    self.peek(),
+                    Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E')
+                ) {
+                    self.pos += 1;
+                }
+                (self.pos > start).then(|| Value::Number(&self.json[start..self.pos]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_top_level_fields() {
+        let json =
+            r#" { "ok" : true, "n": -1.5e3, "s":"a \"b\" {", "o":{"k":[1,"]"]}, "z" :null } "#;
+        assert_eq!(field(json, "ok"), Some(Value::Bool(true)));
+        assert_eq!(field(json, "n"), Some(Value::Number("-1.5e3")));
+        assert_eq!(field(json, "s"), Some(Value::String(r#"a \"b\" {"#)));
+        assert_eq!(field(json, "o"), Some(Value::Object(r#"{"k":[1,"]"]}"#)));
+        assert_eq!(field(json, "z"), Some(Value::Null));
+        assert_eq!(field(json, "missing"), None);
+        assert_eq!(field("{}", "ok"), None);
+    }
+
+    #[test]
+    fn ignores_nested_and_quoted_keys() {
+        // 同名的键只出现在嵌套对象或字符串里
+        let json = r#"{"data":{"reset_health":true},"note":"\"reset_health\":true"}"#;
+        assert_eq!(field(json, "reset_health"), None);
+        // 不是对象，或者在找到之前就坏了
+        assert_eq!(field("[\"reset_health\",true]", "reset_health"), None);
+        assert_eq!(
+            field(r#"{"a":tru, "reset_health":true}"#, "reset_health"),
+            None
+        );
+    }
+}
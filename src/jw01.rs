@@ -0,0 +1,331 @@
+//! JW01-CO2 模块的串口帧解析
+//!
+//! 模块以 9600 8N1 约每秒主动发送一帧，共 6 字节：
+//!
+//! | B1   | B2       | B3       | B4   | B5   | B6                 |
+//! |------|----------|----------|------|------|--------------------|
+//! | 0x2C | ppm 高位 | ppm 低位 | 0x03 | 0xFF | B1..B5 相加取低 8 位 |
+//!
+//! 数据字节也可能等于 0x2C，所以不能只靠“找到 0x2C 就开始收 6 字节”。
+//! `Parser` 逐字节接收，整帧校验失败时把窗口向后滑动到下一个 0x2C 重新对齐，
+//! 不会丢掉混在坏帧里的真正帧头。
+
+/// 上传 JSON 里的字段名 (换成 S8/MH-Z19 时沿用)
+pub const METRIC: &str = "co2";
+/// 一帧的长度
+pub const FRAME_LEN: usize = 6;
+/// 模块主动发送的间隔
+pub const FRAME_PERIOD_MS: u64 = 1000;
+/// 帧头
+pub const HEADER: u8 = 0x2C;
+/// B4 B5 固定的满量程字段 (0x03FF)
+pub const FULL_SCALE: [u8; 2] = [0x03, 0xFF];
+
+/// 校验通过的一帧
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// CO2 浓度 (ppm)
+    pub ppm: u16,
+    /// 原始 6 字节，方便打印排查
+    pub raw: [u8; FRAME_LEN],
+}
+
+/// 帧解析失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// B4 B5 不是 0x03 0xFF (通常是帧没对齐)
+    BadFullScale { high: u8, low: u8 },
+    /// 校验和不匹配
+    Checksum { expected: u8, actual: u8 },
+    /// 帧没收完串口就停了，`received` 是已收到的字节数
+    Truncated { received: usize },
+}
+
+/// 计算 B1..B5 的累加校验和
+pub fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+/// 校验一帧完整的 6 字节
+pub fn decode(raw: &[u8; FRAME_LEN]) -> Result<Frame, FrameError> {
+    if raw[3..5] != FULL_SCALE {
+        return Err(FrameError::BadFullScale {
+            high: raw[3],
+            low: raw[4],
+        });
+    }
+    let expected = checksum(&raw[..5]);
+    if expected != raw[5] {
+        return Err(FrameError::Checksum {
+            expected,
+            actual: raw[5],
+        });
+    }
+    Ok(Frame {
+        ppm: ((raw[1] as u16) << 8) | (raw[2] as u16),
+        raw: *raw,
+    })
+}
+
+/// 逐字节的帧解析器
+#[derive(Debug, Default)]
+pub struct Parser {
+    buf: [u8; FRAME_LEN],
+    len: usize,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; FRAME_LEN],
+            len: 0,
+        }
+    }
+
+    /// 喂入一个字节；凑满一帧时返回解析结果，否则返回 `None`
+    ///
+    /// 帧头之前的杂散字节直接丢弃，不算错误。
+    pub fn push(&mut self, byte: u8) -> Option<Result<Frame, FrameError>> {
+        if self.len == 0 && byte != HEADER {
+            return None;
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len < FRAME_LEN {
+            return None;
+        }
+
+        let result = decode(&self.buf);
+        match result {
+            Ok(_) => self.len = 0,
+            Err(_) => self.resync(),
+        }
+        Some(result)
+    }
+
+    /// 串口停顿时调用：丢弃收到一半的帧，返回 `Truncated`
+    pub fn timeout(&mut self) -> Option<FrameError> {
+        let received = core::mem::take(&mut self.len);
+        (received > 0).then_some(FrameError::Truncated { received })
+    }
+
+    /// 已收到、还没凑成一帧的字节数
+    pub fn pending(&self) -> usize {
+        self.len
+    }
+
+    // 窗口滑动到下一个 0x2C (第一个字节之后)，没有就清空
+    fn resync(&mut self) {
+        match self.buf[1..self.len].iter().position(|&b| b == HEADER) {
+            Some(offset) => {
+                let start = offset + 1;
+                self.buf.copy_within(start..self.len, 0);
+                self.len -= start;
+            }
+            None => self.len = 0,
+        }
+    }
+}
+
+/// 帧接收统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// 校验通过的帧数
+    pub frames: u32,
+    pub bad_full_scale: u32,
+    pub checksum_errors: u32,
+    pub truncated: u32,
+    /// 超过一个发送周期一个字节都没收到的次数 (没接、掉线或没供电)
+    pub no_data: u32,
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Self {
+            frames: 0,
+            bad_full_scale: 0,
+            checksum_errors: 0,
+            truncated: 0,
+            no_data: 0,
+        }
+    }
+
+    /// 记一次 `Parser` 的结果
+    pub fn record(&mut self, result: &Result<Frame, FrameError>) {
+        let counter = match result {
+            Ok(_) => &mut self.frames,
+            Err(FrameError::BadFullScale { .. }) => &mut self.bad_full_scale,
+            Err(FrameError::Checksum { .. }) => &mut self.checksum_errors,
+            Err(FrameError::Truncated { .. }) => &mut self.truncated,
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    /// 记一次串口上完全没有数据 (`Parser` 里也没有收到一半的帧)
+    pub fn record_no_data(&mut self) {
+        self.no_data = self.no_data.saturating_add(1);
+    }
+
+    /// 出错的帧数 (不含成功的帧)，没有数据的周期也算
+    pub fn errors(&self) -> u32 {
+        self.bad_full_scale
+            .saturating_add(self.checksum_errors)
+            .saturating_add(self.truncated)
+            .saturating_add(self.no_data)
+    }
+
+    /// 上传用的计数器名和值
+    pub fn counters(&self) -> [(&'static str, u32); 5] {
+        [
+            ("frames", self.frames),
+            ("full_scale_errors", self.bad_full_scale),
+            ("checksum_errors", self.checksum_errors),
+            ("truncated", self.truncated),
+            ("no_data", self.no_data),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(parser: &mut Parser, bytes: &[u8]) -> Vec<Result<Frame, FrameError>> {
+        bytes.iter().filter_map(|&b| parser.push(b)).collect()
+    }
+
+    #[test]
+    fn decodes_valid_frame() {
+        let raw = [0x2C, 0x01, 0x9C, 0x03, 0xFF, 0xCB];
+        assert_eq!(decode(&raw), Ok(Frame { ppm: 412, raw }));
+    }
+
+    #[test]
+    fn reports_typed_errors() {
+        assert_eq!(
+            decode(&[0x2C, 0x01, 0x9C, 0x02, 0xFF, 0xCA]),
+            Err(FrameError::BadFullScale {
+                high: 0x02,
+                low: 0xFF
+            })
+        );
+        assert_eq!(
+            decode(&[0x2C, 0x01, 0x9C, 0x03, 0xFF, 0x00]),
+            Err(FrameError::Checksum {
+                expected: 0xCB,
+                actual: 0x00
+            })
+        );
+
+        let mut parser = Parser::new();
+        assert_eq!(parser.timeout(), None);
+        assert!(feed(&mut parser, &[0x2C, 0x01, 0x9C]).is_empty());
+        assert_eq!(
+            parser.timeout(),
+            Some(FrameError::Truncated { received: 3 })
+        );
+        assert_eq!(parser.pending(), 0);
+    }
+
+    #[test]
+    fn data_byte_equal_to_header_keeps_sync() {
+        // 300 ppm = 0x012C，连续两帧都能解出来
+        let mut parser = Parser::new();
+        let frames = feed(
+            &mut parser,
+            &[
+                0x2C, 0x01, 0x2C, 0x03, 0xFF, 0x5B, 0x2C, 0x01, 0x2C, 0x03, 0xFF, 0x5B,
+            ],
+        );
+        assert_eq!(frames.len(), 2);
+        assert!(frames
+            .iter()
+            .all(|f| matches!(f, Ok(Frame { ppm: 300, .. }))));
+    }
+
+    #[test]
+    fn stats_count_each_outcome() {
+        let mut parser = Parser::new();
+        let mut stats = Stats::new();
+        for result in feed(
+            &mut parser,
+            &[
+                0x2C, 0x01, 0x9C, 0x03, 0xFF, 0xCB, // 412 ppm
+                0x2C, 0x01, 0x9D, 0x03, 0xFF, 0xCB, // 校验和错误
+                0x2C, 0x01, 0x9C, 0x02, 0xFF, 0xCA, // 满量程字段错误
+                0x2C, 0x01,
+            ],
+        ) {
+            stats.record(&result);
+        }
+        stats.record(&Err(parser.timeout().unwrap()));
+        // 之后串口一直没有数据
+        assert_eq!(parser.timeout(), None);
+        stats.record_no_data();
+
+        assert_eq!(
+            stats,
+            Stats {
+                frames: 1,
+                bad_full_scale: 1,
+                checksum_errors: 1,
+                truncated: 1,
+                no_data: 1,
+            }
+        );
+        assert_eq!(stats.errors(), 4);
+    }
+
+    // 解析一行语料里的期望结果
+    fn expected(event: &str) -> Result<u16, &'static str> {
+        match event {
+            "full_scale" => Err("full_scale"),
+            "checksum" => Err("checksum"),
+            "truncated" => Err("truncated"),
+            ppm => Ok(ppm.parse().unwrap()),
+        }
+    }
+
+    fn outcome(result: Result<Frame, FrameError>) -> Result<u16, &'static str> {
+        match result {
+            Ok(frame) => Ok(frame.ppm),
+            Err(FrameError::BadFullScale { .. }) => Err("full_scale"),
+            Err(FrameError::Checksum { .. }) => Err("checksum"),
+            Err(FrameError::Truncated { .. }) => Err("truncated"),
+        }
+    }
+
+    #[test]
+    fn stream_corpus() {
+        let corpus = include_str!("../testdata/jw01_frames.txt");
+        let mut parser = Parser::new();
+        let mut frames = 0;
+
+        for (number, line) in corpus.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (input, events) = line.split_once("=>").unwrap();
+            let want: Vec<_> = events
+                .split(',')
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+                .map(expected)
+                .collect();
+
+            let got: Vec<_> = if input.trim() == "timeout" {
+                parser.timeout().map(Err).into_iter().map(outcome).collect()
+            } else {
+                let bytes: Vec<u8> = input
+                    .split_whitespace()
+                    .map(|h| u8::from_str_radix(h, 16).unwrap())
+                    .collect();
+                feed(&mut parser, &bytes).into_iter().map(outcome).collect()
+            };
+            frames += got.iter().filter(|r| r.is_ok()).count();
+            assert_eq!(got, want, "line {}: {}", number + 1, line);
+        }
+        assert_eq!(frames, 10);
+    }
+}
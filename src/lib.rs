@@ -1 +1,27 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod adc;
+pub mod calibration;
+pub mod discovery;
+pub mod dns;
+pub mod ds18b20;
+pub mod events;
+pub mod flash;
+pub mod health;
+pub mod http;
+pub mod json;
+pub mod jw01;
+pub mod light;
+pub mod mqtt;
+pub mod ndir;
+pub mod nh3;
+pub mod onewire;
+pub mod outbox;
+pub mod plausibility;
+pub mod power;
+pub mod sensor;
+pub mod sht;
+pub mod tls;
+pub mod window;
@@ -0,0 +1,376 @@
+//! 环境光照度 (BH1750 / VEML7700，I2C) 与明暗周期判断
+//!
+//! 饲养室按 12:12 开关灯，`PhaseTracker` 根据连续几次的照度判断当前是亮期还是暗期，
+//! 记录每次切换发生的时刻 (上电以来的秒数)，服务器据此检查开关灯时间和夜间漏光。
+
+use alloc::{format, string::String, vec, vec::Vec};
+use embedded_hal::i2c::I2c;
+use embedded_hal_async::delay::DelayNs;
+
+use crate::events::EventLog;
+use crate::sensor::{Measurement, Sensor, Unit};
+
+/// 上传 JSON 里的字段名
+pub const METRIC: &str = "lux";
+
+/// 传感器型号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    Bh1750,
+    Veml7700,
+}
+
+impl Model {
+    /// 默认 I2C 地址 (BH1750 ADDR 接地为 0x23，接高为 0x5C；VEML7700 固定 0x10)
+    pub fn default_address(self) -> u8 {
+        match self {
+            Model::Bh1750 => 0x23,
+            Model::Veml7700 => 0x10,
+        }
+    }
+}
+
+// BH1750：单次高分辨率测量 (1 lx 分辨率)，完成后自动掉电，最长 180 ms
+const BH1750_ONE_TIME_HIGH_RES: u8 = 0x20;
+const BH1750_MEASURE_MS: u32 = 180;
+// 默认 MTreg 下的换算系数
+const BH1750_COUNTS_PER_LUX: f32 = 1.2;
+
+// VEML7700 寄存器 (16 位，低字节在前)
+const VEML7700_ALS_CONF: u8 = 0x00;
+const VEML7700_ALS: u8 = 0x04;
+// 增益 ×1、积分时间 100 ms、开启，量程约 0 ~ 3770 lx
+const VEML7700_CONF: u16 = 0x0000;
+// 上电后等一个积分周期再读
+const VEML7700_STARTUP_MS: u32 = 150;
+// 增益 ×1、100 ms 时每个计数对应的照度
+const VEML7700_LUX_PER_COUNT: f32 = 0.0576;
+
+/// BH1750 原始值换算成 lx
+pub fn bh1750_lux(raw: u16) -> f32 {
+    raw as f32 / BH1750_COUNTS_PER_LUX
+}
+
+/// VEML7700 ALS 原始值换算成 lx (增益 ×1、积分 100 ms)
+pub fn veml7700_lux(raw: u16) -> f32 {
+    raw as f32 * VEML7700_LUX_PER_COUNT
+}
+
+/// 照度传感器驱动
+pub struct LuxSensor<I, D> {
+    i2c: I,
+    delay: D,
+    model: Model,
+    address: u8,
+    // VEML7700 需要先写配置寄存器
+    configured: bool,
+}
+
+impl<I: I2c, D: DelayNs> LuxSensor<I, D> {
+    pub fn new(i2c: I, delay: D, model: Model) -> Self {
+        Self {
+            i2c,
+            delay,
+            model,
+            address: model.default_address(),
+            configured: false,
+        }
+    }
+
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// 测量一次照度 (lx)
+    pub async fn measure(&mut self) -> Result<f32, I::Error> {
+        match self.model {
+            Model::Bh1750 => {
+                self.i2c.write(self.address, &[BH1750_ONE_TIME_HIGH_RES])?;
+                self.delay.delay_ms(BH1750_MEASURE_MS).await;
+                let mut buf = [0u8; 2];
+                self.i2c.read(self.address, &mut buf)?;
+                Ok(bh1750_lux(u16::from_be_bytes(buf)))
+            }
+            Model::Veml7700 => {
+                if !self.configured {
+                    let [low, high] = VEML7700_CONF.to_le_bytes();
+                    self.i2c
+                        .write(self.address, &[VEML7700_ALS_CONF, low, high])?;
+                    self.delay.delay_ms(VEML7700_STARTUP_MS).await;
+                    self.configured = true;
+                }
+                let mut buf = [0u8; 2];
+                let result = self.i2c.write_read(self.address, &[VEML7700_ALS], &mut buf);
+                if result.is_err() {
+                    // 可能是掉电重启过，下次重新配置
+                    self.configured = false;
+                }
+                result?;
+                Ok(veml7700_lux(u16::from_le_bytes(buf)))
+            }
+        }
+    }
+}
+
+impl<I: I2c, D: DelayNs> Sensor for LuxSensor<I, D> {
+    fn name(&self) -> &'static str {
+        match self.model {
+            Model::Bh1750 => "BH1750",
+            Model::Veml7700 => "VEML7700",
+        }
+    }
+
+    async fn sample(&mut self) -> Vec<Measurement> {
+        let m = match self.measure().await {
+            Ok(lux) => Measurement::new(METRIC, lux, Unit::Lux),
+            Err(_) => Measurement::invalid(METRIC, Unit::Lux),
+        };
+        vec![m]
+    }
+}
+
+// 上传一直失败时最多保留的切换记录
+const MAX_TRANSITIONS: usize = 16;
+
+/// 明暗状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Light,
+    Dark,
+}
+
+impl Phase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Light => "light",
+            Phase::Dark => "dark",
+        }
+    }
+}
+
+/// 一次开灯或关灯
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    /// 切换之后的状态
+    pub to: Phase,
+    /// 第一次越过阈值的那次采样的时刻 (上电以来的秒数)
+    pub uptime_s: u64,
+}
+
+/// 按照度判断明暗周期
+///
+/// 高于 `on_lux` 进入亮期，低于 `off_lux` 进入暗期，两个阈值之间保持不变。
+/// 要连续 `confirm` 次采样都越过阈值才算切换，开门时手电筒、走廊灯一闪不会被当成开灯。
+pub struct PhaseTracker {
+    on_lux: f32,
+    off_lux: f32,
+    confirm: u8,
+    phase: Option<Phase>,
+    // 正在确认的切换：连续越过阈值的次数和第一次的时刻
+    pending: Option<(u8, u64)>,
+    transitions: EventLog<Transition, MAX_TRANSITIONS>,
+}
+
+impl PhaseTracker {
+    pub fn new(on_lux: f32, off_lux: f32, confirm: u8) -> Self {
+        Self {
+            on_lux,
+            off_lux,
+            confirm: confirm.max(1),
+            phase: None,
+            pending: None,
+            transitions: EventLog::new(),
+        }
+    }
+
+    /// 当前状态，还没有读数时为 `None`
+    pub fn phase(&self) -> Option<Phase> {
+        self.phase
+    }
+
+    /// 输入一次有效的照度，发生切换时返回它
+    pub fn update(&mut self, uptime_s: u64, lux: f32) -> Option<Transition> {
+        let target = if lux >= self.on_lux {
+            Phase::Light
+        } else if lux <= self.off_lux {
+            Phase::Dark
+        } else {
+            // 落在两个阈值之间，正在确认的切换作废
+            self.pending = None;
+            return None;
+        };
+
+        let Some(phase) = self.phase else {
+            // 开机后的第一个读数只确定初始状态，不算切换
+            self.phase = Some(target);
+            return None;
+        };
+        if target == phase {
+            self.pending = None;
+            return None;
+        }
+
+        let (count, since) = match self.pending {
+            Some((count, since)) => (count + 1, since),
+            None => (1, uptime_s),
+        };
+        if count < self.confirm {
+            self.pending = Some((count, since));
+            return None;
+        }
+
+        self.phase = Some(target);
+        self.pending = None;
+        let transition = Transition {
+            to: target,
+            uptime_s: since,
+        };
+        self.transitions.push(transition);
+        Some(transition)
+    }
+
+    /// 上次上传之后发生的切换
+    pub fn transitions(&self) -> &[Transition] {
+        self.transitions.as_slice()
+    }
+
+    /// 切换放进上传队列后清掉
+    pub fn clear(&mut self) {
+        self.transitions.clear();
+    }
+
+    /// 上传 JSON 的字段 (不含外层大括号)：
+    /// `"light_phase":{"state":"dark","transitions":[{"to":"dark","uptime_s":43170}]}`
+    pub fn json_field(&self) -> String {
+        let state = match self.phase {
+            Some(phase) => format!("\"{}\"", phase.as_str()),
+            None => "null".into(),
+        };
+        let transitions = self.transitions.json_array(|t| {
+            format!(
+                "{{\"to\":\"{}\",\"uptime_s\":{}}}",
+                t.to.as_str(),
+                t.uptime_s
+            )
+        });
+        format!(
+            "\"light_phase\":{{\"state\":{},\"transitions\":{}}}",
+            state, transitions
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensor::Quality;
+    use embassy_futures::block_on;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    // 记录写入的字节，按顺序返回预先准备的读数据
+    struct MockI2c {
+        address: u8,
+        writes: Vec<Vec<u8>>,
+        reads: Vec<Vec<u8>>,
+    }
+
+    impl ErrorType for MockI2c {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for MockI2c {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            if address != self.address {
+                return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+            }
+            for op in operations {
+                match op {
+                    Operation::Write(bytes) => self.writes.push(bytes.to_vec()),
+                    Operation::Read(buf) => buf.copy_from_slice(&self.reads.remove(0)),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bh1750_one_time_measurement() {
+        let i2c = MockI2c {
+            address: 0x23,
+            writes: Vec::new(),
+            // 0x0258 = 600 → 500 lx
+            reads: vec![vec![0x02, 0x58]],
+        };
+        let mut sensor = LuxSensor::new(i2c, NoDelay, Model::Bh1750);
+        let out = block_on(sensor.sample());
+        assert_eq!(out[0].metric, METRIC);
+        assert!((out[0].value - 500.0).abs() < 0.01);
+        assert_eq!(sensor.i2c.writes, [vec![0x20]]);
+
+        // 地址上没有设备 (没有 ACK)
+        let mut sensor = sensor.with_address(0x5C);
+        assert_eq!(block_on(sensor.sample())[0].quality, Quality::Invalid);
+    }
+
+    #[test]
+    fn veml7700_configures_once() {
+        let i2c = MockI2c {
+            address: 0x10,
+            writes: Vec::new(),
+            // 1000 个计数，低字节在前
+            reads: vec![vec![0xE8, 0x03], vec![0xE8, 0x03]],
+        };
+        let mut sensor = LuxSensor::new(i2c, NoDelay, Model::Veml7700);
+        assert!((block_on(sensor.measure()).unwrap() - 57.6).abs() < 0.01);
+        block_on(sensor.measure()).unwrap();
+        assert_eq!(
+            sensor.i2c.writes,
+            [vec![0x00, 0x00, 0x00], vec![0x04], vec![0x04]]
+        );
+    }
+
+    #[test]
+    fn tracks_light_cycle_with_debounce() {
+        let mut tracker = PhaseTracker::new(50.0, 10.0, 2);
+        assert_eq!(tracker.update(0, 300.0), None);
+        assert_eq!(tracker.phase(), Some(Phase::Light));
+
+        // 关灯：连续两次低于阈值，时刻取第一次
+        assert_eq!(tracker.update(30, 2.0), None);
+        assert_eq!(
+            tracker.update(60, 1.0),
+            Some(Transition {
+                to: Phase::Dark,
+                uptime_s: 30
+            })
+        );
+
+        // 夜里手电筒照了一下，不算开灯
+        assert_eq!(tracker.update(90, 800.0), None);
+        assert_eq!(tracker.update(120, 0.5), None);
+        // 介于两个阈值之间也会打断确认
+        assert_eq!(tracker.update(150, 120.0), None);
+        assert_eq!(tracker.update(180, 30.0), None);
+        assert_eq!(tracker.update(210, 120.0), None);
+        assert!(tracker.update(240, 150.0).is_some());
+
+        assert_eq!(
+            tracker.json_field(),
+            "\"light_phase\":{\"state\":\"light\",\"transitions\":\
+             [{\"to\":\"dark\",\"uptime_s\":30},{\"to\":\"light\",\"uptime_s\":210}]}"
+        );
+        tracker.clear();
+        assert!(tracker.transitions().is_empty());
+    }
+}
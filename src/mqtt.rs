@@ -0,0 +1,677 @@
+//! 最小的 MQTT 发布客户端 (3.1.1 和 5.0)
+//!
+//! 实验室的其它设备通过 MQTT 上报数据，这里只实现发布需要的部分：CONNECT (用户名、密码、
+//! 遗嘱消息)、QoS 0/1 的 PUBLISH (可以 retain)、PINGREQ 和 DISCONNECT，不订阅主题。
+//! 和 `http::Client` 一样在一条已经建立好的连接上工作：
+//!
+//! - 每个要等 broker 回复的操作 (CONNACK、PUBACK、PINGRESP) 都有超时
+//! - 出错或超时后 `is_connected()` 返回 false，调用方重新建立连接后再 `connect()`
+//! - 连接要一直保持，遗嘱消息才有意义；空闲时靠主循环定期调用 `keep_alive()` 发心跳
+
+use alloc::{string::String, vec, vec::Vec};
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+/// broker 的默认端口 (不加密)
+pub const DEFAULT_PORT: u16 = 1883;
+/// 默认超时：从发出报文到收到 broker 的回复
+pub const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+
+// 收到的报文超过这个长度就读完丢掉 (我们只关心几个字节的确认报文)
+const MAX_PACKET_LEN: usize = 256;
+
+// 报文类型 (首字节的高 4 位)
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+/// 协议版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V311,
+    V5,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QoS {
+    /// 只发一次，不确认
+    AtMostOnce = 0,
+    /// broker 回 PUBACK 才算发出
+    AtLeastOnce = 1,
+}
+
+/// 遗嘱消息：连接异常断开 (没有发 DISCONNECT) 时由 broker 代为发布
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Will<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+/// 连接参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options<'a> {
+    pub version: Version,
+    pub client_id: &'a str,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    /// 心跳间隔 (秒)，broker 超过 1.5 倍时间没收到报文就认为设备掉线；0 表示不检查
+    pub keep_alive_s: u16,
+    pub will: Option<Will<'a>>,
+}
+
+impl<'a> Options<'a> {
+    /// MQTT 3.1.1，没有用户名密码和遗嘱，心跳 60 秒
+    pub fn new(client_id: &'a str) -> Self {
+        Self {
+            version: Version::V311,
+            client_id,
+            username: None,
+            password: None,
+            keep_alive_s: 60,
+            will: None,
+        }
+    }
+
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_credentials(mut self, username: &'a str, password: &'a str) -> Self {
+        self.username = Some(username);
+        self.password = Some(password);
+        self
+    }
+
+    pub fn with_keep_alive_s(mut self, keep_alive_s: u16) -> Self {
+        self.keep_alive_s = keep_alive_s;
+        self
+    }
+
+    pub fn with_will(mut self, will: Will<'a>) -> Self {
+        self.will = Some(will);
+        self
+    }
+}
+
+/// 一次操作失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MqttError<E> {
+    /// 连接读写出错
+    Io(E),
+    Timeout,
+    /// 等回复时连接断了
+    Closed,
+    /// 回复的格式不对
+    Malformed,
+    /// 还没连上 broker (或连接已经失效)
+    NotConnected,
+    /// broker 拒绝连接：CONNACK 的返回码 (3.1.1) 或原因码 (5.0)，例如 5 / 0x87 是没有授权
+    Refused(u8),
+    /// broker 拒绝了消息：PUBACK 的原因码 (只有 5.0 有)
+    Rejected(u8),
+}
+
+// 剩余长度：每字节 7 位，最高位表示后面还有
+fn put_length(buf: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        if len == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+// 带 2 字节长度前缀的字符串或二进制数据
+fn put_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+fn packet(first_byte: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(first_byte);
+    put_length(&mut packet, body.len());
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn connect_packet(options: &Options<'_>) -> Vec<u8> {
+    let v5 = options.version == Version::V5;
+    let mut body = Vec::new();
+    put_bytes(&mut body, b"MQTT");
+    body.push(if v5 { 5 } else { 4 });
+    // 每次都是新会话：不订阅，也就没有需要 broker 保留的状态
+    let mut flags = 0x02;
+    if let Some(will) = &options.will {
+        flags |= 0x04 | (will.qos as u8) << 3;
+        if will.retain {
+            flags |= 0x20;
+        }
+    }
+    if options.username.is_some() {
+        flags |= 0x80;
+    }
+    if options.password.is_some() {
+        flags |= 0x40;
+    }
+    body.push(flags);
+    body.extend_from_slice(&options.keep_alive_s.to_be_bytes());
+    // MQTT 5 的属性，都不用
+    if v5 {
+        body.push(0);
+    }
+    put_bytes(&mut body, options.client_id.as_bytes());
+    if let Some(will) = &options.will {
+        if v5 {
+            body.push(0);
+        }
+        put_bytes(&mut body, will.topic.as_bytes());
+        put_bytes(&mut body, will.payload);
+    }
+    if let Some(username) = options.username {
+        put_bytes(&mut body, username.as_bytes());
+    }
+    if let Some(password) = options.password {
+        put_bytes(&mut body, password.as_bytes());
+    }
+    packet(CONNECT << 4, &body)
+}
+
+fn publish_packet(
+    version: Version,
+    topic: &str,
+    payload: &[u8],
+    retain: bool,
+    packet_id: Option<u16>,
+) -> Vec<u8> {
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 5);
+    put_bytes(&mut body, topic.as_bytes());
+    if let Some(id) = packet_id {
+        body.extend_from_slice(&id.to_be_bytes());
+    }
+    if version == Version::V5 {
+        body.push(0);
+    }
+    body.extend_from_slice(payload);
+    let qos = if packet_id.is_some() {
+        QoS::AtLeastOnce
+    } else {
+        QoS::AtMostOnce
+    };
+    packet(PUBLISH << 4 | (qos as u8) << 1 | retain as u8, &body)
+}
+
+/// 和 broker 之间的一条连接
+pub struct Client<T, D> {
+    conn: T,
+    delay: D,
+    timeout_ms: u32,
+    version: Version,
+    connected: bool,
+    next_id: u16,
+    keep_alive_ms: u64,
+    // 上次确认有报文发出的时刻，以及之后有没有再发过
+    last_sent_ms: u64,
+    sent: bool,
+}
+
+impl<T: Read + Write, D: DelayNs> Client<T, D> {
+    /// `conn` 是已经连上 broker 的连接，之后要先调用 `connect()`
+    pub fn new(conn: T, delay: D) -> Self {
+        Self {
+            conn,
+            delay,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+            version: Version::V311,
+            connected: false,
+            next_id: 0,
+            keep_alive_ms: 0,
+            last_sent_ms: 0,
+            sent: false,
+        }
+    }
+
+    pub fn with_timeout_ms(mut self, timeout_ms: u32) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// 是否已经连上 broker、连接没有出过错
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// 底层连接，用来关闭或重新连接
+    pub fn connection(&mut self) -> &mut T {
+        &mut self.conn
+    }
+
+    pub fn into_inner(self) -> T {
+        self.conn
+    }
+
+    /// 发 CONNECT 并等 CONNACK；`now_ms` 是上电以来的毫秒数，用来安排心跳。
+    /// 返回 broker 是否还保留着上次的会话
+    pub async fn connect(
+        &mut self,
+        options: &Options<'_>,
+        now_ms: u64,
+    ) -> Result<bool, MqttError<T::Error>> {
+        self.connected = false;
+        self.version = options.version;
+        self.keep_alive_ms = options.keep_alive_s as u64 * 1000;
+        let body = self
+            .exchange(&connect_packet(options), CONNACK, None)
+            .await?;
+        // 确认标志 (最低位是 session present)、返回码，MQTT 5 后面还有属性
+        let (&flags, &code) = body.first().zip(body.get(1)).ok_or(MqttError::Malformed)?;
+        if code != 0 {
+            return Err(MqttError::Refused(code));
+        }
+        self.connected = true;
+        self.last_sent_ms = now_ms;
+        self.sent = false;
+        Ok(flags & 0x01 != 0)
+    }
+
+    /// 发布一条消息；QoS 1 时等到 broker 的 PUBACK 才返回
+    pub async fn publish(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        qos: QoS,
+        retain: bool,
+    ) -> Result<(), MqttError<T::Error>> {
+        if !self.connected {
+            return Err(MqttError::NotConnected);
+        }
+        let packet_id = match qos {
+            QoS::AtMostOnce => None,
+            QoS::AtLeastOnce => Some(self.next_packet_id()),
+        };
+        let packet = publish_packet(self.version, topic, payload, retain, packet_id);
+        self.sent = true;
+        let Some(id) = packet_id else {
+            return self.send(&packet).await;
+        };
+        let body = self.exchange(&packet, PUBACK, Some(id)).await?;
+        // MQTT 5 的 PUBACK 可以带原因码，0x80 以上是失败 (0x10 “没有订阅者”不算)
+        match body.get(2) {
+            Some(&reason) if reason >= 0x80 => Err(MqttError::Rejected(reason)),
+            _ => Ok(()),
+        }
+    }
+
+    /// 心跳：距离上次发报文超过心跳间隔的一半时发 PINGREQ 并等 PINGRESP。
+    /// 主循环每隔一段时间 (明显短于心跳间隔的一半) 调用一次
+    pub async fn keep_alive(&mut self, now_ms: u64) -> Result<(), MqttError<T::Error>> {
+        if !self.connected {
+            return Err(MqttError::NotConnected);
+        }
+        // 上次调用之后发过消息：以这次调用的时刻为准
+        if self.sent {
+            self.sent = false;
+            self.last_sent_ms = now_ms;
+            return Ok(());
+        }
+        if self.keep_alive_ms == 0
+            || now_ms.saturating_sub(self.last_sent_ms) < self.keep_alive_ms / 2
+        {
+            return Ok(());
+        }
+        self.exchange(&[PINGREQ << 4, 0], PINGRESP, None).await?;
+        self.last_sent_ms = now_ms;
+        Ok(())
+    }
+
+    /// 正常断开：broker 收到 DISCONNECT 后不会发布遗嘱消息
+    pub async fn disconnect(&mut self) -> Result<(), MqttError<T::Error>> {
+        if !self.connected {
+            return Err(MqttError::NotConnected);
+        }
+        self.connected = false;
+        self.send(&[DISCONNECT << 4, 0]).await
+    }
+
+    // 报文标识符：1~65535 循环使用
+    fn next_packet_id(&mut self) -> u16 {
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        self.next_id
+    }
+
+    async fn send(&mut self, packet: &[u8]) -> Result<(), MqttError<T::Error>> {
+        let result = async {
+            self.conn.write_all(packet).await?;
+            self.conn.flush().await
+        }
+        .await;
+        if result.is_err() {
+            self.connected = false;
+        }
+        result.map_err(MqttError::Io)
+    }
+
+    // 发一个报文，等指定类型 (和报文标识符) 的回复，返回回复的可变报头和载荷
+    async fn exchange(
+        &mut self,
+        packet: &[u8],
+        reply: u8,
+        packet_id: Option<u16>,
+    ) -> Result<Vec<u8>, MqttError<T::Error>> {
+        self.send(packet).await?;
+        let result = match select(
+            wait_for(&mut self.conn, reply, packet_id),
+            self.delay.delay_ms(self.timeout_ms),
+        )
+        .await
+        {
+            Either::First(result) => result,
+            Either::Second(()) => Err(MqttError::Timeout),
+        };
+        // 出错或超时后连接里可能还留着半个报文，不能再用
+        if result.is_err() {
+            self.connected = false;
+        }
+        result
+    }
+}
+
+async fn read_exact<T: Read>(conn: &mut T, buf: &mut [u8]) -> Result<(), MqttError<T::Error>> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match conn.read(&mut buf[filled..]).await.map_err(MqttError::Io)? {
+            0 => return Err(MqttError::Closed),
+            n => filled += n,
+        }
+    }
+    Ok(())
+}
+
+// 读一个报文，返回首字节和剩余部分；太长的报文读完丢掉，剩余部分返回空
+async fn read_packet<T: Read>(conn: &mut T) -> Result<(u8, Vec<u8>), MqttError<T::Error>> {
+    let mut byte = [0u8; 1];
+    read_exact(conn, &mut byte).await?;
+    let first_byte = byte[0];
+    let mut len = 0usize;
+    for shift in (0..28).step_by(7) {
+        read_exact(conn, &mut byte).await?;
+        len |= ((byte[0] & 0x7F) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            if len > MAX_PACKET_LEN {
+                let mut scratch = [0u8; 64];
+                while len > 0 {
+                    let n = len.min(scratch.len());
+                    read_exact(conn, &mut scratch[..n]).await?;
+                    len -= n;
+                }
+                return Ok((first_byte, Vec::new()));
+            }
+            let mut body = vec![0u8; len];
+            read_exact(conn, &mut body).await?;
+            return Ok((first_byte, body));
+        }
+    }
+    Err(MqttError::Malformed)
+}
+
+// 跳过别的报文，直到收到想要的回复
+async fn wait_for<T: Read>(
+    conn: &mut T,
+    reply: u8,
+    packet_id: Option<u16>,
+) -> Result<Vec<u8>, MqttError<T::Error>> {
+    loop {
+        let (first_byte, body) = read_packet(conn).await?;
+        if first_byte >> 4 != reply {
+            continue;
+        }
+        match packet_id {
+            Some(id) if body.get(..2) != Some(&id.to_be_bytes()[..]) => continue,
+            _ => return Ok(body),
+        }
+    }
+}
+
+/// 主题里不能出现的字符 (通配符和层级分隔符) 换成 `_`，用来把 ROM 码、指标名等拼进主题
+pub fn topic_level(name: &str) -> String {
+    name.chars()
+        .map(|c| if matches!(c, '/' | '+' | '#') { '_' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_io_async::{ErrorKind, ErrorType};
+    use std::io::{Read as _, Write as _};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    // 阻塞的 std TcpStream 包成异步读写，每次 await 都立即完成
+    struct Stream(TcpStream);
+
+    impl ErrorType for Stream {
+        type Error = ErrorKind;
+    }
+
+    impl Read for Stream {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+            self.0.read(buf).map_err(|_| ErrorKind::Other)
+        }
+    }
+
+    impl Write for Stream {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+            self.0.write(buf).map_err(|_| ErrorKind::Other)
+        }
+
+        async fn flush(&mut self) -> Result<(), ErrorKind> {
+            self.0.flush().map_err(|_| ErrorKind::Other)
+        }
+    }
+
+    struct Never;
+
+    impl DelayNs for Never {
+        async fn delay_ns(&mut self, _ns: u32) {
+            core::future::pending::<()>().await
+        }
+    }
+
+    // 读一个完整的报文，返回报文和可变头部的起始位置
+    fn read_packet(stream: &mut TcpStream) -> Option<(Vec<u8>, usize)> {
+        let mut first = [0u8; 1];
+        stream.read_exact(&mut first).ok()?;
+        let mut packet = vec![first[0]];
+        let mut len = 0;
+        for shift in (0..28).step_by(7) {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).unwrap();
+            packet.push(byte[0]);
+            len |= ((byte[0] & 0x7F) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let start = packet.len();
+        packet.resize(start + len, 0);
+        stream.read_exact(&mut packet[start..]).unwrap();
+        Some((packet, start))
+    }
+
+    // 本机上的假 broker：读到一个报文就按类型回复 (CONNACK 用 `connack`，
+    // PUBLISH QoS 1 回 PUBACK 前先插一条别的消息)，返回收到的全部报文
+    fn broker(connack: &'static [u8]) -> (String, thread::JoinHandle<Vec<Vec<u8>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut packets = Vec::new();
+            while let Some((packet, start)) = read_packet(&mut stream) {
+                match packet[0] >> 4 {
+                    CONNECT => stream.write_all(connack).unwrap(),
+                    PUBLISH if packet[0] & 0x06 != 0 => {
+                        let topic_len = (packet[start] as usize) << 8 | packet[start + 1] as usize;
+                        let id_at = start + 2 + topic_len;
+                        let id = [packet[id_at], packet[id_at + 1]];
+                        stream.write_all(b"\x30\x05\x00\x01xhi").unwrap();
+                        stream.write_all(&[PUBACK << 4, 2, id[0], id[1]]).unwrap();
+                    }
+                    PINGREQ => stream.write_all(&[PINGRESP << 4, 0]).unwrap(),
+                    _ => {}
+                }
+                packets.push(packet);
+            }
+            packets
+        });
+        (address, handle)
+    }
+
+    fn connect(address: &str) -> Client<Stream, Never> {
+        Client::new(Stream(TcpStream::connect(address).unwrap()), Never)
+    }
+
+    #[test]
+    fn encodes_packets() {
+        let options = Options::new("dev1")
+            .with_credentials("user", "pw")
+            .with_keep_alive_s(120)
+            .with_will(Will {
+                topic: "s",
+                payload: b"offline",
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            });
+        assert_eq!(
+            connect_packet(&options),
+            b"\x10\x26\x00\x04MQTT\x04\xEE\x00\x78\x00\x04dev1\x00\x01s\x00\x07offline\
+              \x00\x04user\x00\x02pw"
+        );
+        assert_eq!(
+            connect_packet(&Options::new("d").with_version(Version::V5)),
+            b"\x10\x0E\x00\x04MQTT\x05\x02\x00\x3C\x00\x00\x01d"
+        );
+        assert_eq!(
+            publish_packet(Version::V311, "a/b", b"21.5", true, Some(7)),
+            b"\x33\x0B\x00\x03a/b\x00\x0721.5"
+        );
+        assert_eq!(
+            publish_packet(Version::V5, "t", b"x", false, None),
+            b"\x30\x05\x00\x01t\x00x"
+        );
+        // 剩余长度超过 127 要用两个字节
+        let long = publish_packet(Version::V311, "t", &[0; 200], false, None);
+        assert_eq!(long[..3], [0x30, 0xCB, 0x01]);
+        assert_eq!(topic_level("28FF/1+#"), "28FF_1__");
+    }
+
+    #[test]
+    fn publishes_to_local_broker() {
+        let (address, broker) = broker(b"\x20\x02\x00\x00");
+        let mut client = connect(&address);
+        let options = Options::new("dev1").with_keep_alive_s(60);
+        assert_eq!(block_on(client.connect(&options, 0)), Ok(false));
+        assert!(client.is_connected());
+
+        block_on(client.publish("lab/t", b"21.5", QoS::AtLeastOnce, true)).unwrap();
+        block_on(client.publish("lab/t", b"21.6", QoS::AtLeastOnce, false)).unwrap();
+        block_on(client.publish("lab/x", b"0", QoS::AtMostOnce, false)).unwrap();
+
+        // 发过消息后 30 秒内不用心跳；之后空闲超过 30 秒才发 PINGREQ
+        block_on(client.keep_alive(10_000)).unwrap();
+        block_on(client.keep_alive(39_000)).unwrap();
+        block_on(client.keep_alive(40_000)).unwrap();
+        block_on(client.disconnect()).unwrap();
+        assert!(!client.is_connected());
+        drop(client);
+
+        let packets = broker.join().unwrap();
+        let kinds: Vec<_> = packets.iter().map(|p| p[0]).collect();
+        assert_eq!(kinds, [0x10, 0x33, 0x32, 0x30, 0xC0, 0xE0]);
+        assert_eq!(packets[1], b"\x33\x0D\x00\x05lab/t\x00\x0121.5");
+        assert_eq!(packets[2][9..11], [0, 2]);
+    }
+
+    #[test]
+    fn reports_refused_connection() {
+        // MQTT 5 的 CONNACK：原因码 0x87 (没有授权)，后面是空的属性
+        let (address, broker) = broker(b"\x20\x03\x00\x87\x00");
+        let mut client = connect(&address);
+        let options = Options::new("dev1").with_version(Version::V5);
+        assert_eq!(
+            block_on(client.connect(&options, 0)),
+            Err(MqttError::Refused(0x87))
+        );
+        assert_eq!(
+            block_on(client.publish("t", b"x", QoS::AtMostOnce, false)),
+            Err(MqttError::NotConnected)
+        );
+        drop(client);
+        assert_eq!(broker.join().unwrap().len(), 1);
+    }
+
+    // 和本机真正的 Mosquitto 互通 (默认不运行)：
+    // 先启动 `mosquitto -p 1883`，再运行 `cargo test-host -- --ignored mosquitto`
+    #[test]
+    #[ignore]
+    fn round_trips_through_mosquitto() {
+        const BROKER: &str = "127.0.0.1:1883";
+        for version in [Version::V311, Version::V5] {
+            let topic = format!("esp-mmu-test/{:?}", version);
+            let mut client = connect(BROKER);
+            let options = Options::new("esp-mmu-test-pub").with_version(version);
+            assert_eq!(block_on(client.connect(&options, 0)), Ok(false));
+            // QoS 1 要等到 broker 的 PUBACK 才返回
+            block_on(client.publish(&topic, b"21.5", QoS::AtLeastOnce, true)).unwrap();
+            block_on(client.disconnect()).unwrap();
+
+            // 另开一条连接订阅，broker 应该马上发来刚才保留的消息
+            let mut subscriber = connect(BROKER);
+            let options = Options::new("esp-mmu-test-sub").with_version(version);
+            block_on(subscriber.connect(&options, 0)).unwrap();
+            let stream = &mut subscriber.connection().0;
+            stream
+                .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+                .unwrap();
+            let properties: &[u8] = if version == Version::V5 { &[0] } else { &[] };
+            let mut body = vec![0, 1];
+            body.extend_from_slice(properties);
+            body.extend_from_slice(&(topic.len() as u16).to_be_bytes());
+            body.extend_from_slice(topic.as_bytes());
+            body.push(QoS::AtLeastOnce as u8);
+            stream.write_all(&[0x82, body.len() as u8]).unwrap();
+            stream.write_all(&body).unwrap();
+
+            let (packet, start) = loop {
+                let (packet, start) = read_packet(stream).expect("没有收到保留的消息");
+                if packet[0] >> 4 == PUBLISH {
+                    break (packet, start);
+                }
+            };
+            // 保留标志、QoS 1
+            assert_eq!(packet[0] & 0x07, 0x03);
+            let topic_len = (packet[start] as usize) << 8 | packet[start + 1] as usize;
+            let received = &packet[start + 2..start + 2 + topic_len];
+            assert_eq!(received, topic.as_bytes());
+            // 报文标识符之后 (MQTT 5 还有属性) 是内容
+            let payload_at = start + 2 + topic_len + 2 + properties.len();
+            assert_eq!(&packet[payload_at..], b"21.5");
+
+            // 清掉保留的消息
+            block_on(subscriber.publish(&topic, b"", QoS::AtMostOnce, true)).unwrap();
+            block_on(subscriber.disconnect()).unwrap();
+        }
+    }
+}
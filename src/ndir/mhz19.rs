@@ -0,0 +1,100 @@
+//! Winsen MH-Z19B/C 的串口命令
+//!
+//! 9600 8N1，命令和响应都是 9 字节，以 0xFF 开头，最后一个字节是校验和：
+//! 第 2~8 字节相加取反再加 1。
+//!
+//! | 命令 | 作用 | 响应 |
+//! |------|------|------|
+//! | `FF 01 86 ...` | 读 CO2 | `FF 86 HH LL ...` |
+//! | `FF 01 87 ...` | 零点校准 (当前环境当作 400 ppm) | 无 |
+//! | `FF 01 79 A0/00 ...` | 打开/关闭 ABC | 无 |
+
+use super::ProtocolError;
+
+/// 命令和响应的长度
+pub const FRAME_LEN: usize = 9;
+
+const START: u8 = 0xFF;
+const SENSOR: u8 = 0x01;
+const READ_CO2: u8 = 0x86;
+const ZERO_POINT: u8 = 0x87;
+const ABC: u8 = 0x79;
+const ABC_ON: u8 = 0xA0;
+const ABC_OFF: u8 = 0x00;
+
+/// 第 2~8 字节的校验和
+pub fn checksum(frame: &[u8; FRAME_LEN]) -> u8 {
+    let sum = frame[1..8].iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    (!sum).wrapping_add(1)
+}
+
+fn command(cmd: u8, arg: u8) -> [u8; FRAME_LEN] {
+    let mut frame = [START, SENSOR, cmd, arg, 0, 0, 0, 0, 0];
+    frame[8] = checksum(&frame);
+    frame
+}
+
+/// 读 CO2：`FF 01 86 00 00 00 00 00 79`
+pub fn read_co2_command() -> [u8; FRAME_LEN] {
+    command(READ_CO2, 0)
+}
+
+/// 零点校准：`FF 01 87 00 00 00 00 00 78`
+pub fn zero_point_command() -> [u8; FRAME_LEN] {
+    command(ZERO_POINT, 0)
+}
+
+/// 打开 (`FF 01 79 A0 ... E6`) 或关闭 (`FF 01 79 00 ... 86`) ABC
+pub fn abc_command(enabled: bool) -> [u8; FRAME_LEN] {
+    command(ABC, if enabled { ABC_ON } else { ABC_OFF })
+}
+
+/// 解析读 CO2 的响应
+pub fn parse_co2(frame: &[u8; FRAME_LEN]) -> Result<u16, ProtocolError> {
+    if frame[0] != START || frame[1] != READ_CO2 {
+        return Err(ProtocolError::Unexpected);
+    }
+    let expected = checksum(frame);
+    if expected != frame[8] {
+        return Err(ProtocolError::Checksum {
+            expected: expected as u16,
+            actual: frame[8] as u16,
+        });
+    }
+    Ok(u16::from_be_bytes([frame[2], frame[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_documented_commands() {
+        assert_eq!(read_co2_command()[8], 0x79);
+        assert_eq!(zero_point_command()[8], 0x78);
+        assert_eq!(
+            abc_command(true),
+            [0xFF, 0x01, 0x79, 0xA0, 0, 0, 0, 0, 0xE6]
+        );
+        assert_eq!(abc_command(false)[8], 0x86);
+    }
+
+    #[test]
+    fn parses_read_response() {
+        // 0x01F4 = 500 ppm，后面是温度和状态字节
+        let mut frame = [0xFF, 0x86, 0x01, 0xF4, 0x45, 0x00, 0x00, 0x00, 0];
+        frame[8] = checksum(&frame);
+        assert_eq!(parse_co2(&frame), Ok(500));
+
+        frame[3] = 0xF5;
+        assert!(matches!(
+            parse_co2(&frame),
+            Err(ProtocolError::Checksum { .. })
+        ));
+        // 没对齐：少了帧头
+        assert_eq!(
+            parse_co2(&[0x86, 0x01, 0xF4, 0x45, 0, 0, 0, 0, 0xFF]),
+            Err(ProtocolError::Unexpected)
+        );
+    }
+}
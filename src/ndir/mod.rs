@@ -0,0 +1,424 @@
+//! 问答式的 NDIR CO2 传感器 (SenseAir S8、MH-Z19)
+//!
+//! JW01 每秒主动发一帧 (见 `jw01`)；这两种传感器要先发请求再读响应，所以除了 RX
+//! 还要接 TX。它们支持零点校准和 ABC (自动基线校准) 开关：饲养室 CO2 一直高于
+//! 室外，ABC 会把高的基线误当作 400 ppm，应当关闭，改为定期在新鲜空气中手动校准。
+//!
+//! 报文的编解码在 `s8` 和 `mhz19` 里，`Ndir` 负责收发、超时和出错后重新对齐。
+
+pub mod mhz19;
+pub mod s8;
+
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, ReadExactError, Write};
+
+/// 传感器型号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    SenseAirS8,
+    Mhz19,
+}
+
+impl Model {
+    pub fn name(self) -> &'static str {
+        match self {
+            Model::SenseAirS8 => "SenseAir S8",
+            Model::Mhz19 => "MH-Z19",
+        }
+    }
+}
+
+/// 响应内容有问题
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// CRC16 (S8) 或校验和 (MH-Z19) 不匹配
+    Checksum { expected: u16, actual: u16 },
+    /// 地址、命令字或长度不对 (通常是没对齐)
+    Unexpected,
+    /// S8 的 Modbus 异常码
+    Exception(u8),
+}
+
+/// 一次请求失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NdirError<E> {
+    /// 串口错误
+    Io(E),
+    /// 超时没有收到完整的响应 (没接线、TX/RX 接反、型号选错)
+    Timeout,
+    Protocol(ProtocolError),
+    /// 发了校准命令，但传感器一直没有确认完成 (S8 确认寄存器的标志位没有置 1)
+    NotAcknowledged,
+}
+
+impl<E> From<ProtocolError> for NdirError<E> {
+    fn from(e: ProtocolError) -> Self {
+        NdirError::Protocol(e)
+    }
+}
+
+// 等待响应的最长时间，9600 波特率下 9 字节约 10 ms
+const RESPONSE_TIMEOUT_MS: u32 = 500;
+// 出错后丢弃残留字节：线路空闲这么久就认为清空了
+const DRAIN_IDLE_MS: u32 = 20;
+// S8 背景校准两条命令之间的间隔
+const S8_COMMAND_GAP_MS: u32 = 100;
+// 发完背景校准命令后每隔 1 s 读一次确认寄存器 (手册要求至少等 2 s)，最多等 5 s
+const S8_ACK_POLL_MS: u32 = 1000;
+const S8_ACK_POLLS: usize = 5;
+
+/// 通过串口问答读取 CO2 的传感器，`T` 是同时实现了异步读写的串口
+pub struct Ndir<T, D> {
+    port: T,
+    delay: D,
+    model: Model,
+}
+
+impl<T: Read + Write, D: DelayNs> Ndir<T, D> {
+    pub fn new(port: T, delay: D, model: Model) -> Self {
+        Self { port, delay, model }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// 读一次 CO2 (ppm)
+    pub async fn read_ppm(&mut self) -> Result<u16, NdirError<T::Error>> {
+        let result = match self.model {
+            Model::SenseAirS8 => self.s8_read_co2().await,
+            Model::Mhz19 => {
+                self.send(&mhz19::read_co2_command()).await?;
+                let mut frame = [0u8; mhz19::FRAME_LEN];
+                match self.receive(&mut frame).await {
+                    Ok(()) => mhz19::parse_co2(&frame).map_err(NdirError::from),
+                    Err(e) => Err(e),
+                }
+            }
+        };
+        if result.is_err() {
+            // 收到一半的响应会让下一次也错位
+            self.drain().await;
+        }
+        result
+    }
+
+    /// 零点校准：把当前环境当作 400 ppm
+    ///
+    /// 只能在室外或通风良好的新鲜空气中、传感器稳定 20 分钟以上之后调用。S8 会等到确认寄存器
+    /// 表示校准完成才返回，超时返回 `NotAcknowledged`；MH-Z19 没有确认，发出命令就算成功。
+    pub async fn calibrate_zero(&mut self) -> Result<(), NdirError<T::Error>> {
+        match self.model {
+            Model::SenseAirS8 => {
+                for request in s8::calibration_requests() {
+                    self.s8_write(&request).await?;
+                    self.delay.delay_ms(S8_COMMAND_GAP_MS).await;
+                }
+                for _ in 0..S8_ACK_POLLS {
+                    self.delay.delay_ms(S8_ACK_POLL_MS).await;
+                    let frame = self.s8_read(&s8::read_ack_request()).await?;
+                    if s8::calibration_acknowledged(&frame)? {
+                        return Ok(());
+                    }
+                }
+                Err(NdirError::NotAcknowledged)
+            }
+            // MH-Z19 的设置命令没有响应
+            Model::Mhz19 => self.send(&mhz19::zero_point_command()).await,
+        }
+    }
+
+    /// 打开或关闭 ABC (S8 打开时使用出厂默认的 180 小时周期)
+    pub async fn set_abc(&mut self, enabled: bool) -> Result<(), NdirError<T::Error>> {
+        match self.model {
+            Model::SenseAirS8 => {
+                let hours = if enabled { s8::DEFAULT_ABC_HOURS } else { 0 };
+                self.s8_write(&s8::abc_request(hours)).await
+            }
+            Model::Mhz19 => self.send(&mhz19::abc_command(enabled)).await,
+        }
+    }
+
+    async fn s8_read_co2(&mut self) -> Result<u16, NdirError<T::Error>> {
+        let frame = self.s8_read(&s8::read_co2_request()).await?;
+        Ok(s8::parse_co2(&frame)?)
+    }
+
+    // 发一个读寄存器的请求，返回完整响应 (CRC 由调用方解析时检查)
+    async fn s8_read(
+        &mut self,
+        request: &[u8; s8::REQUEST_LEN],
+    ) -> Result<[u8; s8::READ_RESPONSE_LEN], NdirError<T::Error>> {
+        self.send(request).await?;
+        let mut frame = [0u8; s8::READ_RESPONSE_LEN];
+        self.s8_receive(&mut frame, request[1]).await?;
+        Ok(frame)
+    }
+
+    async fn s8_write(
+        &mut self,
+        request: &[u8; s8::REQUEST_LEN],
+    ) -> Result<(), NdirError<T::Error>> {
+        self.send(request).await?;
+        let mut frame = [0u8; s8::REQUEST_LEN];
+        let result = match self.s8_receive(&mut frame, request[1]).await {
+            Ok(()) => s8::check_echo(request, &frame).map_err(NdirError::from),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            self.drain().await;
+        }
+        result
+    }
+
+    // 先读异常响应长度的 5 字节，确认不是异常后再读剩下的
+    async fn s8_receive(
+        &mut self,
+        frame: &mut [u8],
+        function: u8,
+    ) -> Result<(), NdirError<T::Error>> {
+        let (head, rest) = frame.split_at_mut(s8::EXCEPTION_LEN);
+        self.receive(head).await?;
+        let head: &[u8; s8::EXCEPTION_LEN] = (&*head).try_into().unwrap();
+        let remaining = s8::response_head(head, function)?;
+        self.receive(&mut rest[..remaining]).await
+    }
+
+    async fn send(&mut self, frame: &[u8]) -> Result<(), NdirError<T::Error>> {
+        self.port.write_all(frame).await.map_err(NdirError::Io)?;
+        self.port.flush().await.map_err(NdirError::Io)
+    }
+
+    async fn receive(&mut self, buf: &mut [u8]) -> Result<(), NdirError<T::Error>> {
+        match select(
+            self.port.read_exact(buf),
+            self.delay.delay_ms(RESPONSE_TIMEOUT_MS),
+        )
+        .await
+        {
+            Either::First(Ok(())) => Ok(()),
+            Either::First(Err(ReadExactError::Other(e))) => Err(NdirError::Io(e)),
+            Either::First(Err(ReadExactError::UnexpectedEof)) | Either::Second(()) => {
+                Err(NdirError::Timeout)
+            }
+        }
+    }
+
+    // 丢掉串口里残留的字节，直到线路空闲
+    async fn drain(&mut self) {
+        let mut junk = [0u8; 16];
+        loop {
+            match select(
+                self.port.read(&mut junk),
+                self.delay.delay_ms(DRAIN_IDLE_MS),
+            )
+            .await
+            {
+                Either::First(Ok(n)) if n > 0 => continue,
+                _ => break,
+            }
+        }
+    }
+}
+
+/// 问答统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// 成功的读数
+    pub readings: u32,
+    pub timeouts: u32,
+    pub checksum_errors: u32,
+    /// 串口错误、没对齐、Modbus 异常
+    pub other_errors: u32,
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Self {
+            readings: 0,
+            timeouts: 0,
+            checksum_errors: 0,
+            other_errors: 0,
+        }
+    }
+
+    /// 记一次 `Ndir::read_ppm` 的结果
+    pub fn record<E>(&mut self, result: &Result<u16, NdirError<E>>) {
+        let counter = match result {
+            Ok(_) => &mut self.readings,
+            Err(NdirError::Timeout) => &mut self.timeouts,
+            Err(NdirError::Protocol(ProtocolError::Checksum { .. })) => &mut self.checksum_errors,
+            Err(_) => &mut self.other_errors,
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    /// 失败的次数
+    pub fn errors(&self) -> u32 {
+        self.timeouts
+            .saturating_add(self.checksum_errors)
+            .saturating_add(self.other_errors)
+    }
+
+    /// 上传用的计数器名和值
+    pub fn counters(&self) -> [(&'static str, u32); 4] {
+        [
+            ("readings", self.readings),
+            ("timeouts", self.timeouts),
+            ("checksum_errors", self.checksum_errors),
+            ("other_errors", self.other_errors),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_io_async::{ErrorKind, ErrorType};
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    // 串口另一端的传感器：每收到一个完整请求就把准备好的响应放进接收缓冲；
+    // 没有数据时 read 永远等待，由超时结束
+    struct MockPort {
+        request_len: usize,
+        written: Vec<u8>,
+        requests: Vec<Vec<u8>>,
+        replies: Vec<Vec<u8>>,
+        rx: Vec<u8>,
+    }
+
+    impl MockPort {
+        fn new(request_len: usize, replies: Vec<Vec<u8>>) -> Self {
+            Self {
+                request_len,
+                written: Vec::new(),
+                requests: Vec::new(),
+                replies,
+                rx: Vec::new(),
+            }
+        }
+    }
+
+    impl ErrorType for MockPort {
+        type Error = ErrorKind;
+    }
+
+    impl Read for MockPort {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+            if self.rx.is_empty() {
+                core::future::pending::<()>().await;
+            }
+            let n = buf.len().min(self.rx.len());
+            buf[..n].copy_from_slice(&self.rx[..n]);
+            self.rx.drain(..n);
+            Ok(n)
+        }
+    }
+
+    impl Write for MockPort {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+            self.written.extend_from_slice(buf);
+            while self.written.len() >= self.request_len {
+                let request: Vec<u8> = self.written.drain(..self.request_len).collect();
+                self.requests.push(request);
+                if !self.replies.is_empty() {
+                    let reply = self.replies.remove(0);
+                    self.rx.extend(reply);
+                }
+            }
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), ErrorKind> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reads_s8_over_modbus() {
+        let port = MockPort::new(
+            s8::REQUEST_LEN,
+            vec![vec![0xFE, 0x04, 0x02, 0x01, 0x90, 0xAC, 0xD8]],
+        );
+        let mut s8 = Ndir::new(port, NoDelay, Model::SenseAirS8);
+        assert_eq!(block_on(s8.read_ppm()), Ok(400));
+        assert_eq!(s8.port.requests, [s8::read_co2_request().to_vec()]);
+
+        // 没有响应
+        assert_eq!(block_on(s8.read_ppm()), Err(NdirError::Timeout));
+    }
+
+    #[test]
+    fn s8_commands_expect_echo() {
+        let abc_off = s8::abc_request(0);
+        let [clear, command] = s8::calibration_requests();
+        // 确认寄存器：第一次读还是 0，第二次 bit 5 置 1
+        let ack = |value: u8| {
+            let mut frame = vec![0xFE, 0x03, 0x02, 0x00, value];
+            frame.extend(s8::crc16(&frame).to_le_bytes());
+            frame
+        };
+        let port = MockPort::new(
+            s8::REQUEST_LEN,
+            vec![
+                abc_off.to_vec(),
+                clear.to_vec(),
+                command.to_vec(),
+                ack(0),
+                ack(0x20),
+            ],
+        );
+        let mut s8 = Ndir::new(port, NoDelay, Model::SenseAirS8);
+        assert_eq!(block_on(s8.set_abc(false)), Ok(()));
+        assert_eq!(block_on(s8.calibrate_zero()), Ok(()));
+        assert_eq!(s8.port.requests.len(), 5);
+        assert_eq!(s8.port.requests[4], s8::read_ack_request());
+
+        // 命令有回显，但一直没有确认
+        s8.port.replies = vec![clear.to_vec(), command.to_vec()];
+        s8.port.replies.extend((0..S8_ACK_POLLS).map(|_| ack(0)));
+        assert_eq!(
+            block_on(s8.calibrate_zero()),
+            Err(NdirError::NotAcknowledged)
+        );
+
+        // 异常响应：非法数据值
+        s8.port.replies.push(vec![0xFE, 0x86, 0x03, 0x32, 0x51]);
+        assert_eq!(
+            block_on(s8.set_abc(true)),
+            Err(NdirError::Protocol(ProtocolError::Exception(0x03)))
+        );
+    }
+
+    #[test]
+    fn reads_mhz19_and_resyncs_after_garbage() {
+        let mut reply = vec![0xFF, 0x86, 0x02, 0x58, 0x45, 0, 0, 0, 0];
+        reply[8] = mhz19::checksum(&reply.clone().try_into().unwrap());
+        // 第一次的响应前面多了两个杂散字节：这次失败，残留字节被丢掉，下一次正常
+        let mut garbled = vec![0x00, 0x12];
+        garbled.extend(&reply);
+        let port = MockPort::new(mhz19::FRAME_LEN, vec![garbled, reply]);
+        let mut mhz19 = Ndir::new(port, NoDelay, Model::Mhz19);
+
+        let mut stats = Stats::new();
+        let first = block_on(mhz19.read_ppm());
+        stats.record(&first);
+        assert_eq!(first, Err(NdirError::Protocol(ProtocolError::Unexpected)));
+        assert!(mhz19.port.rx.is_empty());
+        let second = block_on(mhz19.read_ppm());
+        stats.record(&second);
+        assert_eq!(second, Ok(600));
+        assert_eq!((stats.readings, stats.errors()), (1, 1));
+
+        // 设置命令只发不收
+        block_on(mhz19.set_abc(false)).unwrap();
+        assert_eq!(mhz19.port.requests[2], mhz19::abc_command(false));
+    }
+}
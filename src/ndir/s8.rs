@@ -0,0 +1,204 @@
+//! SenseAir S8 的 Modbus RTU 报文
+//!
+//! 9600 8N1，地址 0xFE 表示“任意地址”。每帧最后 2 字节是 CRC16 (Modbus，低字节在前)。
+//! 用到的寄存器：
+//!
+//! | 寄存器 | 类型 | 作用 |
+//! |--------|------|------|
+//! | IR4 (0x0003) | 输入寄存器 | CO2 (ppm) |
+//! | HR1 (0x0000) | 保持寄存器 | 确认寄存器，校准前清零，背景校准完成后 bit 5 置 1 |
+//! | HR2 (0x0001) | 保持寄存器 | 特殊命令，0x7C06 = 背景 (400 ppm) 校准 |
+//! | HR32 (0x001F) | 保持寄存器 | ABC 周期 (小时)，0 关闭 |
+
+use super::ProtocolError;
+
+/// 任意地址
+pub const ANY_ADDRESS: u8 = 0xFE;
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+// 功能码最高位置 1 表示异常响应
+const EXCEPTION: u8 = 0x80;
+
+const IR_CO2: u16 = 0x0003;
+const HR_ACK: u16 = 0x0000;
+const HR_COMMAND: u16 = 0x0001;
+const HR_ABC_PERIOD: u16 = 0x001F;
+
+/// 背景校准命令 (把当前环境当作 400 ppm)
+pub const BACKGROUND_CALIBRATION: u16 = 0x7C06;
+// 确认寄存器里表示背景校准已完成的位 (CI6)
+const ACK_BACKGROUND_CALIBRATION: u16 = 1 << 5;
+/// 出厂默认的 ABC 周期 (小时)
+pub const DEFAULT_ABC_HOURS: u16 = 180;
+
+/// 请求都是 8 字节
+pub const REQUEST_LEN: usize = 8;
+/// 读 1 个寄存器的响应长度
+pub const READ_RESPONSE_LEN: usize = 7;
+/// 异常响应的长度
+pub const EXCEPTION_LEN: usize = 5;
+
+/// Modbus CRC16 (多项式 0xA001 反射，初值 0xFFFF)
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 0x0001 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn request(function: u8, register: u16, value: u16) -> [u8; REQUEST_LEN] {
+    let [rh, rl] = register.to_be_bytes();
+    let [vh, vl] = value.to_be_bytes();
+    let mut frame = [ANY_ADDRESS, function, rh, rl, vh, vl, 0, 0];
+    let [cl, ch] = crc16(&frame[..6]).to_le_bytes();
+    frame[6] = cl;
+    frame[7] = ch;
+    frame
+}
+
+/// 读 CO2 的请求：`FE 04 00 03 00 01 D5 C5`
+pub fn read_co2_request() -> [u8; REQUEST_LEN] {
+    request(READ_INPUT_REGISTERS, IR_CO2, 1)
+}
+
+/// 写单个保持寄存器的请求，成功时从机原样回显
+pub fn write_request(register: u16, value: u16) -> [u8; REQUEST_LEN] {
+    request(WRITE_SINGLE_REGISTER, register, value)
+}
+
+/// 背景校准要依次写入的两个请求：先清确认寄存器，再发命令
+pub fn calibration_requests() -> [[u8; REQUEST_LEN]; 2] {
+    [
+        write_request(HR_ACK, 0),
+        write_request(HR_COMMAND, BACKGROUND_CALIBRATION),
+    ]
+}
+
+/// 读确认寄存器的请求：`FE 03 00 00 00 01 90 05`
+pub fn read_ack_request() -> [u8; REQUEST_LEN] {
+    request(READ_HOLDING_REGISTERS, HR_ACK, 1)
+}
+
+/// 设置 ABC 的请求，`hours` 为 0 时关闭
+pub fn abc_request(hours: u16) -> [u8; REQUEST_LEN] {
+    write_request(HR_ABC_PERIOD, hours)
+}
+
+// 校验整帧最后两个字节的 CRC
+fn check_crc(frame: &[u8]) -> Result<(), ProtocolError> {
+    let (body, tail) = frame.split_at(frame.len() - 2);
+    let expected = crc16(body);
+    let actual = u16::from_le_bytes([tail[0], tail[1]]);
+    if expected != actual {
+        return Err(ProtocolError::Checksum { expected, actual });
+    }
+    Ok(())
+}
+
+/// 检查响应的前 5 字节，返回还要再读多少字节
+///
+/// 异常响应正好 5 字节，校验后返回 `Exception`。
+pub fn response_head(head: &[u8; EXCEPTION_LEN], function: u8) -> Result<usize, ProtocolError> {
+    if head[0] != ANY_ADDRESS {
+        return Err(ProtocolError::Unexpected);
+    }
+    if head[1] == function | EXCEPTION {
+        check_crc(head)?;
+        return Err(ProtocolError::Exception(head[2]));
+    }
+    match head[1] {
+        f if f == function && (f == READ_INPUT_REGISTERS || f == READ_HOLDING_REGISTERS) => {
+            Ok(READ_RESPONSE_LEN - EXCEPTION_LEN)
+        }
+        f if f == function && f == WRITE_SINGLE_REGISTER => Ok(REQUEST_LEN - EXCEPTION_LEN),
+        _ => Err(ProtocolError::Unexpected),
+    }
+}
+
+// 解析读 1 个寄存器的完整响应
+fn parse_register(frame: &[u8; READ_RESPONSE_LEN], function: u8) -> Result<u16, ProtocolError> {
+    check_crc(frame)?;
+    if frame[1] != function || frame[2] != 2 {
+        return Err(ProtocolError::Unexpected);
+    }
+    Ok(u16::from_be_bytes([frame[3], frame[4]]))
+}
+
+/// 解析读 CO2 的完整响应
+pub fn parse_co2(frame: &[u8; READ_RESPONSE_LEN]) -> Result<u16, ProtocolError> {
+    parse_register(frame, READ_INPUT_REGISTERS)
+}
+
+/// 解析读确认寄存器的响应，返回背景校准是否已经完成
+pub fn calibration_acknowledged(frame: &[u8; READ_RESPONSE_LEN]) -> Result<bool, ProtocolError> {
+    Ok(parse_register(frame, READ_HOLDING_REGISTERS)? & ACK_BACKGROUND_CALIBRATION != 0)
+}
+
+/// 检查写寄存器的回显是否与请求一致
+pub fn check_echo(
+    request: &[u8; REQUEST_LEN],
+    frame: &[u8; REQUEST_LEN],
+) -> Result<(), ProtocolError> {
+    check_crc(frame)?;
+    if frame[1..6] != request[1..6] {
+        return Err(ProtocolError::Unexpected);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_documented_requests() {
+        assert_eq!(
+            read_co2_request(),
+            [0xFE, 0x04, 0x00, 0x03, 0x00, 0x01, 0xD5, 0xC5]
+        );
+        assert_eq!(
+            abc_request(0),
+            [0xFE, 0x06, 0x00, 0x1F, 0x00, 0x00, 0xAC, 0x03]
+        );
+        assert_eq!(
+            calibration_requests()[1],
+            [0xFE, 0x06, 0x00, 0x01, 0x7C, 0x06, 0x6C, 0xC7]
+        );
+        assert_eq!(
+            read_ack_request(),
+            [0xFE, 0x03, 0x00, 0x00, 0x00, 0x01, 0x90, 0x05]
+        );
+    }
+
+    #[test]
+    fn parses_responses() {
+        let frame = [0xFE, 0x04, 0x02, 0x01, 0x90, 0xAC, 0xD8];
+        let head: [u8; 5] = frame[..5].try_into().unwrap();
+        assert_eq!(response_head(&head, 0x04), Ok(2));
+        assert_eq!(parse_co2(&frame), Ok(400));
+
+        let mut bad = frame;
+        bad[4] = 0x91;
+        assert!(matches!(
+            parse_co2(&bad),
+            Err(ProtocolError::Checksum { .. })
+        ));
+
+        // 非法数据地址
+        let exception = [0xFE, 0x84, 0x02, 0xF2, 0xF1];
+        assert_eq!(
+            response_head(&exception, 0x04),
+            Err(ProtocolError::Exception(0x02))
+        );
+    }
+}
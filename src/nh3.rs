@@ -0,0 +1,221 @@
+//! 模拟输出的氨气 (NH3) 传感器模块 (MQ-137 一类的 MOS 传感器)
+//!
+//! 模块的 AOUT 是负载电阻 RL 上的电压：Vout = Vc × RL / (Rs + RL)，
+//! 由此算出传感器电阻 Rs，再用 Rs/R0 (R0 为洁净空气中标定的值) 查数据手册的
+//! 灵敏度曲线得到 ppm。加热丝上电后要预热一段时间读数才稳定，预热期间的读数
+//! 标记为 `Quality::WarmingUp`，不参与统计。
+
+use alloc::{vec, vec::Vec};
+
+use crate::adc::{self, AnalogInput, Curve, Divider};
+use crate::sensor::{Measurement, Quality, Sensor, Unit};
+
+/// 上传 JSON 里的字段名
+pub const METRIC: &str = "nh3";
+
+/// MQ-137 的 NH3 灵敏度曲线 (Rs/R0 → ppm)，取自数据手册拟合的 ppm = 102.2 × (Rs/R0)^-2.473
+pub const MQ137: Curve = Curve(&[
+    (0.4, 985.3),
+    (0.5, 567.4),
+    (0.6, 361.5),
+    (0.8, 177.5),
+    (1.0, 102.2),
+    (1.25, 58.9),
+    (1.5, 37.5),
+    (2.0, 18.4),
+    (2.5, 10.6),
+    (3.0, 6.8),
+    (3.6, 4.3),
+]);
+
+/// MQ-137 在洁净空气中的 Rs/R0
+pub const MQ137_CLEAN_AIR_RATIO: f32 = 3.6;
+
+/// 模块电路参数和标定值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// 模块供电电压 Vc (mV)
+    pub supply_mv: f32,
+    /// 模块上的负载电阻 RL
+    pub load_ohms: f32,
+    /// 洁净空气中 Rs 除以 clean air ratio 得到的 R0
+    pub r0_ohms: f32,
+    /// Rs/R0 → ppm 的灵敏度曲线
+    pub curve: Curve,
+}
+
+impl Calibration {
+    /// 由 AOUT 电压算出传感器电阻 Rs；电压不在 (0, Vc) 之间时说明接线有问题
+    pub fn resistance(&self, aout_mv: f32) -> Option<f32> {
+        if aout_mv <= 0.0 || aout_mv >= self.supply_mv {
+            return None;
+        }
+        Some(self.load_ohms * (self.supply_mv - aout_mv) / aout_mv)
+    }
+
+    /// 由 AOUT 电压算出 NH3 浓度
+    pub fn ppm(&self, aout_mv: f32) -> Option<f32> {
+        let rs = self.resistance(aout_mv)?;
+        Some(self.curve.lookup(rs / self.r0_ohms))
+    }
+}
+
+/// 在洁净空气中读到的 Rs 换算成 R0，用来标定 `Calibration::r0_ohms`
+pub fn r0_from_clean_air(rs_ohms: f32, clean_air_ratio: f32) -> f32 {
+    rs_ohms / clean_air_ratio
+}
+
+/// 通过 ADC 读取的 NH3 模块
+///
+/// `uptime_ms` 返回上电以来的毫秒数，用来判断加热丝是否预热完成。
+pub struct Nh3Sensor<A> {
+    input: A,
+    calibration: Calibration,
+    divider: Divider,
+    oversample: usize,
+    warm_up_ms: u64,
+    uptime_ms: fn() -> u64,
+}
+
+impl<A: AnalogInput> Nh3Sensor<A> {
+    pub fn new(input: A, calibration: Calibration, uptime_ms: fn() -> u64) -> Self {
+        Self {
+            input,
+            calibration,
+            divider: Divider::NONE,
+            oversample: 1,
+            warm_up_ms: 0,
+            uptime_ms,
+        }
+    }
+
+    /// AOUT 经过分压再接到 ADC (5 V 模块接 3.3 V 的 ADC)
+    pub fn with_divider(mut self, divider: Divider) -> Self {
+        self.divider = divider;
+        self
+    }
+
+    /// 每次采样读 `n` 次 ADC 求平均
+    pub fn with_oversample(mut self, n: usize) -> Self {
+        self.oversample = n.max(1);
+        self
+    }
+
+    /// 上电后 `ms` 毫秒内视为预热中
+    pub fn with_warm_up_ms(mut self, ms: u64) -> Self {
+        self.warm_up_ms = ms;
+        self
+    }
+
+    pub fn is_warming_up(&self) -> bool {
+        (self.uptime_ms)() < self.warm_up_ms
+    }
+
+    /// 读取 AOUT 电压 (mV，已还原分压)
+    pub fn read_mv(&mut self) -> Option<f32> {
+        adc::oversample(&mut self.input, self.oversample).map(|mv| self.divider.input_mv(mv))
+    }
+
+    /// 读取当前的传感器电阻 Rs，在洁净空气中读它来标定 R0
+    pub fn read_resistance(&mut self) -> Option<f32> {
+        let mv = self.read_mv()?;
+        self.calibration.resistance(mv)
+    }
+}
+
+impl<A: AnalogInput> Sensor for Nh3Sensor<A> {
+    fn name(&self) -> &'static str {
+        "NH3"
+    }
+
+    async fn sample(&mut self) -> Vec<Measurement> {
+        let m = match self.read_mv().and_then(|mv| self.calibration.ppm(mv)) {
+            // 预热期间的读数不参与统计，只在 flags 里计数
+            Some(ppm) if self.is_warming_up() => {
+                Measurement::new(METRIC, ppm, Unit::Ppm).with_quality(Quality::WarmingUp)
+            }
+            Some(ppm) => Measurement::new(METRIC, ppm, Unit::Ppm),
+            None => Measurement::invalid(METRIC, Unit::Ppm),
+        };
+        vec![m]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use std::cell::Cell;
+
+    thread_local! {
+        static UPTIME_MS: Cell<u64> = const { Cell::new(0) };
+    }
+
+    fn uptime_ms() -> u64 {
+        UPTIME_MS.with(Cell::get)
+    }
+
+    // 一直返回同一个读数
+    struct Fixed(u16);
+
+    impl AnalogInput for Fixed {
+        type Error = ();
+
+        fn read_mv(&mut self) -> Result<u16, ()> {
+            Ok(self.0)
+        }
+    }
+
+    const CAL: Calibration = Calibration {
+        supply_mv: 5000.0,
+        load_ohms: 10_000.0,
+        r0_ohms: 10_000.0,
+        curve: MQ137,
+    };
+
+    #[test]
+    fn curve_interpolates_and_clamps() {
+        assert_eq!(MQ137.lookup(1.0), 102.2);
+        assert!((MQ137.lookup(2.25) - 14.5).abs() < 0.01);
+        assert_eq!(MQ137.lookup(0.1), 985.3);
+        assert_eq!(MQ137.lookup(10.0), 4.3);
+    }
+
+    #[test]
+    fn converts_aout_to_ppm() {
+        // Vout = Vc/2 → Rs = RL = R0 → Rs/R0 = 1
+        assert_eq!(CAL.resistance(2500.0), Some(10_000.0));
+        assert_eq!(CAL.ppm(2500.0), Some(102.2));
+        // Vout = Vc/3 → Rs = 2 RL
+        assert!((CAL.ppm(5000.0 / 3.0).unwrap() - 18.4).abs() < 0.01);
+        // 断线 (0 V) 或短路 (Vc)
+        assert_eq!(CAL.ppm(0.0), None);
+        assert_eq!(CAL.ppm(5000.0), None);
+
+        assert_eq!(r0_from_clean_air(36_000.0, MQ137_CLEAN_AIR_RATIO), 10_000.0);
+    }
+
+    #[test]
+    fn flags_readings_while_warming_up() {
+        // 5 V 模块经 10k/20k 分压后接 ADC：ADC 上 1667 mV ≈ AOUT 2500 mV
+        let mut nh3 = Nh3Sensor::new(Fixed(1667), CAL, uptime_ms)
+            .with_divider(Divider {
+                top_ohms: 10_000.0,
+                bottom_ohms: 20_000.0,
+            })
+            .with_oversample(8)
+            .with_warm_up_ms(180_000);
+
+        UPTIME_MS.with(|t| t.set(60_000));
+        let out = block_on(nh3.sample());
+        assert_eq!(
+            (out[0].metric, out[0].quality),
+            (METRIC, Quality::WarmingUp)
+        );
+
+        UPTIME_MS.with(|t| t.set(180_000));
+        let out = block_on(nh3.sample());
+        assert_eq!(out[0].quality, Quality::Good);
+        assert!((out[0].value - 102.2).abs() < 0.5);
+    }
+}
@@ -0,0 +1,457 @@
+//! 1-Wire 总线驱动
+//!
+//! 分两层：`OneWireBus` 只负责复位和读写时隙，可以是 GPIO 位翻转 (`BitBang`)
+//! 或 RMT 外设 (`rmt::RmtOneWire`)；`OneWire` 在其上实现字节读写、ROM 搜索/匹配
+//! 和寄生供电处理，与具体硬件无关。
+
+#[cfg(all(target_arch = "riscv32", feature = "rmt-onewire"))]
+pub mod rmt;
+#[cfg(test)]
+pub(crate) mod sim;
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+};
+
+use crate::ds18b20::crc8;
+
+/// 64 位 ROM 码，按总线发送顺序存放 (rom[0] 为家族码，rom[7] 为 CRC)
+pub type Rom = [u8; 8];
+
+const SEARCH_ROM: u8 = 0xF0;
+const MATCH_ROM: u8 = 0x55;
+const SKIP_ROM: u8 = 0xCC;
+const READ_POWER_SUPPLY: u8 = 0xB4;
+
+/// 时隙没能正确发出或录下 (例如 RMT 传输出错)，这时读到的数据不可信
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusError;
+
+/// 1-Wire 的底层时隙
+pub trait OneWireBus {
+    /// 复位脉冲，返回是否有器件发出存在脉冲
+    fn reset(&mut self) -> bool;
+
+    fn write_bit(&mut self, bit: bool);
+
+    fn read_bit(&mut self) -> bool;
+
+    /// 低位先行写一个字节
+    fn write_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            self.write_bit((byte >> i) & 0x01 != 0);
+        }
+    }
+
+    /// 低位先行读一个字节
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = 0;
+        for i in 0..8 {
+            if self.read_bit() {
+                byte |= 1 << i;
+            }
+        }
+        byte
+    }
+
+    /// 写一个字节，写完后立即主动推高总线 (数据手册要求 10us 内)，
+    /// 给寄生供电的器件提供转换/写 EEPROM 的电流
+    fn write_byte_strong_pullup(&mut self, byte: u8);
+
+    /// 结束强上拉，恢复开漏释放状态
+    fn end_strong_pullup(&mut self);
+
+    /// 取出上次复位以来记下的硬件错误并清除；不会出错的实现 (位翻转) 总是 `Ok`
+    fn take_error(&mut self) -> Result<(), BusError> {
+        Ok(())
+    }
+}
+
+/// 能临时改为推挽输出高电平的引脚，用来给寄生供电的器件做强上拉
+pub trait StrongPullup {
+    fn set_strong_pullup(&mut self, on: bool);
+}
+
+/// 标准时隙参数 (Maxim AN126，单位 us)
+const RESET_LOW_US: u32 = 480;
+const PRESENCE_SAMPLE_US: u32 = 70;
+const RESET_RECOVERY_US: u32 = 410;
+const WRITE1_LOW_US: u32 = 6;
+const WRITE1_HIGH_US: u32 = 64;
+const WRITE0_LOW_US: u32 = 60;
+const WRITE0_HIGH_US: u32 = 10;
+const READ_LOW_US: u32 = 6;
+const READ_SAMPLE_US: u32 = 9;
+const READ_RECOVERY_US: u32 = 55;
+
+/// GPIO 位翻转实现
+///
+/// 引脚必须配置为开漏：`set_low` 拉低总线，`set_high` 释放总线 (靠外部 4.7k 上拉)，
+/// 同时输入常开用于采样。每个时隙在 `critical_section` 里完成，保证时序不被打断。
+pub struct BitBang<P, D> {
+    pin: P,
+    delay: D,
+}
+
+impl<P, D> BitBang<P, D>
+where
+    P: InputPin + OutputPin + StrongPullup,
+    D: DelayNs,
+{
+    pub fn new(mut pin: P, delay: D) -> Self {
+        // 初始状态：释放总线 (High)
+        let _ = pin.set_high();
+        Self { pin, delay }
+    }
+
+    #[inline(always)]
+    fn drive_low(&mut self) {
+        let _ = self.pin.set_low();
+    }
+
+    #[inline(always)]
+    fn release_high(&mut self) {
+        let _ = self.pin.set_high();
+    }
+
+    #[inline(always)]
+    fn is_high(&mut self) -> bool {
+        self.pin.is_high().unwrap_or(true)
+    }
+}
+
+impl<P, D> OneWireBus for BitBang<P, D>
+where
+    P: InputPin + OutputPin + StrongPullup,
+    D: DelayNs,
+{
+    fn reset(&mut self) -> bool {
+        critical_section::with(|_| {
+            self.drive_low();
+            self.delay.delay_us(RESET_LOW_US);
+            self.release_high();
+            self.delay.delay_us(PRESENCE_SAMPLE_US);
+            let presence = !self.is_high();
+            self.delay.delay_us(RESET_RECOVERY_US);
+            presence
+        })
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        critical_section::with(|_| {
+            self.drive_low();
+            if bit {
+                // 写 1: 拉低很短时间，然后释放
+                self.delay.delay_us(WRITE1_LOW_US);
+                self.release_high();
+                self.delay.delay_us(WRITE1_HIGH_US);
+            } else {
+                // 写 0: 拉低整个时隙
+                self.delay.delay_us(WRITE0_LOW_US);
+                self.release_high();
+                self.delay.delay_us(WRITE0_HIGH_US);
+            }
+        });
+    }
+
+    fn read_bit(&mut self) -> bool {
+        critical_section::with(|_| {
+            self.drive_low();
+            self.delay.delay_us(READ_LOW_US);
+            self.release_high();
+            self.delay.delay_us(READ_SAMPLE_US);
+            let bit = self.is_high();
+            self.delay.delay_us(READ_RECOVERY_US);
+            bit
+        })
+    }
+
+    fn write_byte_strong_pullup(&mut self, byte: u8) {
+        for i in 0..7 {
+            self.write_bit((byte >> i) & 0x01 != 0);
+        }
+        critical_section::with(|_| {
+            self.drive_low();
+            if byte & 0x80 != 0 {
+                self.delay.delay_us(WRITE1_LOW_US);
+            } else {
+                self.delay.delay_us(WRITE0_LOW_US);
+            }
+            // 不经过释放状态，直接由低电平切换为推挽输出高电平
+            self.pin.set_strong_pullup(true);
+        });
+    }
+
+    fn end_strong_pullup(&mut self) {
+        self.pin.set_strong_pullup(false);
+        self.release_high();
+    }
+}
+
+/// 与具体时隙实现无关的 1-Wire 主机
+pub struct OneWire<B> {
+    bus: B,
+    // 总线上是否有寄生供电 (只接 DQ 和 GND) 的器件
+    parasite: bool,
+}
+
+impl<B: OneWireBus> OneWire<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            parasite: false,
+        }
+    }
+
+    pub fn reset(&mut self) -> bool {
+        self.bus.reset()
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        self.bus.write_bit(bit);
+    }
+
+    pub fn read_bit(&mut self) -> bool {
+        self.bus.read_bit()
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        self.bus.write_byte(byte);
+    }
+
+    pub fn read_byte(&mut self) -> u8 {
+        self.bus.read_byte()
+    }
+
+    /// 上次复位以来的时隙有没有出错，读完之后清除
+    pub fn take_error(&mut self) -> Result<(), BusError> {
+        self.bus.take_error()
+    }
+
+    /// Skip ROM：复位后对总线上所有器件广播
+    pub fn skip(&mut self) {
+        self.write_byte(SKIP_ROM);
+    }
+
+    /// Match ROM：复位后只选中指定 ROM 码的器件
+    pub fn select(&mut self, rom: &Rom) {
+        self.write_byte(MATCH_ROM);
+        for &b in rom {
+            self.write_byte(b);
+        }
+    }
+
+    /// Search ROM (0xF0)：按 Maxim AN187 的二叉树搜索枚举总线上所有器件
+    ///
+    /// 每一位先读“该位”和“该位的反码”两个时隙：01 / 10 表示所有器件一致，
+    /// 00 表示有冲突，11 表示没有器件应答。然后写回选中的方向，不匹配的器件退出本轮。
+    /// 返回找到的器件数量；CRC 不正确的 ROM 码会被丢弃。
+    pub fn search(&mut self, roms: &mut [Rom]) -> usize {
+        let mut count = 0;
+        let mut rom = [0u8; 8];
+        // 上一轮最后一个“选了 0”的冲突位 (1..=64)，0 表示没有剩余分支
+        let mut last_discrepancy = 0u8;
+
+        while count < roms.len() {
+            if !self.reset() {
+                break;
+            }
+            self.write_byte(SEARCH_ROM);
+
+            let mut last_zero = 0u8;
+            for bit_index in 1..=64u8 {
+                let id_bit = self.read_bit();
+                let cmp_bit = self.read_bit();
+                if id_bit && cmp_bit {
+                    // 没有器件参与本轮搜索 (掉线或干扰)
+                    return count;
+                }
+
+                let byte = ((bit_index - 1) / 8) as usize;
+                let mask = 1u8 << ((bit_index - 1) % 8);
+                let direction = if id_bit != cmp_bit {
+                    id_bit
+                } else {
+                    // 冲突：在上次分叉点之前沿用上一轮路径，到分叉点改走 1，之后先走 0
+                    let dir = if bit_index < last_discrepancy {
+                        rom[byte] & mask != 0
+                    } else {
+                        bit_index == last_discrepancy
+                    };
+                    if !dir {
+                        last_zero = bit_index;
+                    }
+                    dir
+                };
+
+                if direction {
+                    rom[byte] |= mask;
+                } else {
+                    rom[byte] &= !mask;
+                }
+                self.write_bit(direction);
+            }
+            // 时隙出错时这一轮的路径不可信，已经找到的保留
+            if self.take_error().is_err() {
+                return count;
+            }
+
+            // ROM 码最后一个字节是前 7 字节的 CRC8，不对就丢弃这一条
+            if crc8(&rom[..7]) == rom[7] {
+                roms[count] = rom;
+                count += 1;
+            }
+
+            last_discrepancy = last_zero;
+            if last_discrepancy == 0 {
+                break;
+            }
+        }
+        count
+    }
+
+    /// Read Power Supply (0xB4)：寄生供电的器件会在随后的读时隙里把总线拉低
+    ///
+    /// 用 Skip ROM 一次检查整条总线，结果用于之后的 `write_power_command`。
+    pub fn detect_parasite_power(&mut self) -> bool {
+        self.parasite = false;
+        if self.reset() {
+            self.skip();
+            self.write_byte(READ_POWER_SUPPLY);
+            self.parasite = !self.read_bit() && self.take_error().is_ok();
+        }
+        self.parasite
+    }
+
+    pub fn is_parasite(&self) -> bool {
+        self.parasite
+    }
+
+    /// 写入需要大电流的命令 (Convert T / Copy Scratchpad)
+    ///
+    /// 寄生供电时写完立刻开启强上拉，之后必须调用 `end_strong_pullup`。
+    pub fn write_power_command(&mut self, byte: u8) {
+        if self.parasite {
+            self.bus.write_byte_strong_pullup(byte);
+        } else {
+            self.bus.write_byte(byte);
+        }
+    }
+
+    pub fn end_strong_pullup(&mut self) {
+        if self.parasite {
+            self.bus.end_strong_pullup();
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+mod esp {
+    use esp_hal::gpio::{DriveMode, Flex, OutputConfig, Pull};
+
+    use super::StrongPullup;
+
+    /// 把 GPIO 配置成 1-Wire 需要的开漏 + 输入常开，初始释放总线
+    pub fn open_drain(mut pin: Flex<'_>) -> Flex<'_> {
+        pin.apply_output_config(&open_drain_config());
+        pin.set_input_enable(true);
+        pin.set_high();
+        pin.set_output_enable(true);
+        pin
+    }
+
+    fn open_drain_config() -> OutputConfig {
+        // 板载 4.7k 上拉电阻，不开内部上拉
+        OutputConfig::default()
+            .with_drive_mode(DriveMode::OpenDrain)
+            .with_pull(Pull::None)
+    }
+
+    impl StrongPullup for Flex<'_> {
+        fn set_strong_pullup(&mut self, on: bool) {
+            if on {
+                self.set_high();
+                self.apply_output_config(&OutputConfig::default());
+            } else {
+                self.apply_output_config(&open_drain_config());
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+pub use esp::open_drain;
+
+#[cfg(test)]
+mod tests {
+    use super::sim::{self, SimDevice};
+    use super::*;
+
+    fn rom(family: u8, serial: u64) -> Rom {
+        let mut rom = [0u8; 8];
+        rom[0] = family;
+        rom[1..7].copy_from_slice(&serial.to_le_bytes()[..6]);
+        rom[7] = crc8(&rom[..7]);
+        rom
+    }
+
+    #[test]
+    fn reset_reports_presence() {
+        let (bus, _) = sim::bus(vec![SimDevice::new(rom(0x28, 1))]);
+        let mut ow = OneWire::new(bus);
+        assert!(ow.reset());
+
+        let (bus, _) = sim::bus(vec![]);
+        let mut ow = OneWire::new(bus);
+        assert!(!ow.reset());
+    }
+
+    #[test]
+    fn search_finds_every_device() {
+        let roms = [
+            rom(0x28, 0x0000_1E64_FF01),
+            rom(0x28, 0x0000_1E64_FF02),
+            rom(0x28, 0x8000_0000_0003),
+            rom(0x10, 0x0000_0000_0042),
+        ];
+        let (bus, _) = sim::bus(roms.iter().map(|&r| SimDevice::new(r)).collect());
+        let mut ow = OneWire::new(bus);
+
+        let mut found = [[0u8; 8]; 8];
+        let count = ow.search(&mut found);
+        assert_eq!(count, roms.len());
+        for r in &roms {
+            assert!(found[..count].contains(r), "missing {r:02X?}");
+        }
+    }
+
+    #[test]
+    fn search_respects_output_capacity() {
+        let (bus, _) = sim::bus((1..=3).map(|i| SimDevice::new(rom(0x28, i))).collect());
+        let mut ow = OneWire::new(bus);
+        let mut found = [[0u8; 8]; 2];
+        assert_eq!(ow.search(&mut found), 2);
+    }
+
+    #[test]
+    fn search_on_empty_bus() {
+        let (bus, _) = sim::bus(vec![]);
+        let mut ow = OneWire::new(bus);
+        let mut found = [[0u8; 8]; 4];
+        assert_eq!(ow.search(&mut found), 0);
+    }
+
+    #[test]
+    fn detects_parasite_power() {
+        let (bus, _) = sim::bus(vec![SimDevice::new(rom(0x28, 1))]);
+        let mut ow = OneWire::new(bus);
+        assert!(!ow.detect_parasite_power());
+
+        let mut device = SimDevice::new(rom(0x28, 2));
+        device.parasite = true;
+        let (bus, _) = sim::bus(vec![SimDevice::new(rom(0x28, 1)), device]);
+        let mut ow = OneWire::new(bus);
+        assert!(ow.detect_parasite_power());
+    }
+}
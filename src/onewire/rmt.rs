@@ -0,0 +1,244 @@
+//! 基于 RMT 外设的 1-Wire 总线 (cargo feature `rmt-onewire`)
+//!
+//! 位翻转驱动需要在 `critical_section` 里用 `Delay` 掐时间，复位一次就要关中断近 1ms。
+//! 这里改由 RMT 硬件产生时隙：TX 通道输出低脉冲，RX 通道同时在同一个引脚上记录总线，
+//! 根据每个低电平的宽度判断存在脉冲和读到的位，全程不关中断。
+//!
+//! 引脚配置为开漏，TX 空闲输出高电平就等于释放总线，仍然依靠外部 4.7k 上拉。
+
+use esp_hal::{
+    gpio::{DriveMode, Flex, Level, OutputConfig, Pull},
+    peripherals::{GPIO, RMT},
+    rmt::{
+        Channel, Error, PulseCode, Rmt, Rx, RxChannelConfig, RxChannelCreator, Tx, TxChannelConfig,
+        TxChannelCreator,
+    },
+    time::Rate,
+    Blocking,
+};
+
+use super::{BusError, OneWireBus};
+
+// RMT 时钟 80 MHz，通道再 80 分频：1 tick = 1 µs
+const RMT_FREQ_MHZ: u32 = 80;
+const CLK_DIVIDER: u8 = 80;
+// 总线超过 100 µs 没有跳变就结束接收 (比时隙之间的高电平都长)
+const RX_IDLE_US: u16 = 100;
+// 过滤短于 10 个 RMT 时钟 (125 ns) 的毛刺
+const RX_FILTER_TICKS: u8 = 10;
+// 读时隙里低电平不超过 15 µs 说明器件没有拉低，读到 1
+const READ_SAMPLE_US: u16 = 15;
+// 一次接收最多记录的脉冲数 (一个字节 8 个时隙，留出余量)
+const RX_CODES: usize = 16;
+
+// 与位翻转驱动相同的时隙参数 (µs)
+const RESET_LOW_US: u16 = 480;
+const RESET_WAIT_US: u16 = 480;
+const WRITE1_LOW_US: u16 = 6;
+const WRITE1_HIGH_US: u16 = 64;
+const WRITE0_LOW_US: u16 = 60;
+const WRITE0_HIGH_US: u16 = 10;
+const READ_LOW_US: u16 = 6;
+const READ_HIGH_US: u16 = 64;
+
+/// 用 RMT 的 TX/RX 通道驱动一根 1-Wire 总线
+pub struct RmtOneWire<'d> {
+    tx: Channel<'d, Blocking, Tx>,
+    rx: Channel<'d, Blocking, Rx>,
+    // 用于切换开漏/推挽 (强上拉)
+    gpio: u8,
+    // 上次复位以来第一个传输错误
+    error: Option<Error>,
+}
+
+impl<'d> RmtOneWire<'d> {
+    /// 占用 RMT 的通道 0 (TX) 和通道 2 (RX)，两者都连到同一个引脚；
+    /// 引脚没有 GPIO 编号时返回 `Error::InvalidArgument`
+    pub fn new(rmt: RMT<'d>, mut pin: Flex<'d>) -> Result<Self, Error> {
+        // 开漏输出 + 输入常开：TX 输出高电平时释放总线，RX 同时能看到器件拉低
+        pin.apply_output_config(
+            &OutputConfig::default()
+                .with_drive_mode(DriveMode::OpenDrain)
+                .with_pull(Pull::None),
+        );
+        pin.set_high();
+        pin.set_output_enable(true);
+
+        // 两个信号都是 frozen 的，RMT 驱动不会改回推挽配置
+        let input = pin.peripheral_input();
+        // 强上拉要按编号改引脚的寄存器，拿不到编号就不能用
+        let gpio = input.gpio_number().ok_or(Error::InvalidArgument)?;
+        let output = pin.into_peripheral_output();
+
+        let rmt = Rmt::new(rmt, Rate::from_mhz(RMT_FREQ_MHZ))?;
+        let tx = rmt.channel0.configure_tx(
+            output,
+            TxChannelConfig::default()
+                .with_clk_divider(CLK_DIVIDER)
+                .with_idle_output(true)
+                .with_idle_output_level(Level::High),
+        )?;
+        let rx = rmt.channel2.configure_rx(
+            input,
+            RxChannelConfig::default()
+                .with_clk_divider(CLK_DIVIDER)
+                .with_filter_threshold(RX_FILTER_TICKS)
+                .with_idle_threshold(RX_IDLE_US),
+        )?;
+
+        Ok(Self {
+            tx,
+            rx,
+            gpio,
+            error: None,
+        })
+    }
+
+    /// 强上拉：把引脚切成推挽，TX 空闲的高电平就会主动驱动总线
+    pub fn set_strong_pullup(&mut self, on: bool) {
+        GPIO::regs()
+            .pin(self.gpio as usize)
+            .modify(|_, w| w.pad_driver().bit(!on));
+    }
+
+    // 只发送，不需要采样
+    fn transmit(&mut self, slots: &[PulseCode]) {
+        let result = self
+            .tx
+            .reborrow()
+            .transmit(slots)
+            .and_then(|tx| tx.wait().map_err(|(e, _)| e));
+        if let Err(e) = result {
+            self.error.get_or_insert(e);
+        }
+    }
+
+    // 发送一串时隙并记录总线，出错时记下来 (由 take_error 交给上层)
+    fn transfer(&mut self, slots: &[PulseCode], lows: &mut [u16]) -> Result<usize, Error> {
+        let result = self.record(slots, lows);
+        if let Err(e) = result {
+            self.error.get_or_insert(e);
+        }
+        result
+    }
+
+    // 发送一串时隙，同时用 RX 记录总线上每个低电平的宽度 (µs)，返回低电平个数
+    fn record(&mut self, slots: &[PulseCode], lows: &mut [u16]) -> Result<usize, Error> {
+        let mut codes = [PulseCode::end_marker(); RX_CODES];
+        // 先开始接收，再发送，才能录到自己发出的第一个下降沿
+        let rx = self.rx.reborrow().receive(&mut codes)?;
+        let tx = self.tx.reborrow().transmit(slots)?;
+        tx.wait().map_err(|(e, _)| e)?;
+        let (total, _) = rx.wait().map_err(|(e, _)| e)?;
+
+        let mut count = 0;
+        for code in &codes[..total.min(RX_CODES)] {
+            for (level, length) in [
+                (code.level1(), code.length1()),
+                (code.level2(), code.length2()),
+            ] {
+                // 长度为 0 表示接收结束
+                if length == 0 {
+                    return Ok(count);
+                }
+                if level == Level::Low && count < lows.len() {
+                    lows[count] = length;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl OneWireBus for RmtOneWire<'_> {
+    /// 复位脉冲，返回是否检测到存在脉冲
+    fn reset(&mut self) -> bool {
+        let slots = [
+            PulseCode::new(Level::Low, RESET_LOW_US, Level::High, RESET_WAIT_US),
+            PulseCode::end_marker(),
+        ];
+        let mut lows = [0u16; RX_CODES];
+        self.error = None;
+        // 第一个低电平是自己发出的复位脉冲，之后还有低电平就是器件的存在脉冲
+        matches!(self.transfer(&slots, &mut lows), Ok(n) if n >= 2)
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        let slots = [write_slot(bit), PulseCode::end_marker()];
+        self.transmit(&slots);
+    }
+
+    fn read_bit(&mut self) -> bool {
+        let slots = [read_slot(), PulseCode::end_marker()];
+        let mut lows = [0u16; RX_CODES];
+        match self.transfer(&slots, &mut lows) {
+            Ok(n) if n >= 1 => lows[0] <= READ_SAMPLE_US,
+            // 出错时按总线空闲 (全 1) 处理，错误由 take_error 报告
+            _ => true,
+        }
+    }
+
+    /// 一次 RMT 传输发完整个字节 (低位先行)
+    fn write_byte(&mut self, byte: u8) {
+        let mut slots = [PulseCode::end_marker(); 9];
+        for (i, slot) in slots.iter_mut().take(8).enumerate() {
+            *slot = write_slot((byte >> i) & 0x01 != 0);
+        }
+        self.transmit(&slots);
+    }
+
+    /// 一次 RMT 传输读完整个字节 (低位先行)
+    fn read_byte(&mut self) -> u8 {
+        let mut slots = [read_slot(); 9];
+        slots[8] = PulseCode::end_marker();
+        let mut lows = [0u16; RX_CODES];
+        let n = self.transfer(&slots, &mut lows).unwrap_or(0);
+
+        let mut byte = 0;
+        for (i, &low) in lows.iter().take(8).enumerate() {
+            // 缺失的时隙同样按 1 处理
+            if i >= n || low <= READ_SAMPLE_US {
+                byte |= 1 << i;
+            }
+        }
+        byte
+    }
+
+    /// 前 7 位照常发送，切成推挽后再发最后一个时隙：它的上升沿和之后的高电平都由引脚主动
+    /// 驱动，强上拉从最后一个时隙结束 (0 µs) 就开始，不用等 RMT 传输结束、`wait` 返回
+    /// (那要多出整个 64 µs 恢复时间再加软件延迟，超过数据手册的 10 µs)。写时隙里只有主机
+    /// 驱动总线，推挽输出低电平不会和器件冲突；时隙之间的间隔没有上限，分两次发送不影响器件。
+    fn write_byte_strong_pullup(&mut self, byte: u8) {
+        let mut slots = [PulseCode::end_marker(); 8];
+        for (i, slot) in slots.iter_mut().take(7).enumerate() {
+            *slot = write_slot((byte >> i) & 0x01 != 0);
+        }
+        self.transmit(&slots);
+        self.set_strong_pullup(true);
+        self.transmit(&[write_slot(byte & 0x80 != 0), PulseCode::end_marker()]);
+    }
+
+    fn end_strong_pullup(&mut self) {
+        self.set_strong_pullup(false);
+    }
+
+    fn take_error(&mut self) -> Result<(), BusError> {
+        match self.error.take() {
+            Some(_) => Err(BusError),
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_slot(bit: bool) -> PulseCode {
+    if bit {
+        PulseCode::new(Level::Low, WRITE1_LOW_US, Level::High, WRITE1_HIGH_US)
+    } else {
+        PulseCode::new(Level::Low, WRITE0_LOW_US, Level::High, WRITE0_HIGH_US)
+    }
+}
+
+fn read_slot() -> PulseCode {
+    PulseCode::new(Level::Low, READ_LOW_US, Level::High, READ_HIGH_US)
+}
@@ -0,0 +1,381 @@
+//! 在 PC 上模拟 1-Wire 总线和 DS18B20，供单元测试使用
+//!
+//! 主机通过 `SimPin` 拉低/释放总线、`SimDelay` 推进虚拟时间 (ns)。每个模拟器件
+//! 根据下降沿到释放的时长区分复位、写 0 和写 1；需要发送 0 时在下降沿后把总线
+//! 拉低 30us，和真实器件一样由主机在 15us 处采样。
+
+use std::{cell::RefCell, collections::VecDeque, convert::Infallible, rc::Rc, vec::Vec};
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{ErrorType, InputPin, OutputPin},
+};
+
+use super::{BitBang, Rom, StrongPullup};
+use crate::ds18b20::{crc8, Resolution, POWER_ON_RAW};
+
+const US: u64 = 1_000;
+// 至少拉低这么久才算复位脉冲
+const RESET_MIN: u64 = 480 * US;
+// 写时隙里拉低不足 15us 视为写 1
+const WRITE1_MAX: u64 = 15 * US;
+// 器件发送 0 / 存在脉冲时拉低总线的时长
+const DEVICE_HOLD: u64 = 30 * US;
+const PRESENCE_DELAY: u64 = 30 * US;
+const PRESENCE_LEN: u64 = 120 * US;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    // 未被选中，等待下一次复位
+    Idle,
+    RomCommand,
+    MatchRom,
+    // 搜索第 bit 位：phase 0 发该位，1 发反码，2 接收主机选择的方向
+    Search { bit: u8, phase: u8 },
+    FunctionCommand,
+    WriteScratchpad,
+    // 依次发出 tx 队列里的位，发完后读时隙返回 1
+    Transmit,
+    // 温度转换中，读时隙先返回 busy_slots 个 0，再返回 1
+    Converting,
+}
+
+/// 一个模拟的 DS18B20
+pub struct SimDevice {
+    pub rom: Rom,
+    /// 暂存器前 8 字节，读取时自动附加 CRC
+    pub scratchpad: [u8; 8],
+    /// EEPROM 里的 TH、TL、配置寄存器
+    pub eeprom: [u8; 3],
+    /// 只接 DQ 和 GND，转换时需要强上拉
+    pub parasite: bool,
+    /// Convert T 之后写入温度寄存器的原始值 (12 位精度)
+    pub temperature_raw: u16,
+    /// 转换期间返回 0 的读时隙数量
+    pub busy_slots: u32,
+    /// 读暂存器时翻转的位 (模拟总线干扰)
+    pub corrupt_bit: Option<usize>,
+
+    state: State,
+    rx: Vec<bool>,
+    tx: VecDeque<bool>,
+    busy_left: u32,
+    // 寄生供电转换还在等待强上拉
+    awaiting_power: bool,
+    // 正在拉低总线的时间段 [from, until)
+    hold: Option<(u64, u64)>,
+    // 当前时隙由器件发送，释放时不当作写时隙
+    sending: bool,
+}
+
+impl SimDevice {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            rom,
+            scratchpad: [0x50, 0x05, 0x4B, 0x46, 0x7F, 0xFF, 0x0C, 0x10],
+            eeprom: [0x4B, 0x46, 0x7F],
+            parasite: false,
+            temperature_raw: 0x0191,
+            busy_slots: 0,
+            corrupt_bit: None,
+            state: State::Idle,
+            rx: Vec::new(),
+            tx: VecDeque::new(),
+            busy_left: 0,
+            awaiting_power: false,
+            hold: None,
+            sending: false,
+        }
+    }
+
+    fn rom_bit(&self, bit: u8) -> bool {
+        self.rom[(bit / 8) as usize] >> (bit % 8) & 0x01 != 0
+    }
+
+    fn holds_low(&self, now: u64) -> bool {
+        matches!(self.hold, Some((from, until)) if from <= now && now < until)
+    }
+
+    // 主机拉低总线：如果这个时隙该器件发送，就决定要不要拉低
+    fn on_fall(&mut self, now: u64) {
+        let bit = match self.state {
+            State::Transmit => Some(self.tx.pop_front().unwrap_or(true)),
+            State::Converting => {
+                if self.busy_left > 0 {
+                    self.busy_left -= 1;
+                    Some(false)
+                } else {
+                    Some(true)
+                }
+            }
+            State::Search { bit, phase: 0 } => Some(self.rom_bit(bit)),
+            State::Search { bit, phase: 1 } => Some(!self.rom_bit(bit)),
+            _ => None,
+        };
+        self.sending = bit.is_some();
+        if bit == Some(false) {
+            self.hold = Some((now, now + DEVICE_HOLD));
+        }
+        if let State::Search { bit, phase } = self.state {
+            if phase < 2 {
+                self.state = State::Search {
+                    bit,
+                    phase: phase + 1,
+                };
+            }
+        }
+    }
+
+    // 主机释放总线
+    fn on_rise(&mut self, now: u64, low_for: u64) {
+        if low_for >= RESET_MIN {
+            self.state = State::RomCommand;
+            self.rx.clear();
+            self.tx.clear();
+            self.hold = Some((now + PRESENCE_DELAY, now + PRESENCE_DELAY + PRESENCE_LEN));
+            return;
+        }
+        if self.sending {
+            return;
+        }
+        self.receive(low_for < WRITE1_MAX);
+    }
+
+    fn receive(&mut self, bit: bool) {
+        match self.state {
+            State::Idle | State::Transmit | State::Converting => {}
+            State::Search { bit: index, .. } => {
+                if bit != self.rom_bit(index) {
+                    self.state = State::Idle;
+                } else if index == 63 {
+                    self.state = State::FunctionCommand;
+                } else {
+                    self.state = State::Search {
+                        bit: index + 1,
+                        phase: 0,
+                    };
+                }
+            }
+            State::RomCommand
+            | State::FunctionCommand
+            | State::MatchRom
+            | State::WriteScratchpad => {
+                self.rx.push(bit);
+                self.on_bits();
+            }
+        }
+    }
+
+    fn on_bits(&mut self) {
+        let needed = match self.state {
+            State::MatchRom => 64,
+            State::WriteScratchpad => 24,
+            _ => 8,
+        };
+        if self.rx.len() < needed {
+            return;
+        }
+        let bytes: Vec<u8> = self
+            .rx
+            .chunks(8)
+            .map(|c| c.iter().rev().fold(0u8, |acc, &b| (acc << 1) | b as u8))
+            .collect();
+        self.rx.clear();
+
+        match self.state {
+            State::RomCommand => {
+                self.state = match bytes[0] {
+                    0xCC => State::FunctionCommand,
+                    0x55 => State::MatchRom,
+                    0xF0 => State::Search { bit: 0, phase: 0 },
+                    _ => State::Idle,
+                }
+            }
+            State::MatchRom => {
+                self.state = if bytes[..] == self.rom[..] {
+                    State::FunctionCommand
+                } else {
+                    State::Idle
+                }
+            }
+            State::WriteScratchpad => {
+                self.scratchpad[2] = bytes[0];
+                self.scratchpad[3] = bytes[1];
+                self.scratchpad[4] = (bytes[2] & 0x60) | 0x1F;
+                self.state = State::Idle;
+            }
+            State::FunctionCommand => self.function(bytes[0]),
+            _ => {}
+        }
+    }
+
+    fn function(&mut self, command: u8) {
+        match command {
+            // Convert T
+            0x44 => {
+                if self.parasite {
+                    self.awaiting_power = true;
+                } else {
+                    self.convert();
+                }
+                self.busy_left = self.busy_slots;
+                self.state = State::Converting;
+            }
+            // Read Scratchpad
+            0xBE => {
+                let mut data = [0u8; 9];
+                data[..8].copy_from_slice(&self.scratchpad);
+                data[8] = crc8(&self.scratchpad);
+                self.tx = data
+                    .iter()
+                    .flat_map(|&b| (0..8).map(move |i| b >> i & 0x01 != 0))
+                    .collect();
+                if let Some(bit) = self.corrupt_bit {
+                    self.tx[bit] = !self.tx[bit];
+                }
+                self.state = State::Transmit;
+            }
+            // Write Scratchpad
+            0x4E => self.state = State::WriteScratchpad,
+            // Copy Scratchpad
+            0x48 => {
+                self.eeprom.copy_from_slice(&self.scratchpad[2..5]);
+                self.state = State::Idle;
+            }
+            // Read Power Supply
+            0xB4 => {
+                self.tx = VecDeque::from([!self.parasite]);
+                self.state = State::Transmit;
+            }
+            _ => self.state = State::Idle,
+        }
+    }
+
+    fn convert(&mut self) {
+        let resolution = Resolution::from_config(self.scratchpad[4]);
+        let raw = self.temperature_raw & !resolution.undefined_mask();
+        self.scratchpad[0] = raw as u8;
+        self.scratchpad[1] = (raw >> 8) as u8;
+    }
+
+    /// 模拟重新上电：暂存器恢复为 EEPROM 里的值，温度回到 85°C
+    pub fn power_cycle(&mut self) {
+        self.scratchpad[0] = POWER_ON_RAW as u8;
+        self.scratchpad[1] = (POWER_ON_RAW >> 8) as u8;
+        self.scratchpad[2..5].copy_from_slice(&self.eeprom);
+        self.state = State::Idle;
+    }
+}
+
+/// 总线和挂在上面的所有器件
+pub struct SimWire {
+    pub devices: Vec<SimDevice>,
+    now: u64,
+    master_low: bool,
+    fall: u64,
+    strong_pullup: bool,
+    /// 强上拉期间主机又拉低了总线 (会让寄生供电的转换失败)
+    pub pullup_violations: u32,
+}
+
+impl SimWire {
+    fn is_low(&self) -> bool {
+        self.master_low || self.devices.iter().any(|d| d.holds_low(self.now))
+    }
+
+    fn set_master_low(&mut self, low: bool) {
+        if low == self.master_low {
+            return;
+        }
+        self.master_low = low;
+        if low {
+            if self.strong_pullup {
+                self.pullup_violations += 1;
+            }
+            self.fall = self.now;
+            let now = self.now;
+            for device in &mut self.devices {
+                // 没有等到强上拉就开始下一个时隙，寄生供电的转换失败
+                device.awaiting_power = false;
+                device.on_fall(now);
+            }
+        } else {
+            let (now, low_for) = (self.now, self.now - self.fall);
+            for device in &mut self.devices {
+                device.on_rise(now, low_for);
+            }
+        }
+    }
+
+    fn set_strong_pullup(&mut self, on: bool) {
+        if on {
+            // 推挽高电平同样结束当前时隙，器件据此收到最后一位
+            self.set_master_low(false);
+        }
+        self.strong_pullup = on;
+        if on {
+            for device in &mut self.devices {
+                if device.awaiting_power {
+                    device.awaiting_power = false;
+                    device.convert();
+                }
+            }
+        }
+    }
+}
+
+pub struct SimPin(Rc<RefCell<SimWire>>);
+
+pub struct SimDelay(Rc<RefCell<SimWire>>);
+
+/// 创建一条挂着 `devices` 的模拟总线，返回位翻转驱动和总线句柄 (用于检查器件状态)
+pub fn bus(devices: Vec<SimDevice>) -> (BitBang<SimPin, SimDelay>, Rc<RefCell<SimWire>>) {
+    let wire = Rc::new(RefCell::new(SimWire {
+        devices,
+        now: 0,
+        master_low: false,
+        fall: 0,
+        strong_pullup: false,
+        pullup_violations: 0,
+    }));
+    let bus = BitBang::new(SimPin(wire.clone()), SimDelay(wire.clone()));
+    (bus, wire)
+}
+
+impl ErrorType for SimPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for SimPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.borrow_mut().set_master_low(true);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.borrow_mut().set_master_low(false);
+        Ok(())
+    }
+}
+
+impl InputPin for SimPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.0.borrow().is_low())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.0.borrow().is_low())
+    }
+}
+
+impl StrongPullup for SimPin {
+    fn set_strong_pullup(&mut self, on: bool) {
+        self.0.borrow_mut().set_strong_pullup(on);
+    }
+}
+
+impl DelayNs for SimDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0.borrow_mut().now += ns as u64;
+    }
+}
@@ -0,0 +1,430 @@
+//! 发不出去的上传记录先存起来，恢复连接后按时间顺序补发
+//!
+//! Wi-Fi 断开或服务器连不上时，每个周期的 JSON 放进内存里的队列 (最多 `ram_capacity` 条)；
+//! 内存满了就把最早的一条转存到 Flash 分区 (可选)，Flash 也满了才丢掉最早的记录并计数。
+//! Flash 里的总是比内存里的早，补发时先发 Flash 里的，所以总是从最早的一条开始。
+//!
+//! Flash 分区当作环形日志使用：记录一条接一条写下去，一个扇区写满了才擦除下一个扇区，
+//! 每擦一次能存好几条 (擦除期间整片 Flash 不能访问，中断也要屏蔽)。绕回来要擦除最早的
+//! 扇区时，里面还没发出去的记录只能丢掉。
+//!
+//! 同一条记录要发往几个通道 (例如 HTTP 和 MQTT) 时，每个通道确认收到后用
+//! [`Outbox::delivered`] 记下来，已经收到的通道下次不再重发，所有通道都收到才出队。
+//!
+//! 记录里保留采样时的 `uptime_s`，发送时在最前面加上 `"age_s"` (在队列里等了多少秒)，
+//! 服务器用收到的时间减去 `age_s` 就是这条记录的时间。Flash 里记录的位置只保存在内存里，
+//! 重启后从头覆盖——重启后 uptime 从 0 开始，旧记录的时间已经对不上了。
+
+use alloc::{collections::VecDeque, format, string::String, vec};
+use embedded_storage::nor_flash::NorFlash;
+
+// 每条记录的开头，后面是采样时刻 (u64) 和 JSON 的长度 (u32)，都是小端，再后面是 JSON
+const MAGIC: [u8; 4] = *b"OBX2";
+const HEADER_LEN: usize = 16;
+
+/// 一条待发送的记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// 生成这条记录时的上电时间 (ms)
+    pub captured_ms: u64,
+    /// 上传的 JSON 对象
+    pub body: String,
+}
+
+impl Record {
+    pub fn new(captured_ms: u64, body: String) -> Self {
+        Self { captured_ms, body }
+    }
+
+    /// 发送用的 JSON：在对象最前面加上 `"age_s"`
+    pub fn json(&self, now_ms: u64) -> String {
+        let age_s = now_ms.saturating_sub(self.captured_ms) / 1000;
+        match self.body.strip_prefix('{') {
+            Some(rest) if rest.trim_start().starts_with('}') => {
+                format!("{{\"age_s\":{}{}", age_s, rest)
+            }
+            Some(rest) => format!("{{\"age_s\":{}, {}", age_s, rest),
+            None => self.body.clone(),
+        }
+    }
+}
+
+// Flash 上的环形日志
+struct FlashLog<F> {
+    flash: F,
+    capacity: u32,
+    // 排队中的记录在分区里的偏移和还没收到的通道，从最早的开始
+    entries: VecDeque<(u32, u8)>,
+    // 下一条写在哪里；正好在扇区开头时，这个扇区还没擦除
+    head: u32,
+}
+
+impl<F: NorFlash> FlashLog<F> {
+    const SECTOR: u32 = F::ERASE_SIZE as u32;
+
+    fn new(flash: F) -> Self {
+        let capacity = (flash.capacity() / F::ERASE_SIZE * F::ERASE_SIZE) as u32;
+        Self {
+            flash,
+            capacity,
+            entries: VecDeque::new(),
+            head: 0,
+        }
+    }
+
+    // 写到队尾 (调用前确认记录放得进一个扇区)，返回因为擦除扇区而丢掉的记录数
+    fn push(&mut self, record: &Record, pending: u8) -> Result<u32, F::Error> {
+        let len = HEADER_LEN + record.body.len();
+        let mut data = vec![0xFF; len.next_multiple_of(F::WRITE_SIZE)];
+        data[..4].copy_from_slice(&MAGIC);
+        data[4..12].copy_from_slice(&record.captured_ms.to_le_bytes());
+        data[12..16].copy_from_slice(&(record.body.len() as u32).to_le_bytes());
+        data[HEADER_LEN..len].copy_from_slice(record.body.as_bytes());
+
+        // 当前扇区剩下的地方放不下，换到下一个扇区
+        let used = self.head % Self::SECTOR;
+        if used != 0 && used + data.len() as u32 > Self::SECTOR {
+            self.head = (self.head - used + Self::SECTOR) % self.capacity;
+        }
+        let mut dropped = 0;
+        if self.head.is_multiple_of(Self::SECTOR) {
+            self.flash.erase(self.head, self.head + Self::SECTOR)?;
+            // 擦掉的扇区里是最早的几条记录
+            let sector = self.head / Self::SECTOR;
+            while self
+                .entries
+                .front()
+                .is_some_and(|&(offset, _)| offset / Self::SECTOR == sector)
+            {
+                self.entries.pop_front();
+                dropped += 1;
+            }
+        }
+        let offset = self.head;
+        self.head = (offset + data.len() as u32) % self.capacity;
+        self.flash.write(offset, &data)?;
+        self.entries.push_back((offset, pending));
+        Ok(dropped)
+    }
+
+    // 读队首的记录，内容不对 (没写完就断电等) 时返回 Ok(None)
+    fn oldest(&mut self) -> Result<Option<Record>, F::Error> {
+        let Some(&(offset, _)) = self.entries.front() else {
+            return Ok(None);
+        };
+        let mut header = [0u8; HEADER_LEN];
+        self.flash.read(offset, &mut header)?;
+        let body_len =
+            u32::from_le_bytes([header[12], header[13], header[14], header[15]]) as usize;
+        if header[..4] != MAGIC || HEADER_LEN + body_len > F::ERASE_SIZE {
+            return Ok(None);
+        }
+        let mut body = vec![0u8; body_len.next_multiple_of(F::READ_SIZE)];
+        self.flash.read(offset + HEADER_LEN as u32, &mut body)?;
+        body.truncate(body_len);
+        let mut captured_ms = [0u8; 8];
+        captured_ms.copy_from_slice(&header[4..12]);
+        Ok(String::from_utf8(body)
+            .ok()
+            .map(|body| Record::new(u64::from_le_bytes(captured_ms), body)))
+    }
+
+    fn pop(&mut self) {
+        self.entries.pop_front();
+    }
+}
+
+/// 待发送记录的队列
+pub struct Outbox<F> {
+    // 内存里的记录和还没收到的通道
+    ram: VecDeque<(Record, u8)>,
+    ram_capacity: usize,
+    channels: u8,
+    flash: Option<FlashLog<F>>,
+    dropped: u32,
+    flash_errors: u32,
+}
+
+impl<F: NorFlash> Outbox<F> {
+    /// 内存里最多放 `ram_capacity` 条 (至少 1 条)
+    pub fn new(ram_capacity: usize) -> Self {
+        Self {
+            ram: VecDeque::new(),
+            ram_capacity: ram_capacity.max(1),
+            channels: 1,
+            flash: None,
+            dropped: 0,
+            flash_errors: 0,
+        }
+    }
+
+    /// 每条记录要发往的通道 (位掩码，默认只有一个通道 `1`)
+    pub fn with_channels(mut self, channels: u8) -> Self {
+        self.channels = channels.max(1);
+        self
+    }
+
+    /// 内存满了以后转存到 `flash` (整个分区都归队列使用，原有内容会被覆盖)
+    pub fn with_flash(mut self, flash: F) -> Self {
+        let log = FlashLog::new(flash);
+        self.flash = (log.capacity > 0).then_some(log);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.ram.len() + self.flash_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn flash_len(&self) -> usize {
+        self.flash.as_ref().map_or(0, |f| f.entries.len())
+    }
+
+    /// 放到队尾，满了就丢掉最早的一条
+    pub fn push(&mut self, record: Record) {
+        if self.ram.len() == self.ram_capacity {
+            if let Some((oldest, pending)) = self.ram.pop_front() {
+                self.spill(oldest, pending);
+            }
+        }
+        self.ram.push_back((record, self.channels));
+    }
+
+    // 把内存里挤出来的一条转存到 Flash，存不下就丢掉
+    fn spill(&mut self, record: Record, pending: u8) {
+        let Some(flash) = &mut self.flash else {
+            self.dropped = self.dropped.saturating_add(1);
+            return;
+        };
+        if HEADER_LEN + record.body.len() > F::ERASE_SIZE {
+            self.dropped = self.dropped.saturating_add(1);
+            return;
+        }
+        match flash.push(&record, pending) {
+            Ok(overwritten) => self.dropped = self.dropped.saturating_add(overwritten),
+            Err(_) => {
+                self.flash_errors = self.flash_errors.saturating_add(1);
+                self.dropped = self.dropped.saturating_add(1);
+            }
+        }
+    }
+
+    /// 最早的一条 (不出队，发送成功后调用 [`pop`](Self::pop))；Flash 里读不出来的记录直接丢掉
+    pub fn oldest(&mut self) -> Option<Record> {
+        while let Some(flash) = self.flash.as_mut().filter(|f| !f.entries.is_empty()) {
+            match flash.oldest() {
+                Ok(Some(record)) => return Some(record),
+                Ok(None) | Err(_) => {
+                    flash.pop();
+                    self.flash_errors = self.flash_errors.saturating_add(1);
+                    self.dropped = self.dropped.saturating_add(1);
+                }
+            }
+        }
+        self.ram.front().map(|(record, _)| record.clone())
+    }
+
+    /// 最早的一条还没收到的通道
+    pub fn pending(&self) -> u8 {
+        match self.flash.as_ref().and_then(|f| f.entries.front()) {
+            Some(&(_, pending)) => pending,
+            None => self.ram.front().map_or(0, |&(_, pending)| pending),
+        }
+    }
+
+    /// 最早的一条已经被 `channel` 收到，所有通道都收到了就出队
+    pub fn delivered(&mut self, channel: u8) {
+        let pending = match self.flash.as_mut().and_then(|f| f.entries.front_mut()) {
+            Some((_, pending)) => pending,
+            None => match self.ram.front_mut() {
+                Some((_, pending)) => pending,
+                None => return,
+            },
+        };
+        *pending &= !channel;
+        if *pending == 0 {
+            self.pop();
+        }
+    }
+
+    /// 去掉最早的一条
+    pub fn pop(&mut self) {
+        match self.flash.as_mut().filter(|f| !f.entries.is_empty()) {
+            Some(flash) => flash.pop(),
+            None => {
+                self.ram.pop_front();
+            }
+        }
+    }
+
+    /// 丢弃计数清零 (队列里的记录不动)
+    pub fn reset_stats(&mut self) {
+        self.dropped = 0;
+        self.flash_errors = 0;
+    }
+
+    /// 上传用的计数器名和值：排队中的条数 (其中在 Flash 里的条数)、
+    /// 队列满或 Flash 出错丢掉的条数、Flash 读写出错的次数
+    pub fn counters(&self) -> [(&'static str, u32); 4] {
+        [
+            ("queued", self.len() as u32),
+            ("in_flash", self.flash_len() as u32),
+            ("dropped", self.dropped),
+            ("flash_errors", self.flash_errors),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flash::sim::RamFlash;
+    use alloc::vec::Vec;
+
+    fn record(i: u64) -> Record {
+        Record::new(i * 1000, format!("{{\"uptime_s\":{}}}", i))
+    }
+
+    #[test]
+    fn drops_oldest_when_ram_is_full() {
+        let mut outbox: Outbox<RamFlash> = Outbox::new(3);
+        for i in 0..5 {
+            outbox.push(record(i));
+        }
+        assert_eq!(outbox.len(), 3);
+        assert_eq!(
+            outbox.counters(),
+            [
+                ("queued", 3),
+                ("in_flash", 0),
+                ("dropped", 2),
+                ("flash_errors", 0)
+            ]
+        );
+
+        // 发送失败时不出队，下次还是同一条
+        assert_eq!(outbox.oldest(), Some(record(2)));
+        assert_eq!(outbox.oldest(), Some(record(2)));
+        let sent: Vec<_> = core::iter::from_fn(|| {
+            let r = outbox.oldest()?;
+            outbox.pop();
+            Some(r.json(10_500))
+        })
+        .collect();
+        assert_eq!(
+            sent,
+            [
+                "{\"age_s\":8, \"uptime_s\":2}",
+                "{\"age_s\":7, \"uptime_s\":3}",
+                "{\"age_s\":6, \"uptime_s\":4}",
+            ]
+        );
+        assert!(outbox.is_empty());
+        outbox.reset_stats();
+        assert_eq!(outbox.counters()[2], ("dropped", 0));
+
+        // 两个通道都收到才出队，已经收到的通道不再重发
+        let mut outbox = Outbox::new(1)
+            .with_channels(0b11)
+            .with_flash(RamFlash::new(4096));
+        outbox.push(record(0));
+        assert_eq!(outbox.pending(), 0b11);
+        outbox.delivered(0b01);
+        // 转存到 Flash 以后也记得哪个通道已经收到
+        outbox.push(record(1));
+        assert_eq!((outbox.oldest(), outbox.pending()), (Some(record(0)), 0b10));
+        outbox.delivered(0b10);
+        assert_eq!((outbox.oldest(), outbox.pending()), (Some(record(1)), 0b11));
+        assert_eq!(Record::new(0, "{}".into()).json(2000), "{\"age_s\":2}");
+    }
+
+    // 约 1.5 KB 的记录，一个扇区放两条
+    fn large_record(i: u64) -> Record {
+        Record::new(
+            i * 1000,
+            format!("{{\"uptime_s\":{}, \"pad\":\"{}\"}}", i, "x".repeat(1500)),
+        )
+    }
+
+    fn flash_erases(outbox: &Outbox<RamFlash>) -> usize {
+        outbox.flash.as_ref().unwrap().flash.erases
+    }
+
+    #[test]
+    fn spills_to_flash_and_replays_in_order() {
+        let mut outbox = Outbox::new(2).with_flash(RamFlash::new(2 * 4096));
+        for i in 0..8 {
+            outbox.push(large_record(i));
+        }
+        // 转存了 6 条：写满两个扇区后绕回第一个，擦掉里面最早的两条
+        assert_eq!(
+            outbox.counters(),
+            [
+                ("queued", 6),
+                ("in_flash", 4),
+                ("dropped", 2),
+                ("flash_errors", 0)
+            ]
+        );
+        assert_eq!(flash_erases(&outbox), 3);
+
+        // 先发 Flash 里的，中途又来了新记录，顺序不乱
+        for i in 2..4 {
+            assert_eq!(outbox.oldest(), Some(large_record(i)));
+            outbox.pop();
+        }
+        outbox.push(large_record(8));
+        outbox.push(large_record(9));
+        let mut replayed = Vec::new();
+        while let Some(r) = outbox.oldest() {
+            replayed.push(r.captured_ms / 1000);
+            outbox.pop();
+        }
+        assert_eq!(replayed, [4, 5, 6, 7, 8, 9]);
+        assert_eq!(
+            outbox.counters()[2..],
+            [("dropped", 2), ("flash_errors", 0)]
+        );
+        assert_eq!(flash_erases(&outbox), 4);
+
+        // 短记录一个扇区能放很多条，只擦除一次
+        let mut outbox = Outbox::new(1).with_flash(RamFlash::new(2 * 4096));
+        for i in 0..50 {
+            outbox.push(record(i));
+        }
+        assert_eq!(outbox.counters()[1], ("in_flash", 49));
+        assert_eq!(flash_erases(&outbox), 1);
+    }
+
+    #[test]
+    fn skips_corrupted_flash_records() {
+        let mut outbox = Outbox::new(1).with_flash(RamFlash::new(2 * 4096));
+        for i in 0..3 {
+            outbox.push(record(i));
+        }
+        // 第一条的开头坏了 (例如写到一半断电)
+        outbox.flash.as_mut().unwrap().flash.data[..4].fill(0);
+        assert_eq!(outbox.oldest(), Some(record(1)));
+        assert_eq!(
+            outbox.counters(),
+            [
+                ("queued", 2),
+                ("in_flash", 1),
+                ("dropped", 1),
+                ("flash_errors", 1)
+            ]
+        );
+
+        // 一个扇区放不下的记录丢掉
+        let mut outbox = Outbox::new(1).with_flash(RamFlash::new(4096));
+        outbox.push(Record::new(0, "x".repeat(4096)));
+        outbox.push(record(1));
+        assert_eq!(
+            outbox.counters()[..3],
+            [("queued", 1), ("in_flash", 0), ("dropped", 1)]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! 预热和合理性检查
+//!
+//! NDIR 模块上电后要预热几分钟，期间的读数是乱的；接触不良、干扰也会偶尔读出
+//! 物理上不可能的值。`Plausibility` 在测量值进入统计之前检查一遍：
+//!
+//! - 预热期内的值标记为 `WarmingUp`
+//! - 超出 `Limits` 范围的值标记为 `Implausible`
+//! - 和上一个被接受的值相比变化太快的也标记为 `Implausible`；不过连续几次都是
+//!   新水平时，认为是真实的跳变 (例如开门通风)，接受新值作为基准
+//!
+//! 被标记的值不进入窗口统计，在上传的 `flags` 字段里计数。
+
+use alloc::vec::Vec;
+
+use crate::onewire::Rom;
+use crate::sensor::{Measurement, Quality};
+
+/// 一个指标的合理范围
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub min: f32,
+    pub max: f32,
+    /// 允许的最大变化速度 (每秒)
+    pub max_rate: f32,
+    /// 连续这么多次超速后接受新水平
+    pub confirm: u8,
+}
+
+struct Rule {
+    metric: &'static str,
+    limits: Limits,
+}
+
+// 每个通道上一个被接受的值
+struct Baseline {
+    metric: &'static str,
+    channel: Option<Rom>,
+    value: f32,
+    at_ms: u64,
+    rejected: u8,
+}
+
+/// 按指标配置预热时间和合理范围
+#[derive(Default)]
+pub struct Plausibility {
+    warm_up: Vec<(&'static str, u64)>,
+    rules: Vec<Rule>,
+    baselines: Vec<Baseline>,
+}
+
+impl Plausibility {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 上电后 `warm_up_ms` 之内的 `metric` 标记为预热中
+    pub fn with_warm_up(mut self, metric: &'static str, warm_up_ms: u64) -> Self {
+        self.warm_up.push((metric, warm_up_ms));
+        self
+    }
+
+    pub fn with_limits(mut self, metric: &'static str, limits: Limits) -> Self {
+        self.rules.push(Rule { metric, limits });
+        self
+    }
+
+    /// `metric` 是否还在预热，`uptime_ms` 为上电以来的毫秒数
+    pub fn is_warming_up(&self, metric: &str, uptime_ms: u64) -> bool {
+        self.warm_up
+            .iter()
+            .any(|&(m, warm_up_ms)| m == metric && uptime_ms < warm_up_ms)
+    }
+
+    /// 检查一个测量值，不合理时改写它的质量；只检查 Good 的值
+    pub fn check(&mut self, m: Measurement, uptime_ms: u64) -> Measurement {
+        if m.quality != Quality::Good {
+            return m;
+        }
+        if self.is_warming_up(m.metric, uptime_ms) {
+            return m.with_quality(Quality::WarmingUp);
+        }
+        let Some(limits) = self
+            .rules
+            .iter()
+            .find(|r| r.metric == m.metric)
+            .map(|r| r.limits)
+        else {
+            return m;
+        };
+        if !(limits.min..=limits.max).contains(&m.value) {
+            return m.with_quality(Quality::Implausible);
+        }
+
+        let baseline = self
+            .baselines
+            .iter_mut()
+            .find(|b| b.metric == m.metric && b.channel == m.channel);
+        let Some(baseline) = baseline else {
+            self.baselines.push(Baseline {
+                metric: m.metric,
+                channel: m.channel,
+                value: m.value,
+                at_ms: uptime_ms,
+                rejected: 0,
+            });
+            return m;
+        };
+        // 至少按 1 秒算，避免同一时刻的两次读数被当成无限快
+        let elapsed_s = (uptime_ms.saturating_sub(baseline.at_ms) as f32 / 1000.0).max(1.0);
+        let rate = (m.value - baseline.value).abs() / elapsed_s;
+        if rate > limits.max_rate {
+            baseline.rejected += 1;
+            if baseline.rejected < limits.confirm {
+                return m.with_quality(Quality::Implausible);
+            }
+        }
+        baseline.value = m.value;
+        baseline.at_ms = uptime_ms;
+        baseline.rejected = 0;
+        m
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensor::Unit;
+
+    const CO2: Limits = Limits {
+        min: 300.0,
+        max: 5000.0,
+        max_rate: 10.0,
+        confirm: 3,
+    };
+
+    fn co2(ppm: f32) -> Measurement {
+        Measurement::new("co2", ppm, Unit::Ppm)
+    }
+
+    #[test]
+    fn marks_warm_up_and_out_of_range() {
+        let mut check = Plausibility::new()
+            .with_warm_up("co2", 180_000)
+            .with_limits("co2", CO2);
+        assert_eq!(check.check(co2(5000.0), 1_000).quality, Quality::WarmingUp);
+        assert!(check.is_warming_up("co2", 179_999));
+        assert!(!check.is_warming_up("temps", 0));
+        assert_eq!(check.check(co2(600.0), 180_000).quality, Quality::Good);
+        assert_eq!(check.check(co2(0.0), 210_000).quality, Quality::Implausible);
+        // 没有配置的指标原样通过
+        let rh = Measurement::new("humidity", 150.0, Unit::Percent);
+        assert_eq!(check.check(rh, 210_000), rh);
+    }
+
+    #[test]
+    fn rejects_jumps_until_confirmed() {
+        let mut check = Plausibility::new().with_limits("co2", CO2);
+        assert_eq!(check.check(co2(600.0), 0).quality, Quality::Good);
+        // 30 秒内上升 200 ppm，没超过 10 ppm/s
+        assert_eq!(check.check(co2(800.0), 30_000).quality, Quality::Good);
+        // 30 秒内上升 3000 ppm：前两次判为不合理，第三次接受新水平
+        assert_eq!(
+            check.check(co2(3800.0), 60_000).quality,
+            Quality::Implausible
+        );
+        assert_eq!(
+            check.check(co2(3810.0), 90_000).quality,
+            Quality::Implausible
+        );
+        assert_eq!(check.check(co2(3805.0), 120_000).quality, Quality::Good);
+        assert_eq!(check.check(co2(3790.0), 150_000).quality, Quality::Good);
+
+        // 不同通道分开比较
+        let a = co2(3790.0).with_channel([1; 8]);
+        assert_eq!(check.check(a, 150_000).quality, Quality::Good);
+    }
+}
@@ -0,0 +1,268 @@
+//! 电池与 UPS 供电监测
+//!
+//! UPS 模块的 18650 电池 (1S 并联) 经电阻分压接 ADC，按锂电池静置电压曲线估算剩余电量；
+//! 模块的“外部供电正常”信号 (可选) 接 GPIO，用来记录停电和来电的时刻。
+
+use alloc::{format, string::String, vec, vec::Vec};
+use embedded_hal::digital::InputPin;
+
+use crate::adc::{self, AnalogInput, Curve, Divider};
+use crate::events::EventLog;
+use crate::sensor::{Measurement, Sensor, Unit};
+
+/// 上传 JSON 里的字段名：电池电压
+pub const VOLTAGE_METRIC: &str = "battery_v";
+/// 上传 JSON 里的字段名：估算的剩余电量
+pub const SOC_METRIC: &str = "battery_soc";
+
+/// 单节锂离子电池的电压 (mV) → 剩余电量 (%)
+pub const LI_ION_CURVE: Curve = Curve(&[
+    (3300.0, 0.0),
+    (3500.0, 5.0),
+    (3610.0, 10.0),
+    (3670.0, 20.0),
+    (3710.0, 30.0),
+    (3750.0, 40.0),
+    (3790.0, 50.0),
+    (3850.0, 60.0),
+    (3920.0, 70.0),
+    (4000.0, 80.0),
+    (4100.0, 90.0),
+    (4200.0, 100.0),
+]);
+
+/// 由电池电压估算剩余电量 (%)，超出曲线范围取端点
+///
+/// 充电或大电流放电时端电压会偏离静置电压，这只是粗略估计。
+pub fn state_of_charge(battery_mv: f32) -> f32 {
+    LI_ION_CURVE.lookup(battery_mv)
+}
+
+/// 经分压接在 ADC 上的电池
+pub struct BatterySensor<A> {
+    input: A,
+    divider: Divider,
+    oversample: usize,
+}
+
+impl<A: AnalogInput> BatterySensor<A> {
+    pub fn new(input: A, divider: Divider) -> Self {
+        Self {
+            input,
+            divider,
+            oversample: 1,
+        }
+    }
+
+    /// 每次采样读 `n` 次 ADC 求平均
+    pub fn with_oversample(mut self, n: usize) -> Self {
+        self.oversample = n.max(1);
+        self
+    }
+
+    /// 电池电压 (mV)
+    pub fn read_mv(&mut self) -> Option<f32> {
+        adc::oversample(&mut self.input, self.oversample).map(|mv| self.divider.input_mv(mv))
+    }
+}
+
+impl<A: AnalogInput> Sensor for BatterySensor<A> {
+    fn name(&self) -> &'static str {
+        "Battery"
+    }
+
+    async fn sample(&mut self) -> Vec<Measurement> {
+        match self.read_mv() {
+            Some(mv) => vec![
+                Measurement::new(VOLTAGE_METRIC, mv / 1000.0, Unit::Volt),
+                Measurement::new(SOC_METRIC, state_of_charge(mv), Unit::Percent),
+            ],
+            None => vec![
+                Measurement::invalid(VOLTAGE_METRIC, Unit::Volt),
+                Measurement::invalid(SOC_METRIC, Unit::Percent),
+            ],
+        }
+    }
+}
+
+/// 外部供电的变化
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerEvent {
+    /// true：来电；false：停电，开始用电池
+    pub external: bool,
+    /// 检测到变化的时刻 (上电以来的秒数)
+    pub uptime_s: u64,
+}
+
+const MAX_EVENTS: usize = 16;
+
+/// 读取 UPS 模块的“外部供电正常”信号，记录停电/来电事件
+pub struct PowerMonitor<P> {
+    pin: P,
+    active_high: bool,
+    external: Option<bool>,
+    events: EventLog<PowerEvent, MAX_EVENTS>,
+}
+
+impl<P: InputPin> PowerMonitor<P> {
+    /// `active_high`：信号为高电平时表示外部供电正常
+    pub fn new(pin: P, active_high: bool) -> Self {
+        Self {
+            pin,
+            active_high,
+            external: None,
+            events: EventLog::new(),
+        }
+    }
+
+    /// 当前是否有外部供电，还没读过或读取失败时为 `None`
+    pub fn external(&self) -> Option<bool> {
+        self.external
+    }
+
+    /// 读一次信号，供电状态变化时返回事件
+    ///
+    /// 上电后的第一次读取只确定初始状态；不过如果一开机就是电池供电，说明是在停电期间
+    /// 重启的，同样记一次停电。
+    pub fn poll(&mut self, uptime_s: u64) -> Option<PowerEvent> {
+        let external = self.pin.is_high().ok()? == self.active_high;
+        let previous = self.external.replace(external);
+        let changed = match previous {
+            Some(previous) => previous != external,
+            None => !external,
+        };
+        if !changed {
+            return None;
+        }
+        let event = PowerEvent { external, uptime_s };
+        self.events.push(event);
+        Some(event)
+    }
+
+    /// 上次上传之后发生的事件
+    pub fn events(&self) -> &[PowerEvent] {
+        self.events.as_slice()
+    }
+
+    /// 事件放进上传队列后清掉
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// 上传用的 `power` 字段，带上当前供电状态和还没上传的事件：
+    /// `"power":{"external":false,"events":[{"event":"lost","uptime_s":3600}]}`
+    pub fn json_field(&self) -> String {
+        let external = match self.external {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "null",
+        };
+        let events = self.events.json_array(|e| {
+            format!(
+                "{{\"event\":\"{}\",\"uptime_s\":{}}}",
+                if e.external { "restored" } else { "lost" },
+                e.uptime_s
+            )
+        });
+        format!(
+            "\"power\":{{\"external\":{},\"events\":{}}}",
+            external, events
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensor::Quality;
+    use core::convert::Infallible;
+    use embassy_futures::block_on;
+    use embedded_hal::digital::ErrorType;
+
+    struct Fixed(u16);
+
+    impl AnalogInput for Fixed {
+        type Error = ();
+
+        fn read_mv(&mut self) -> Result<u16, ()> {
+            match self.0 {
+                0 => Err(()),
+                mv => Ok(mv),
+            }
+        }
+    }
+
+    // 电平由测试直接设置
+    struct Level(bool);
+
+    impl ErrorType for Level {
+        type Error = Infallible;
+    }
+
+    impl InputPin for Level {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0)
+        }
+    }
+
+    #[test]
+    fn estimates_state_of_charge() {
+        assert_eq!(state_of_charge(4250.0), 100.0);
+        assert_eq!(state_of_charge(3790.0), 50.0);
+        assert!((state_of_charge(3770.0) - 45.0).abs() < 0.01);
+        assert_eq!(state_of_charge(3000.0), 0.0);
+    }
+
+    #[test]
+    fn battery_sensor_reports_voltage_and_soc() {
+        // 100k/100k 分压：ADC 上 1950 mV = 电池 3.9 V
+        let divider = Divider {
+            top_ohms: 100_000.0,
+            bottom_ohms: 100_000.0,
+        };
+        let mut battery = BatterySensor::new(Fixed(1950), divider).with_oversample(4);
+        let out = block_on(battery.sample());
+        assert_eq!(out[0].metric, VOLTAGE_METRIC);
+        assert!((out[0].value - 3.9).abs() < 0.001);
+        assert!((out[1].value - 67.1).abs() < 0.1);
+
+        let mut battery = BatterySensor::new(Fixed(0), divider);
+        let out = block_on(battery.sample());
+        assert!(out.iter().all(|m| m.quality == Quality::Invalid));
+    }
+
+    #[test]
+    fn records_power_loss_and_restore() {
+        let mut power = PowerMonitor::new(Level(true), true);
+        assert_eq!(power.poll(0), None);
+        assert_eq!(power.external(), Some(true));
+
+        power.pin.0 = false;
+        assert_eq!(
+            power.poll(3600),
+            Some(PowerEvent {
+                external: false,
+                uptime_s: 3600
+            })
+        );
+        assert_eq!(power.poll(3630), None);
+        power.pin.0 = true;
+        assert!(power.poll(5400).is_some());
+
+        assert_eq!(
+            power.json_field(),
+            "\"power\":{\"external\":true,\"events\":\
+             [{\"event\":\"lost\",\"uptime_s\":3600},{\"event\":\"restored\",\"uptime_s\":5400}]}"
+        );
+        power.clear();
+        assert!(power.events().is_empty());
+
+        // 停电期间重启：第一次读取就记一次停电
+        let mut power = PowerMonitor::new(Level(true), false);
+        assert_eq!(power.poll(5).map(|e| e.external), Some(false));
+    }
+}
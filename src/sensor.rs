@@ -0,0 +1,556 @@
+//! 传感器抽象与测量值
+//!
+//! 每种探头实现 `Sensor`，一次 `sample()` 返回若干带单位和质量标记的 `Measurement`。
+//! 主循环只遍历 `Registry`，把结果交给 `Collector` 做窗口统计和生成上传 JSON，
+//! 新增探头时只需要写一个驱动并加进注册表。
+
+use alloc::{format, string::String, vec::Vec};
+use core::{fmt, future::Future};
+
+use crate::onewire::Rom;
+use crate::window::{OutlierFilter, Summary, Window};
+
+/// 测量值的单位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Celsius,
+    Ppm,
+    /// 百分比 (相对湿度、电池电量)
+    Percent,
+    Lux,
+    Volt,
+}
+
+impl Unit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Ppm => "ppm",
+            Unit::Percent => "%",
+            Unit::Lux => "lx",
+            Unit::Volt => "V",
+        }
+    }
+
+    /// 上传和打印时保留的小数位数
+    pub fn decimals(self) -> usize {
+        match self {
+            Unit::Celsius | Unit::Volt => 2,
+            Unit::Ppm => 0,
+            Unit::Percent | Unit::Lux => 1,
+        }
+    }
+}
+
+/// 测量值是否可信
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    /// 本次采样得到的有效值
+    Good,
+    /// 缓存的旧值，已经超过允许的时间没有更新
+    Stale,
+    /// 没有读到有效值 (掉线、校验失败等)，`value` 为 NaN
+    Invalid,
+    /// 传感器上电后还在预热，读数不可信
+    WarmingUp,
+    /// 超出合理范围或变化快得不符合物理规律
+    Implausible,
+}
+
+impl Quality {
+    /// 打印和上传时用的名字
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Quality::Good => "good",
+            Quality::Stale => "stale",
+            Quality::Invalid => "invalid",
+            Quality::WarmingUp => "warming_up",
+            Quality::Implausible => "implausible",
+        }
+    }
+}
+
+/// 一个测量值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// 指标名，同时是上传 JSON 里的字段名 ("temps"、"co2"、"humidity" ...)
+    pub metric: &'static str,
+    /// 同一指标有多个探头时用来区分 (例如 DS18B20 的 ROM 码)
+    pub channel: Option<Rom>,
+    pub value: f32,
+    pub unit: Unit,
+    pub quality: Quality,
+}
+
+impl Measurement {
+    pub fn new(metric: &'static str, value: f32, unit: Unit) -> Self {
+        Self {
+            metric,
+            channel: None,
+            value,
+            unit,
+            quality: Quality::Good,
+        }
+    }
+
+    /// 没有读到数据时占位，保证上传里仍然出现这个字段 (值为 null)
+    pub fn invalid(metric: &'static str, unit: Unit) -> Self {
+        Self {
+            quality: Quality::Invalid,
+            ..Self::new(metric, f32::NAN, unit)
+        }
+    }
+
+    pub fn with_channel(mut self, channel: Rom) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    pub fn with_quality(mut self, quality: Quality) -> Self {
+        self.quality = quality;
+        self
+    }
+}
+
+// 打印成 `temps[28FF1E64...] = 21.50 °C` 的形式，质量不是 Good 时附在后面
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.metric)?;
+        if let Some(channel) = &self.channel {
+            write!(f, "[{}]", ChannelHex(channel))?;
+        }
+        if self.quality == Quality::Invalid {
+            return f.write_str(" = -- (invalid)");
+        }
+        write!(
+            f,
+            " = {:.*} {}",
+            self.unit.decimals(),
+            self.value,
+            self.unit.symbol()
+        )?;
+        if self.quality != Quality::Good {
+            write!(f, " ({})", self.quality.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+// 通道 (ROM 码) 按总线顺序打印成十六进制，家族码在前
+pub(crate) struct ChannelHex<'a>(pub(crate) &'a Rom);
+
+impl fmt::Display for ChannelHex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02X}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// 一种探头的驱动
+pub trait Sensor {
+    /// 用于日志的名称
+    fn name(&self) -> &'static str;
+
+    /// 采样一次，返回本次得到的所有测量值
+    fn sample(&mut self) -> impl Future<Output = Vec<Measurement>>;
+}
+
+/// 开机时没有发现的传感器用 `None` 占位，采样时不返回任何测量值
+impl<S: Sensor> Sensor for Option<S> {
+    fn name(&self) -> &'static str {
+        self.as_ref().map_or("(absent)", Sensor::name)
+    }
+
+    async fn sample(&mut self) -> Vec<Measurement> {
+        match self {
+            Some(sensor) => sensor.sample().await,
+            None => Vec::new(),
+        }
+    }
+}
+
+/// 一组按顺序采样的传感器，由元组实现：`(A, B, C)`
+pub trait SensorList {
+    fn sample_into(&mut self, out: &mut Vec<Measurement>) -> impl Future<Output = ()>;
+}
+
+macro_rules! impl_sensor_list {
+    ($($name:ident),+) => {
+        impl<$($name: Sensor),+> SensorList for ($($name,)+) {
+            #[allow(non_snake_case)]
+            async fn sample_into(&mut self, out: &mut Vec<Measurement>) {
+                let ($($name,)+) = self;
+                $(out.extend($name.sample().await);)+
+            }
+        }
+    };
+}
+
+impl_sensor_list!(A);
+impl_sensor_list!(A, B);
+impl_sensor_list!(A, B, C);
+impl_sensor_list!(A, B, C, D);
+impl_sensor_list!(A, B, C, D, E);
+impl_sensor_list!(A, B, C, D, E, F);
+impl_sensor_list!(A, B, C, D, E, F, G);
+impl_sensor_list!(A, B, C, D, E, F, G, H);
+
+/// 主循环遍历的传感器注册表
+pub struct Registry<L> {
+    sensors: L,
+}
+
+impl<L: SensorList> Registry<L> {
+    pub fn new(sensors: L) -> Self {
+        Self { sensors }
+    }
+
+    /// 依次采样所有传感器
+    pub async fn sample(&mut self) -> Vec<Measurement> {
+        let mut out = Vec::new();
+        self.sensors.sample_into(&mut out).await;
+        out
+    }
+
+    /// 注册的传感器元组，用来读取某个驱动自己的统计
+    pub fn sensors(&self) -> &L {
+        &self.sensors
+    }
+
+    pub fn sensors_mut(&mut self) -> &mut L {
+        &mut self.sensors
+    }
+}
+
+/// 一个指标 (同一 metric + channel) 在上传周期内的样本
+pub struct Series<const N: usize> {
+    pub metric: &'static str,
+    pub channel: Option<Rom>,
+    pub unit: Unit,
+    pub window: Window<N>,
+    /// 质量不是 Good、没有计入窗口的次数
+    pub skipped: usize,
+    /// 其中因为预热被跳过的次数
+    pub warming_up: usize,
+    /// 其中因为不合理被跳过的次数
+    pub implausible: usize,
+}
+
+/// 按指标收集一个上传周期内的测量值
+pub struct Collector<const N: usize> {
+    series: Vec<Series<N>>,
+}
+
+impl<const N: usize> Default for Collector<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Collector<N> {
+    pub const fn new() -> Self {
+        Self { series: Vec::new() }
+    }
+
+    /// 记录一个测量值；只有 Good 的值进入统计窗口
+    pub fn record(&mut self, m: &Measurement) {
+        let index = match self
+            .series
+            .iter()
+            .position(|s| s.metric == m.metric && s.channel == m.channel)
+        {
+            Some(index) => index,
+            None => {
+                self.series.push(Series {
+                    metric: m.metric,
+                    channel: m.channel,
+                    unit: m.unit,
+                    window: Window::new(),
+                    skipped: 0,
+                    warming_up: 0,
+                    implausible: 0,
+                });
+                self.series.len() - 1
+            }
+        };
+        let series = &mut self.series[index];
+        match m.quality {
+            Quality::Good => {
+                series.window.push(m.value);
+            }
+            quality => {
+                series.skipped += 1;
+                match quality {
+                    Quality::WarmingUp => series.warming_up += 1,
+                    Quality::Implausible => series.implausible += 1,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// 开始新的上传周期 (探头列表保留，出现过的字段仍然会上传)
+    pub fn clear(&mut self) {
+        for series in &mut self.series {
+            series.window.clear();
+            series.skipped = 0;
+            series.warming_up = 0;
+            series.implausible = 0;
+        }
+    }
+
+    pub fn series(&self) -> &[Series<N>] {
+        &self.series
+    }
+
+    /// 指定指标第一个有数据的通道的统计
+    pub fn first(&self, metric: &str, filter: OutlierFilter) -> Option<Summary> {
+        self.series
+            .iter()
+            .filter(|s| s.metric == metric)
+            .find_map(|s| s.window.summary(filter))
+    }
+
+    /// 生成上传 JSON 的字段 (不含外层大括号)
+    ///
+    /// 每个指标上传周期内的平均值：单通道为 `"co2":612`，多通道按 ROM 码展开为
+    /// `"temps":{"28FF...":21.50}`，没有数据为 null；`"stats"` 里是完整的统计。
+    /// 有样本因为预热或不合理被跳过时，再加上 `"flags":{"co2":{"warming_up":10}}`。
+    /// 还没有任何指标时只有 `"stats":{}`。
+    pub fn json_fields(&self, filter: OutlierFilter) -> String {
+        let mut values = Vec::new();
+        let mut stats = Vec::new();
+        let mut flags = Vec::new();
+        for metric in self.metrics() {
+            let series: Vec<_> = self.series.iter().filter(|s| s.metric == metric).collect();
+            let value = |s: &Series<N>| match s.window.summary(filter) {
+                Some(summary) => json_number(summary.mean, s.unit.decimals()),
+                None => "null".into(),
+            };
+            let stat = |s: &Series<N>| match s.window.summary(filter) {
+                Some(summary) => summary_json(&summary, s.unit.decimals()),
+                None => "null".into(),
+            };
+            let flagged: Vec<_> = series
+                .iter()
+                .filter_map(|s| flags_json(s).map(|f| (s, f)))
+                .collect();
+            if series[0].channel.is_none() {
+                values.push(format!("\"{}\":{}", metric, value(series[0])));
+                stats.push(format!("\"{}\":{}", metric, stat(series[0])));
+                if let Some((_, f)) = flagged.first() {
+                    flags.push(format!("\"{}\":{}", metric, f));
+                }
+            } else {
+                let keyed = |f: &dyn Fn(&Series<N>) -> String| {
+                    series
+                        .iter()
+                        .map(|s| format!("\"{}\":{}", channel_key(s), f(s)))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                };
+                values.push(format!("\"{}\":{{{}}}", metric, keyed(&value)));
+                stats.push(format!("\"{}\":{{{}}}", metric, keyed(&stat)));
+                if !flagged.is_empty() {
+                    let keyed: Vec<_> = flagged
+                        .iter()
+                        .map(|(s, f)| format!("\"{}\":{}", channel_key(s), f))
+                        .collect();
+                    flags.push(format!("\"{}\":{{{}}}", metric, keyed.join(",")));
+                }
+            }
+        }
+        let mut fields = values.join(", ");
+        if !fields.is_empty() {
+            fields.push_str(", ");
+        }
+        fields.push_str(&format!("\"stats\":{{{}}}", stats.join(", ")));
+        if !flags.is_empty() {
+            fields.push_str(&format!(", \"flags\":{{{}}}", flags.join(", ")));
+        }
+        fields
+    }
+
+    // 按第一次出现的顺序列出所有指标名
+    fn metrics(&self) -> Vec<&'static str> {
+        let mut metrics: Vec<&'static str> = Vec::new();
+        for s in &self.series {
+            if !metrics.contains(&s.metric) {
+                metrics.push(s.metric);
+            }
+        }
+        metrics
+    }
+}
+
+fn channel_key<const N: usize>(series: &Series<N>) -> String {
+    match &series.channel {
+        Some(channel) => format!("{}", ChannelHex(channel)),
+        None => String::new(),
+    }
+}
+
+// 被跳过的样本按原因计数，没有预热或不合理的样本时为 None
+fn flags_json<const N: usize>(series: &Series<N>) -> Option<String> {
+    let counts: Vec<_> = [
+        (Quality::WarmingUp, series.warming_up),
+        (Quality::Implausible, series.implausible),
+    ]
+    .into_iter()
+    .filter(|&(_, n)| n > 0)
+    .map(|(quality, n)| format!("\"{}\":{}", quality.as_str(), n))
+    .collect();
+    (!counts.is_empty()).then(|| format!("{{{}}}", counts.join(",")))
+}
+
+// 统计结果转成 JSON 对象，decimals 为小数位数
+fn summary_json(summary: &Summary, decimals: usize) -> String {
+    format!(
+        "{{\"n\":{}, \"rejected\":{}, \"min\":{}, \"max\":{}, \"mean\":{}, \"median\":{}}}",
+        summary.count,
+        summary.rejected,
+        json_number(summary.min, decimals),
+        json_number(summary.max, decimals),
+        json_number(summary.mean, decimals),
+        json_number(summary.median, decimals)
+    )
+}
+
+/// 保留 `decimals` 位小数的 JSON 数字；NaN 和无穷大不是合法的 JSON，写成 null
+pub fn json_number(value: f32, decimals: usize) -> String {
+    if value.is_finite() {
+        format!("{:.*}", decimals, value)
+    } else {
+        "null".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+
+    const ROM_A: Rom = [0x28, 0xFF, 0x1E, 0x64, 0x00, 0x00, 0x00, 0x01];
+    const ROM_B: Rom = [0x28, 0xFF, 0x1E, 0x64, 0x00, 0x00, 0x00, 0x02];
+
+    // 按顺序返回预先给定的读数
+    struct Fake {
+        readings: Vec<Vec<Measurement>>,
+    }
+
+    impl Sensor for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn sample(&mut self) -> Vec<Measurement> {
+            self.readings.remove(0)
+        }
+    }
+
+    #[test]
+    fn registry_samples_every_sensor_in_order() {
+        let temps = Fake {
+            readings: vec![vec![
+                Measurement::new("temps", 21.5, Unit::Celsius).with_channel(ROM_A),
+                Measurement::invalid("temps", Unit::Celsius).with_channel(ROM_B),
+            ]],
+        };
+        let co2 = Fake {
+            readings: vec![vec![Measurement::new("co2", 612.0, Unit::Ppm)]],
+        };
+        let mut registry = Registry::new((temps, co2, None::<Fake>));
+
+        let out = block_on(registry.sample());
+        let metrics: Vec<_> = out.iter().map(|m| m.metric).collect();
+        assert_eq!(metrics, ["temps", "temps", "co2"]);
+        assert_eq!(registry.sensors().2.name(), "(absent)");
+    }
+
+    #[test]
+    fn displays_measurements() {
+        let m = Measurement::new("temps", 21.5, Unit::Celsius).with_channel(ROM_A);
+        assert_eq!(format!("{m}"), "temps[28FF1E6400000001] = 21.50 °C");
+        let m = Measurement::new("co2", 612.4, Unit::Ppm).with_quality(Quality::Stale);
+        assert_eq!(format!("{m}"), "co2 = 612 ppm (stale)");
+        let m = Measurement::invalid("co2", Unit::Ppm);
+        assert_eq!(format!("{m}"), "co2 = -- (invalid)");
+    }
+
+    #[test]
+    fn collector_builds_payload_fields() {
+        let mut collector: Collector<8> = Collector::new();
+        for (a, co2) in [(21.0, 600.0), (22.0, 610.0)] {
+            collector.record(&Measurement::new("temps", a, Unit::Celsius).with_channel(ROM_A));
+            collector.record(&Measurement::invalid("temps", Unit::Celsius).with_channel(ROM_B));
+            collector.record(&Measurement::new("co2", co2, Unit::Ppm));
+        }
+        // 过期的值不进入统计
+        collector.record(&Measurement::new("co2", 9999.0, Unit::Ppm).with_quality(Quality::Stale));
+
+        assert_eq!(
+            collector.json_fields(OutlierFilter::None),
+            "\"temps\":{\"28FF1E6400000001\":21.50,\"28FF1E6400000002\":null}, \
+             \"co2\":605, \
+             \"stats\":{\"temps\":{\"28FF1E6400000001\":{\"n\":2, \"rejected\":0, \"min\":21.00, \
+             \"max\":22.00, \"mean\":21.50, \"median\":21.50},\"28FF1E6400000002\":null}, \
+             \"co2\":{\"n\":2, \"rejected\":0, \"min\":600, \"max\":610, \"mean\":605, \"median\":605}}"
+        );
+        assert_eq!(
+            collector
+                .first("temps", OutlierFilter::None)
+                .map(|s| s.mean),
+            Some(21.5)
+        );
+        assert_eq!(collector.series()[2].skipped, 1);
+
+        // 新周期：字段保留，值变为 null
+        collector.clear();
+        assert!(collector
+            .json_fields(OutlierFilter::None)
+            .contains("\"co2\":null"));
+    }
+
+    #[test]
+    fn collector_flags_skipped_samples() {
+        let mut collector: Collector<8> = Collector::new();
+        collector
+            .record(&Measurement::new("co2", 3000.0, Unit::Ppm).with_quality(Quality::WarmingUp));
+        collector
+            .record(&Measurement::new("co2", 3000.0, Unit::Ppm).with_quality(Quality::WarmingUp));
+        collector.record(&Measurement::new("temps", 21.0, Unit::Celsius).with_channel(ROM_A));
+        collector.record(
+            &Measurement::new("temps", 85.0, Unit::Celsius)
+                .with_channel(ROM_A)
+                .with_quality(Quality::Implausible),
+        );
+        collector.record(&Measurement::new("temps", 21.5, Unit::Celsius).with_channel(ROM_B));
+
+        let json = collector.json_fields(OutlierFilter::None);
+        assert!(json.starts_with("\"co2\":null, \"temps\":"));
+        assert!(json.ends_with(
+            ", \"flags\":{\"co2\":{\"warming_up\":2}, \
+             \"temps\":{\"28FF1E6400000001\":{\"implausible\":1}}}"
+        ));
+        assert_eq!(collector.series()[1].skipped, 1);
+
+        let m = Measurement::new("co2", 3000.0, Unit::Ppm).with_quality(Quality::WarmingUp);
+        assert_eq!(format!("{m}"), "co2 = 3000 ppm (warming_up)");
+    }
+
+    #[test]
+    fn collector_always_builds_valid_json() {
+        // 还没有任何指标：不能以逗号开头
+        let mut collector: Collector<8> = Collector::new();
+        assert_eq!(collector.json_fields(OutlierFilter::None), "\"stats\":{}");
+
+        collector.record(&Measurement::new("nh3", f32::INFINITY, Unit::Ppm));
+        assert_eq!(
+            collector.json_fields(OutlierFilter::None),
+            "\"nh3\":null, \"stats\":{\"nh3\":{\"n\":1, \"rejected\":0, \"min\":null, \
+             \"max\":null, \"mean\":null, \"median\":null}}"
+        );
+    }
+}
@@ -0,0 +1,442 @@
+//! Sensirion SHT4x / SHT3x 温湿度传感器 (I2C)
+//!
+//! 两个系列的测量结果格式相同：温度和湿度各 2 字节，后面跟 1 字节 CRC8
+//! (多项式 0x31，初值 0xFF)。区别在于命令长度 (SHT4x 1 字节，SHT3x 2 字节)、
+//! 湿度换算公式和加热器的用法。
+//!
+//! 鼠笼里湿度高，探头表面结露后会一直读到接近 100 %RH。连续多次读数超过阈值时
+//! 短暂打开片上加热器把水汽蒸发掉 (condensation recovery)。
+
+use alloc::{vec, vec::Vec};
+use embedded_hal::i2c::I2c;
+use embedded_hal_async::delay::DelayNs;
+
+use crate::sensor::{Measurement, Sensor, Unit};
+
+/// 默认 I2C 地址 (SHT4x-A / SHT3x ADDR 接地)
+pub const DEFAULT_ADDRESS: u8 = 0x44;
+
+/// 上传 JSON 里的字段名
+pub const METRIC: &str = "humidity";
+
+/// 传感器系列
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    Sht4x,
+    Sht3x,
+}
+
+// SHT4x 命令 (1 字节)
+const SHT4X_MEASURE_HIGH: u8 = 0xFD;
+const SHT4X_SERIAL: u8 = 0x89;
+const SHT4X_SOFT_RESET: u8 = 0x94;
+// 200 mW 加热 1 s，结束时顺带做一次高精度测量
+const SHT4X_HEATER_200MW_1S: u8 = 0x39;
+// SHT3x 命令 (2 字节)
+const SHT3X_MEASURE_HIGH: u16 = 0x2400;
+const SHT3X_SOFT_RESET: u16 = 0x30A2;
+const SHT3X_HEATER_ON: u16 = 0x306D;
+const SHT3X_HEATER_OFF: u16 = 0x3066;
+const SHT3X_STATUS: u16 = 0xF32D;
+
+// 数据手册给出的最长等待时间
+const SHT4X_MEASURE_MS: u32 = 10;
+const SHT4X_HEATER_MS: u32 = 1_100;
+const SHT3X_MEASURE_MS: u32 = 16;
+const SHT3X_HEATER_MS: u32 = 1_000;
+const SOFT_RESET_MS: u32 = 2;
+
+/// 一次测量的结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    pub celsius: f32,
+    /// 相对湿度 (%RH)，限制在 0..=100
+    pub humidity: f32,
+}
+
+/// 读取失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShtError<E> {
+    /// I2C 总线错误 (没有 ACK、仲裁丢失等)
+    I2c(E),
+    /// 某个数据字的 CRC 不匹配
+    CrcMismatch { expected: u8, actual: u8 },
+}
+
+/// Sensirion CRC8 (多项式 x^8 + x^5 + x^4 + 1 = 0x31，初值 0xFF，高位先行)
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+// 校验一个 3 字节的数据字 (2 字节数据 + CRC)，返回数据
+fn word<E>(chunk: &[u8]) -> Result<u16, ShtError<E>> {
+    let expected = crc8(&chunk[..2]);
+    if expected != chunk[2] {
+        return Err(ShtError::CrcMismatch {
+            expected,
+            actual: chunk[2],
+        });
+    }
+    Ok(((chunk[0] as u16) << 8) | chunk[1] as u16)
+}
+
+/// 校验 6 字节的测量结果并换算成摄氏度和 %RH
+pub fn decode<E>(model: Model, raw: &[u8; 6]) -> Result<Reading, ShtError<E>> {
+    let t = word(&raw[0..3])? as f32 / 65535.0;
+    let rh = word(&raw[3..6])? as f32 / 65535.0;
+    let humidity = match model {
+        // SHT4x 的公式可能超出 0..100，数据手册建议截断
+        Model::Sht4x => -6.0 + 125.0 * rh,
+        Model::Sht3x => 100.0 * rh,
+    };
+    Ok(Reading {
+        celsius: -45.0 + 175.0 * t,
+        humidity: humidity.clamp(0.0, 100.0),
+    })
+}
+
+/// SHT4x / SHT3x 驱动
+///
+/// I2C 传输很短，用阻塞接口；等待测量和加热的时间用异步延时，期间不占用 CPU。
+pub struct Sht<I, D> {
+    i2c: I,
+    delay: D,
+    model: Model,
+    address: u8,
+    // 结露恢复：超过 threshold %RH 连续 samples 次后加热
+    recovery: Option<(f32, u8)>,
+    humid_streak: u8,
+    heater_runs: u32,
+}
+
+impl<I: I2c, D: DelayNs> Sht<I, D> {
+    pub fn new(i2c: I, delay: D, model: Model) -> Self {
+        Self {
+            i2c,
+            delay,
+            model,
+            address: DEFAULT_ADDRESS,
+            recovery: None,
+            humid_streak: 0,
+            heater_runs: 0,
+        }
+    }
+
+    /// 使用非默认地址 (SHT3x ADDR 接高为 0x45，SHT4x-B 为 0x45)
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// 连续 `samples` 次读数不低于 `threshold` %RH 时启动加热器去除结露
+    pub fn with_condensation_recovery(mut self, threshold: f32, samples: u8) -> Self {
+        self.recovery = Some((threshold, samples.max(1)));
+        self
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// 到目前为止启动加热器的次数
+    pub fn heater_runs(&self) -> u32 {
+        self.heater_runs
+    }
+
+    /// 软复位，恢复上电默认状态 (同时会关掉 SHT3x 的加热器)
+    pub async fn soft_reset(&mut self) -> Result<(), ShtError<I::Error>> {
+        match self.model {
+            Model::Sht4x => self.command(SHT4X_SOFT_RESET)?,
+            Model::Sht3x => self.command16(SHT3X_SOFT_RESET)?,
+        }
+        self.delay.delay_ms(SOFT_RESET_MS).await;
+        Ok(())
+    }
+
+    /// 读取序列号 (SHT4x) 或状态寄存器 (SHT3x)，用来确认探头在线
+    pub fn identify(&mut self) -> Result<u32, ShtError<I::Error>> {
+        match self.model {
+            Model::Sht4x => {
+                self.command(SHT4X_SERIAL)?;
+                let mut buf = [0u8; 6];
+                self.i2c
+                    .read(self.address, &mut buf)
+                    .map_err(ShtError::I2c)?;
+                let high = word(&buf[0..3])?;
+                let low = word(&buf[3..6])?;
+                Ok(((high as u32) << 16) | low as u32)
+            }
+            Model::Sht3x => {
+                let mut buf = [0u8; 3];
+                self.i2c
+                    .write_read(self.address, &SHT3X_STATUS.to_be_bytes(), &mut buf)
+                    .map_err(ShtError::I2c)?;
+                Ok(word(&buf)? as u32)
+            }
+        }
+    }
+
+    /// 高重复性单次测量
+    pub async fn measure(&mut self) -> Result<Reading, ShtError<I::Error>> {
+        match self.model {
+            Model::Sht4x => {
+                self.command(SHT4X_MEASURE_HIGH)?;
+                self.delay.delay_ms(SHT4X_MEASURE_MS).await;
+            }
+            Model::Sht3x => {
+                self.command16(SHT3X_MEASURE_HIGH)?;
+                self.delay.delay_ms(SHT3X_MEASURE_MS).await;
+            }
+        }
+        self.read_result()
+    }
+
+    /// 打开加热器约 1 秒后关闭
+    ///
+    /// 加热期间和刚结束时读数偏高偏干，不能当作环境值；SHT4x 加热命令结束时
+    /// 附带的那次测量这里只读出来丢掉。
+    pub async fn heat(&mut self) -> Result<(), ShtError<I::Error>> {
+        self.heater_runs = self.heater_runs.saturating_add(1);
+        match self.model {
+            Model::Sht4x => {
+                self.command(SHT4X_HEATER_200MW_1S)?;
+                self.delay.delay_ms(SHT4X_HEATER_MS).await;
+                self.read_result()?;
+            }
+            Model::Sht3x => {
+                self.command16(SHT3X_HEATER_ON)?;
+                self.delay.delay_ms(SHT3X_HEATER_MS).await;
+                self.command16(SHT3X_HEATER_OFF)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 根据本次读数决定是否需要加热
+    async fn recover_condensation(&mut self, humidity: f32) {
+        let Some((threshold, samples)) = self.recovery else {
+            return;
+        };
+        if humidity < threshold {
+            self.humid_streak = 0;
+            return;
+        }
+        self.humid_streak += 1;
+        if self.humid_streak >= samples {
+            self.humid_streak = 0;
+            // 加热失败时下一次采样会照常报告错误，这里不单独处理
+            let _ = self.heat().await;
+        }
+    }
+
+    fn command(&mut self, command: u8) -> Result<(), ShtError<I::Error>> {
+        self.i2c
+            .write(self.address, &[command])
+            .map_err(ShtError::I2c)
+    }
+
+    fn command16(&mut self, command: u16) -> Result<(), ShtError<I::Error>> {
+        self.i2c
+            .write(self.address, &command.to_be_bytes())
+            .map_err(ShtError::I2c)
+    }
+
+    fn read_result(&mut self) -> Result<Reading, ShtError<I::Error>> {
+        let mut raw = [0u8; 6];
+        self.i2c
+            .read(self.address, &mut raw)
+            .map_err(ShtError::I2c)?;
+        decode(self.model, &raw)
+    }
+}
+
+impl<I: I2c, D: DelayNs> Sensor for Sht<I, D> {
+    fn name(&self) -> &'static str {
+        match self.model {
+            Model::Sht4x => "SHT4x",
+            Model::Sht3x => "SHT3x",
+        }
+    }
+
+    async fn sample(&mut self) -> Vec<Measurement> {
+        match self.measure().await {
+            Ok(reading) => {
+                self.recover_condensation(reading.humidity).await;
+                vec![Measurement::new(METRIC, reading.humidity, Unit::Percent)]
+            }
+            Err(_) => vec![Measurement::invalid(METRIC, Unit::Percent)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensor::Quality;
+    use embassy_futures::block_on;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    // 记录写入的命令，按顺序返回预先准备的读数据；没有设备时返回 NACK
+    #[derive(Default)]
+    struct MockI2c {
+        present: bool,
+        writes: Vec<Vec<u8>>,
+        reads: Vec<Vec<u8>>,
+    }
+
+    impl MockI2c {
+        fn new(reads: Vec<Vec<u8>>) -> Self {
+            Self {
+                present: true,
+                reads,
+                ..Default::default()
+            }
+        }
+    }
+
+    impl ErrorType for MockI2c {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for MockI2c {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            if !self.present || address != DEFAULT_ADDRESS {
+                return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+            }
+            for op in operations {
+                match op {
+                    Operation::Write(bytes) => self.writes.push(bytes.to_vec()),
+                    Operation::Read(buf) => buf.copy_from_slice(&self.reads.remove(0)),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // 把原始值打包成带 CRC 的 6 字节
+    fn frame(t: u16, rh: u16) -> Vec<u8> {
+        let mut out = Vec::new();
+        for w in [t, rh] {
+            let bytes = w.to_be_bytes();
+            out.extend_from_slice(&bytes);
+            out.push(crc8(&bytes));
+        }
+        out
+    }
+
+    #[test]
+    fn crc_matches_datasheet_example() {
+        assert_eq!(crc8(&[0xBE, 0xEF]), 0x92);
+    }
+
+    #[test]
+    fn decodes_both_models() {
+        let raw: [u8; 6] = frame(0x6666, 0x8000).try_into().unwrap();
+        let r = decode::<()>(Model::Sht4x, &raw).unwrap();
+        assert!((r.celsius - 25.0).abs() < 0.01);
+        assert!((r.humidity - 56.5).abs() < 0.01);
+        let r = decode::<()>(Model::Sht3x, &raw).unwrap();
+        assert!((r.humidity - 50.0).abs() < 0.01);
+
+        // SHT4x 公式在两端会超出范围
+        let raw: [u8; 6] = frame(0x6666, 0xFFFF).try_into().unwrap();
+        assert_eq!(decode::<()>(Model::Sht4x, &raw).unwrap().humidity, 100.0);
+
+        let mut raw: [u8; 6] = frame(0x6666, 0x8000).try_into().unwrap();
+        raw[5] ^= 0x01;
+        assert!(matches!(
+            decode::<()>(Model::Sht4x, &raw),
+            Err(ShtError::CrcMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn sends_model_specific_commands() {
+        let mut sht = Sht::new(
+            MockI2c::new(vec![frame(0x6666, 0x8000)]),
+            NoDelay,
+            Model::Sht4x,
+        );
+        block_on(sht.measure()).unwrap();
+        assert_eq!(sht.i2c.writes, [vec![0xFD]]);
+
+        let mut sht = Sht::new(
+            MockI2c::new(vec![vec![0x80, 0x10, crc8(&[0x80, 0x10])]]),
+            NoDelay,
+            Model::Sht3x,
+        );
+        assert_eq!(sht.identify(), Ok(0x8010));
+        block_on(sht.heat()).unwrap();
+        assert_eq!(
+            sht.i2c.writes,
+            [vec![0xF3, 0x2D], vec![0x30, 0x6D], vec![0x30, 0x66]]
+        );
+    }
+
+    #[test]
+    fn sample_reports_humidity_or_invalid() {
+        let mut sht = Sht::new(
+            MockI2c::new(vec![frame(0x6666, 0x8000)]),
+            NoDelay,
+            Model::Sht3x,
+        );
+        let out = block_on(sht.sample());
+        assert_eq!(out.len(), 1);
+        assert_eq!((out[0].metric, out[0].unit), (METRIC, Unit::Percent));
+        assert!((out[0].value - 50.0).abs() < 0.01);
+
+        // CRC 错误和没有 ACK 都给出无效值
+        let mut bad = frame(0x6666, 0x8000);
+        bad[2] ^= 0xFF;
+        sht.i2c.reads.push(bad);
+        assert_eq!(block_on(sht.sample())[0].quality, Quality::Invalid);
+        sht.i2c.present = false;
+        assert_eq!(block_on(sht.sample())[0].quality, Quality::Invalid);
+    }
+
+    #[test]
+    fn heats_after_consecutive_humid_readings() {
+        let saturated = frame(0x6666, 0xFFFF);
+        let mut sht = Sht::new(
+            MockI2c::new(vec![
+                saturated.clone(),
+                frame(0x6666, 0x8000),
+                saturated.clone(),
+                saturated.clone(),
+                // 加热命令结束时的那次测量
+                frame(0x8000, 0x1000),
+            ]),
+            NoDelay,
+            Model::Sht4x,
+        )
+        .with_condensation_recovery(95.0, 2);
+
+        for _ in 0..4 {
+            block_on(sht.sample());
+        }
+        assert_eq!(sht.heater_runs(), 1);
+        assert_eq!(sht.i2c.writes.last(), Some(&vec![SHT4X_HEATER_200MW_1S]));
+        assert!(sht.i2c.reads.is_empty());
+    }
+}
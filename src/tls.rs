@@ -0,0 +1,509 @@
+//! HTTPS 上传用的服务器证书校验
+//!
+//! TLS 1.3 协议本身由 [`embedded_tls`] 实现，这里只提供它需要的 [`CryptoProvider`]：
+//! 随机数来自调用方，服务器证书按烧进固件的 [`Trust`] 校验，不使用系统根证书：
+//!
+//! - `Trust::PublicKeySha256`：服务器证书公钥的 SHA-256 必须等于固定值
+//! - `Trust::Ca`：服务器证书必须由固定的 CA 直接签发，且 subjectAltName 与主机名相符
+//!
+//! 只接受 ECDSA P-256 的服务器证书 (签名算法 ecdsa_secp256r1_sha256)，记录加密用
+//! TLS_AES_128_GCM_SHA256。设备上没有可信的时钟，不检查证书有效期。
+
+use alloc::string::String;
+use core::net::Ipv4Addr;
+use embedded_tls::{
+    Aes128GcmSha256, CertificateEntryRef, CertificateRef, CertificateVerifyRef, CryptoProvider,
+    SignatureScheme, TlsError, TlsVerifier,
+};
+use p256::ecdsa::signature::Verifier as _;
+use p256::ecdsa::{DerSignature, Signature, VerifyingKey};
+use rand_core::{CryptoRng, CryptoRngCore, RngCore};
+use sha2::{Digest, Sha256};
+use x509_cert::der::asn1::ObjectIdentifier;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::{BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAltName};
+use x509_cert::Certificate;
+
+/// 收一条 TLS 记录的缓冲区大小 (协议允许的最大加密记录)
+pub const READ_BUFFER_LEN: usize = 16640;
+/// 发送缓冲区大小，更长的请求拆成多条记录
+pub const WRITE_BUFFER_LEN: usize = 4096;
+
+/// 使用的加密套件
+pub type CipherSuite = Aes128GcmSha256;
+
+const OID_EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const OID_PRIME256V1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const OID_ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const OID_SERVER_AUTH: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.1");
+
+/// 怎样判断服务器可信
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust<'a> {
+    /// 服务器证书公钥 (SubjectPublicKeyInfo 的 DER 编码) 的 SHA-256；
+    /// 换证书时只要沿用同一把密钥就不用更新固件
+    PublicKeySha256([u8; 32]),
+    /// 签发服务器证书的 CA 证书 (DER 编码，P-256 密钥)
+    Ca(&'a [u8]),
+}
+
+/// 按 [`Trust`] 校验服务器证书和握手签名
+pub struct Verifier<'a> {
+    trust: Trust<'a>,
+    host: Option<String>,
+    // 校验通过的服务器证书公钥和到 Certificate 为止的握手摘要，留给 CertificateVerify 用
+    server_key: Option<VerifyingKey>,
+    transcript: Option<Sha256>,
+}
+
+impl<'a> Verifier<'a> {
+    pub fn new(trust: Trust<'a>) -> Self {
+        Self {
+            trust,
+            host: None,
+            server_key: None,
+            transcript: None,
+        }
+    }
+
+    // 检查服务器证书，返回其中的 P-256 公钥
+    fn check(&self, der: &[u8]) -> Result<VerifyingKey, TlsError> {
+        let cert = Certificate::from_der(der).map_err(|_| TlsError::DecodeError)?;
+        let spki = &cert.tbs_certificate.subject_public_key_info;
+        match self.trust {
+            Trust::PublicKeySha256(pin) => {
+                let spki = spki.to_der().map_err(|_| TlsError::DecodeError)?;
+                if Sha256::digest(&spki)[..] != pin {
+                    return Err(TlsError::InvalidCertificate);
+                }
+            }
+            Trust::Ca(ca) => {
+                let ca = Certificate::from_der(ca).map_err(|_| TlsError::InvalidCertificate)?;
+                let host = self.host.as_ref().ok_or(TlsError::InvalidCertificate)?;
+                if !is_ca(&ca)
+                    || !is_server(&cert)
+                    || !issued_by(&cert, &ca)
+                    || !names_host(&cert, host)
+                {
+                    return Err(TlsError::InvalidCertificate);
+                }
+            }
+        }
+        p256_key(&cert)
+    }
+}
+
+impl TlsVerifier<CipherSuite> for Verifier<'_> {
+    fn set_hostname_verification(&mut self, hostname: &str) -> Result<(), TlsError> {
+        self.host = Some(hostname.into());
+        Ok(())
+    }
+
+    fn verify_certificate(
+        &mut self,
+        transcript: &Sha256,
+        cert: CertificateRef,
+    ) -> Result<(), TlsError> {
+        // 第一张是服务器自己的证书，后面的中间证书用不上
+        let Some(CertificateEntryRef::X509(der)) = cert.entries.first() else {
+            return Err(TlsError::InvalidCertificate);
+        };
+        self.server_key = Some(self.check(der)?);
+        self.transcript = Some(transcript.clone());
+        Ok(())
+    }
+
+    fn verify_signature(&mut self, verify: CertificateVerifyRef) -> Result<(), TlsError> {
+        let (Some(key), Some(transcript)) = (self.server_key.take(), self.transcript.take()) else {
+            return Err(TlsError::InvalidSignature);
+        };
+        if verify.signature_scheme != SignatureScheme::EcdsaSecp256r1Sha256 {
+            return Err(TlsError::InvalidSignatureScheme);
+        }
+        // RFC 8446 4.4.3：64 个空格 + 上下文字符串 + 0 + 握手摘要
+        let mut message = [0x20; 64 + 34 + 32];
+        message[64..98].copy_from_slice(b"TLS 1.3, server CertificateVerify\x00");
+        message[98..].copy_from_slice(&transcript.finalize());
+        let signature = Signature::from_der(verify.signature).map_err(|_| TlsError::DecodeError)?;
+        key.verify(&message, &signature)
+            .map_err(|_| TlsError::InvalidSignature)
+    }
+}
+
+// CA 证书必须声明 cA，keyUsage 存在时必须允许签发证书
+fn is_ca(ca: &Certificate) -> bool {
+    let tbs = &ca.tbs_certificate;
+    matches!(tbs.get::<BasicConstraints>(), Ok(Some((_, bc))) if bc.ca)
+        && match tbs.get::<KeyUsage>() {
+            Ok(Some((_, usage))) => usage.key_cert_sign(),
+            Ok(None) => true,
+            Err(_) => false,
+        }
+}
+
+// 服务器证书不能是 CA，keyUsage/extKeyUsage 存在时必须允许签名和用作服务器
+fn is_server(cert: &Certificate) -> bool {
+    let tbs = &cert.tbs_certificate;
+    let not_ca = match tbs.get::<BasicConstraints>() {
+        Ok(Some((_, bc))) => !bc.ca,
+        Ok(None) => true,
+        Err(_) => false,
+    };
+    let signs = match tbs.get::<KeyUsage>() {
+        Ok(Some((_, usage))) => usage.digital_signature(),
+        Ok(None) => true,
+        Err(_) => false,
+    };
+    let server_auth = match tbs.get::<ExtendedKeyUsage>() {
+        Ok(Some((_, usage))) => usage.0.contains(&OID_SERVER_AUTH),
+        Ok(None) => true,
+        Err(_) => false,
+    };
+    not_ca && signs && server_auth
+}
+
+// 颁发者是 CA 证书的主体，且签名能用 CA 的公钥验证
+fn issued_by(cert: &Certificate, ca: &Certificate) -> bool {
+    if cert.tbs_certificate.issuer != ca.tbs_certificate.subject
+        || cert.signature_algorithm.oid != OID_ECDSA_WITH_SHA256
+    {
+        return false;
+    }
+    let (Ok(ca_key), Ok(tbs)) = (p256_key(ca), cert.tbs_certificate.to_der()) else {
+        return false;
+    };
+    cert.signature
+        .as_bytes()
+        .and_then(|sig| Signature::from_der(sig).ok())
+        .is_some_and(|sig| ca_key.verify(&tbs, &sig).is_ok())
+}
+
+// subjectAltName 里有这个主机名 (支持最左边一级的 *. 通配) 或 IPv4 地址
+fn names_host(cert: &Certificate, host: &str) -> bool {
+    let Ok(Some((_, SubjectAltName(names)))) = cert.tbs_certificate.get::<SubjectAltName>() else {
+        return false;
+    };
+    let ip = host.parse::<Ipv4Addr>().ok();
+    names.iter().any(|name| match name {
+        GeneralName::DnsName(pattern) => ip.is_none() && dns_name_matches(pattern.as_str(), host),
+        GeneralName::IpAddress(octets) => {
+            ip.is_some_and(|ip| octets.as_bytes() == ip.octets().as_slice())
+        }
+        _ => false,
+    })
+}
+
+fn dns_name_matches(pattern: &str, name: &str) -> bool {
+    let name = name.trim_end_matches('.');
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => name
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(suffix)),
+        None => pattern.eq_ignore_ascii_case(name),
+    }
+}
+
+// 证书里的 P-256 公钥，其它算法返回错误
+fn p256_key(cert: &Certificate) -> Result<VerifyingKey, TlsError> {
+    let spki = &cert.tbs_certificate.subject_public_key_info;
+    let curve = spki
+        .algorithm
+        .parameters
+        .as_ref()
+        .and_then(|p| p.decode_as::<ObjectIdentifier>().ok());
+    if spki.algorithm.oid != OID_EC_PUBLIC_KEY || curve != Some(OID_PRIME256V1) {
+        return Err(TlsError::InvalidCertificate);
+    }
+    let point = spki
+        .subject_public_key
+        .as_bytes()
+        .ok_or(TlsError::DecodeError)?;
+    VerifyingKey::from_sec1_bytes(point).map_err(|_| TlsError::InvalidCertificate)
+}
+
+/// 交给 [`embedded_tls::TlsContext`] 的随机数来源和证书校验
+pub struct Provider<'a, R> {
+    rng: R,
+    verifier: Verifier<'a>,
+}
+
+impl<'a, R: RngCore + CryptoRng> Provider<'a, R> {
+    /// `rng` 用来生成握手的临时密钥，必须是密码学安全的随机数
+    pub fn new(rng: R, trust: Trust<'a>) -> Self {
+        Self {
+            rng,
+            verifier: Verifier::new(trust),
+        }
+    }
+}
+
+impl<R: RngCore + CryptoRng> CryptoProvider for Provider<'_, R> {
+    type CipherSuite = CipherSuite;
+    type Signature = DerSignature;
+
+    fn rng(&mut self) -> impl CryptoRngCore {
+        &mut self.rng
+    }
+
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<CipherSuite>, TlsError> {
+        Ok(&mut self.verifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Client;
+    use alloc::{string::ToString, sync::Arc, vec, vec::Vec};
+    use embassy_futures::block_on;
+    use embedded_hal_async::delay::DelayNs;
+    use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+    use embedded_tls::{TlsConfig, TlsConnection, TlsContext};
+    use std::io::{Read as _, Write as _};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    // 阻塞的 std TcpStream 包成异步读写，每次 await 都立即完成
+    struct Stream(TcpStream);
+
+    impl ErrorType for Stream {
+        type Error = ErrorKind;
+    }
+
+    impl Read for Stream {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+            self.0.read(buf).map_err(|_| ErrorKind::Other)
+        }
+    }
+
+    impl Write for Stream {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+            self.0.write(buf).map_err(|_| ErrorKind::Other)
+        }
+
+        async fn flush(&mut self) -> Result<(), ErrorKind> {
+            self.0.flush().map_err(|_| ErrorKind::Other)
+        }
+    }
+
+    struct Never;
+
+    impl DelayNs for Never {
+        async fn delay_ns(&mut self, _ns: u32) {
+            core::future::pending::<()>().await
+        }
+    }
+
+    // 测试用的 xorshift，冒充密码学安全的随机数
+    struct TestRng(u64);
+
+    impl RngCore for TestRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            rand_core::impls::fill_bytes_via_next(self, dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for TestRng {}
+
+    struct Server {
+        address: String,
+        /// 服务器证书的公钥 (SubjectPublicKeyInfo)
+        spki: Vec<u8>,
+        /// 签发服务器证书的 CA 证书
+        ca: Vec<u8>,
+        handle: thread::JoinHandle<Vec<String>>,
+    }
+
+    // 本机上的 rustls 服务器 (只开 TLS 1.3)，证书由 `issuer` 签发给 localhost；
+    // 依次接受 `connections` 个连接，每个连接读一个 HTTP 请求并回复，握手失败的连接跳过
+    fn serve(connections: usize, issuer: rcgen::IsCa) -> Server {
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec![]).unwrap();
+        params.is_ca = issuer;
+        let ca = params.self_signed(&ca_key).unwrap();
+        let key = rcgen::KeyPair::generate().unwrap();
+        let params = rcgen::CertificateParams::new(vec!["localhost".into()]).unwrap();
+        let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![cert.der().clone()],
+                rustls::pki_types::PrivatePkcs8KeyDer::from(key.serialize_der()).into(),
+            )
+            .unwrap();
+        let config = Arc::new(config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                let conn = rustls::ServerConnection::new(config.clone()).unwrap();
+                let mut tls = rustls::StreamOwned::new(conn, stream);
+                let mut request = Vec::new();
+                let mut buf = [0u8; 256];
+                // 读到请求头结束，请求体很短，和请求头一起到达
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match tls.read(&mut buf) {
+                        Ok(n) if n > 0 => request.extend_from_slice(&buf[..n]),
+                        _ => break,
+                    }
+                }
+                if request.is_empty() {
+                    continue;
+                }
+                let body = "{\"ok\":true}";
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                tls.write_all(response.as_bytes()).unwrap();
+                tls.conn.send_close_notify();
+                tls.flush().unwrap();
+                requests.push(String::from_utf8(request).unwrap());
+            }
+            requests
+        });
+        Server {
+            address,
+            spki: key.public_key_der(),
+            ca: ca.der().to_vec(),
+            handle,
+        }
+    }
+
+    type Connection<'a> = TlsConnection<'a, Stream, CipherSuite>;
+
+    fn open<'a>(
+        server: &Server,
+        name: &str,
+        trust: Trust<'_>,
+        buffers: &'a mut (Vec<u8>, Vec<u8>),
+    ) -> Result<Connection<'a>, TlsError> {
+        let stream = Stream(TcpStream::connect(&server.address).unwrap());
+        let mut tls = TlsConnection::new(stream, &mut buffers.0, &mut buffers.1);
+        let config = TlsConfig::new().with_server_name(name);
+        let provider = Provider::new(TestRng(0x1234_5678_9ABC_DEF0), trust);
+        block_on(tls.open(TlsContext::new(&config, provider)))?;
+        Ok(tls)
+    }
+
+    fn buffers() -> (Vec<u8>, Vec<u8>) {
+        (vec![0; READ_BUFFER_LEN], vec![0; WRITE_BUFFER_LEN])
+    }
+
+    #[test]
+    fn posts_over_tls_with_pinned_key() {
+        let server = serve(2, rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained));
+        let pin = Sha256::digest(&server.spki).into();
+        let mut buffers = buffers();
+
+        // 公钥不对：握手失败
+        let result = open(
+            &server,
+            "localhost",
+            Trust::PublicKeySha256([0; 32]),
+            &mut buffers,
+        );
+        assert!(matches!(result, Err(TlsError::InvalidCertificate)));
+
+        let tls = open(
+            &server,
+            "localhost",
+            Trust::PublicKeySha256(pin),
+            &mut buffers,
+        )
+        .unwrap();
+        let mut client = Client::new(tls, Never, "localhost");
+        let resp = block_on(client.post_json("/upload", "{\"temp\":21.5}")).unwrap();
+        assert_eq!((resp.status, resp.body_str()), (200, Some("{\"ok\":true}")));
+        block_on(client.into_inner().close()).ok();
+
+        let requests = server.handle.join().unwrap();
+        assert_eq!(
+            requests,
+            [
+                "POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 13\r\n\
+              Content-Type: application/json\r\n\r\n{\"temp\":21.5}"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_issuer_and_name_against_ca() {
+        let server = serve(3, rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained));
+        let other_key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec![]).unwrap();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let other_ca = params.self_signed(&other_key).unwrap();
+        let mut buffers = buffers();
+
+        assert!(open(&server, "localhost", Trust::Ca(&server.ca), &mut buffers).is_ok());
+
+        // 证书不是签发给这个名字的
+        let result = open(&server, "example.com", Trust::Ca(&server.ca), &mut buffers);
+        assert!(matches!(result, Err(TlsError::InvalidCertificate)));
+
+        // 不是这个 CA 签发的
+        let result = open(
+            &server,
+            "localhost",
+            Trust::Ca(other_ca.der()),
+            &mut buffers,
+        );
+        assert!(matches!(result, Err(TlsError::InvalidCertificate)));
+
+        assert!(server.handle.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_issuer_without_ca_flag() {
+        // 签发者的证书没有声明 cA (普通的服务器证书不能再签发证书)
+        let server = serve(1, rcgen::IsCa::ExplicitNoCa);
+        let mut buffers = buffers();
+        let result = open(&server, "localhost", Trust::Ca(&server.ca), &mut buffers);
+        assert!(matches!(result, Err(TlsError::InvalidCertificate)));
+        assert!(server.handle.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn matches_wildcard_and_ip_names() {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let params =
+            rcgen::CertificateParams::new(vec!["*.example.com".into(), "10.0.0.1".into()]).unwrap();
+        let der = params.self_signed(&key).unwrap().der().to_vec();
+        let cert = Certificate::from_der(&der).unwrap();
+
+        assert!(names_host(&cert, "data.example.com"));
+        assert!(names_host(&cert, "DATA.Example.com."));
+        assert!(!names_host(&cert, "example.com"));
+        assert!(!names_host(&cert, "a.b.example.com"));
+        assert!(names_host(&cert, "10.0.0.1"));
+        assert!(!names_host(&cert, "10.0.0.2"));
+        assert!(is_server(&cert));
+        assert!(p256_key(&cert).is_ok());
+    }
+}
@@ -0,0 +1,220 @@
+//! 上传周期内的采样窗口统计
+//!
+//! 一个上传周期 (300 s) 内连续采样，上传时给出每个指标的最小/最大/平均/中位数和样本数，
+//! 避免某一帧噪声代表整整五分钟的数据。可选用中位数绝对偏差 (MAD) 剔除离群值。
+
+/// 离群值剔除方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutlierFilter {
+    /// 所有样本都参与统计
+    None,
+    /// 与中位数的偏差超过 `k` 倍标准差估计 (1.4826 × MAD) 的样本被剔除
+    Mad { k: f32 },
+}
+
+/// 少于这么多样本时不做离群值剔除
+const MIN_SAMPLES_FOR_FILTER: usize = 3;
+// 正态分布下 MAD 换算成标准差的系数
+const MAD_TO_SIGMA: f32 = 1.4826;
+
+/// 一个窗口的统计结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    /// 参与统计的样本数
+    pub count: usize,
+    /// 被剔除的离群值个数
+    pub rejected: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub median: f32,
+}
+
+/// 最多容纳 `N` 个样本的窗口
+#[derive(Debug, Clone)]
+pub struct Window<const N: usize> {
+    samples: [f32; N],
+    len: usize,
+    // 窗口满了之后丢掉的样本数
+    dropped: usize,
+}
+
+impl<const N: usize> Default for Window<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Window<N> {
+    pub const fn new() -> Self {
+        Self {
+            samples: [0.0; N],
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /// 加入一个样本；NaN 会被忽略，窗口已满时丢弃并计数，返回是否加入
+    pub fn push(&mut self, value: f32) -> bool {
+        if value.is_nan() {
+            return false;
+        }
+        if self.len == N {
+            self.dropped += 1;
+            return false;
+        }
+        self.samples[self.len] = value;
+        self.len += 1;
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// 开始新的上传周期
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.dropped = 0;
+    }
+
+    /// 统计当前窗口，没有样本时返回 `None`
+    pub fn summary(&self, filter: OutlierFilter) -> Option<Summary> {
+        if self.len == 0 {
+            return None;
+        }
+        let mut sorted = self.samples;
+        let sorted = &mut sorted[..self.len];
+        sort(sorted);
+
+        let mut kept = sorted.len();
+        if let OutlierFilter::Mad { k } = filter {
+            if kept >= MIN_SAMPLES_FOR_FILTER {
+                let center = median(sorted);
+                let mut deviations = [0.0f32; N];
+                let deviations = &mut deviations[..kept];
+                for (d, &x) in deviations.iter_mut().zip(sorted.iter()) {
+                    *d = (x - center).abs();
+                }
+                sort(deviations);
+                let limit = k * MAD_TO_SIGMA * median(deviations);
+                // MAD 为 0 说明大部分样本完全相同，此时不剔除任何值
+                if limit > 0.0 {
+                    let mut write = 0;
+                    for read in 0..kept {
+                        if (sorted[read] - center).abs() <= limit {
+                            sorted[write] = sorted[read];
+                            write += 1;
+                        }
+                    }
+                    kept = write;
+                }
+            }
+        }
+
+        let samples = &sorted[..kept];
+        let sum: f32 = samples.iter().sum();
+        Some(Summary {
+            count: kept,
+            rejected: self.len - kept,
+            min: samples[0],
+            max: samples[kept - 1],
+            mean: sum / kept as f32,
+            median: median(samples),
+        })
+    }
+}
+
+fn sort(values: &mut [f32]) {
+    values.sort_unstable_by(|a, b| a.total_cmp(b));
+}
+
+// values 必须已排序且非空
+fn median(values: &[f32]) -> f32 {
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(values: &[f32]) -> Window<16> {
+        let mut w = Window::new();
+        for &v in values {
+            w.push(v);
+        }
+        w
+    }
+
+    #[test]
+    fn summarizes_samples() {
+        let s = window(&[21.5, 20.0, 23.0, 22.0])
+            .summary(OutlierFilter::None)
+            .unwrap();
+        assert_eq!(s.count, 4);
+        assert_eq!(s.rejected, 0);
+        assert_eq!(s.min, 20.0);
+        assert_eq!(s.max, 23.0);
+        assert_eq!(s.mean, 21.625);
+        assert_eq!(s.median, 21.75);
+
+        assert_eq!(
+            window(&[3.0, 1.0, 2.0])
+                .summary(OutlierFilter::None)
+                .unwrap()
+                .median,
+            2.0
+        );
+        assert_eq!(window(&[]).summary(OutlierFilter::None), None);
+    }
+
+    #[test]
+    fn rejects_outliers_by_mad() {
+        // 一帧干扰把 CO2 读成 5000 ppm
+        let w = window(&[612.0, 608.0, 615.0, 5000.0, 610.0, 611.0]);
+        let raw = w.summary(OutlierFilter::None).unwrap();
+        assert_eq!(raw.max, 5000.0);
+
+        let s = w.summary(OutlierFilter::Mad { k: 3.0 }).unwrap();
+        assert_eq!(s.count, 5);
+        assert_eq!(s.rejected, 1);
+        assert_eq!(s.max, 615.0);
+        assert_eq!(s.median, 611.0);
+    }
+
+    #[test]
+    fn keeps_everything_when_mad_is_zero() {
+        let s = window(&[25.0, 25.0, 25.0, 26.0])
+            .summary(OutlierFilter::Mad { k: 3.0 })
+            .unwrap();
+        assert_eq!(s.count, 4);
+        assert_eq!(s.max, 26.0);
+    }
+
+    #[test]
+    fn counts_dropped_samples_when_full() {
+        let mut w: Window<2> = Window::new();
+        assert!(w.push(1.0));
+        assert!(!w.push(f32::NAN));
+        assert!(w.push(2.0));
+        assert!(!w.push(3.0));
+        assert_eq!((w.len(), w.dropped()), (2, 1));
+
+        w.clear();
+        assert!(w.is_empty());
+        assert_eq!(w.dropped(), 0);
+    }
+}